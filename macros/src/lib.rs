@@ -1,8 +1,9 @@
 use proc_macro::TokenStream;
 use quote::quote;
+use syn::ext::IdentExt;
 use syn::{Data, DeriveInput, Fields, Type, parse_macro_input};
 
-#[proc_macro_derive(Dataset, attributes(field_prefix))]
+#[proc_macro_derive(Dataset, attributes(field_prefix, nested))]
 pub fn dataset_derive(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     let name = &input.ident;
@@ -22,7 +23,8 @@ pub fn dataset_derive(input: TokenStream) -> TokenStream {
     // Generate match arms for each field
     let match_arms = fields.iter().map(|field| {
         let field_name = field.ident.as_ref().unwrap();
-        let field_name_str = field_name.to_string();
+        // Strip the raw identifier marker so `r#type` is exposed as `type`
+        let field_name_str = field_name.unraw().to_string();
         let field_type = &field.ty;
 
         // Create the prefixed field name
@@ -38,9 +40,74 @@ pub fn dataset_derive(input: TokenStream) -> TokenStream {
         }
     });
 
+    // Generate assignment arms for the inverse conversion
+    let set_arms = fields.iter().map(|field| {
+        let field_name = field.ident.as_ref().unwrap();
+        let prefixed_name = format!("{}{}", prefix, field_name.unraw());
+        let assignment = generate_assignment(&field.ty, quote!(self.#field_name), &prefixed_name);
+
+        quote! {
+            #prefixed_name => {
+                #assignment
+            }
+        }
+    });
+
+    // Generate type arms from the same classification used for conversion
+    let type_arms = fields.iter().map(|field| {
+        let prefixed_name = format!("{}{}", prefix, field.ident.as_ref().unwrap().unraw());
+        let ty = option_inner(&field.ty).unwrap_or(&field.ty);
+        let column_type = match classify(ty) {
+            FieldKind::F32 | FieldKind::F64 | FieldKind::Int => "number",
+            FieldKind::Bool => "boolean",
+            FieldKind::Str | FieldKind::Other => "string",
+        };
+
+        quote! {
+            #prefixed_name => Some(#column_type),
+        }
+    });
+
+    // `#[nested]` fields also derive Dataset, and "<name>.<path>" is looked up in them
+    let nested = fields
+        .iter()
+        .filter(|field| {
+            field
+                .attrs
+                .iter()
+                .any(|attr| attr.path().is_ident("nested"))
+        })
+        .map(|field| {
+            let field_name = field.ident.as_ref().unwrap();
+            let path_prefix = format!("{}{}.", prefix, field_name.unraw());
+            (field_name, path_prefix)
+        })
+        .collect::<Vec<_>>();
+    let nested_get_arms = nested.iter().map(|(field_name, path_prefix)| {
+        quote! {
+            _ if field_name.starts_with(#path_prefix) => {
+                self.#field_name.get(&field_name[#path_prefix.len()..])
+            }
+        }
+    });
+    let nested_set_arms = nested.iter().map(|(field_name, path_prefix)| {
+        quote! {
+            _ if field_name.starts_with(#path_prefix) => {
+                self.#field_name.set(&field_name[#path_prefix.len()..], value)
+            }
+        }
+    });
+    let nested_type_arms = nested.iter().map(|(field_name, path_prefix)| {
+        quote! {
+            _ if field_name.starts_with(#path_prefix) => {
+                self.#field_name.column_type(&field_name[#path_prefix.len()..])
+            }
+        }
+    });
+
     let all_fields = fields
         .iter()
-        .map(|field| format!("{}{}", prefix, field.ident.as_ref().unwrap()))
+        .map(|field| format!("{}{}", prefix, field.ident.as_ref().unwrap().unraw()))
         .collect::<Vec<String>>();
     let field_names = all_fields.iter().map(|s| s.as_str());
 
@@ -50,12 +117,37 @@ pub fn dataset_derive(input: TokenStream) -> TokenStream {
             fn get(&self, field_name: &str) -> Result<Value, String> {
                 match field_name {
                     #(#match_arms)*
+                    #(#nested_get_arms)*
+                    // Exact names are the fast path; retry mixed-case names lowercased
+                    _ if field_name.bytes().any(|b| b.is_ascii_uppercase()) => {
+                        self.get(&field_name.to_ascii_lowercase())
+                    }
+                    _ => Err(format!("Field '{}' not found. Fields must start with prefix '{}'", field_name, #prefix)),
+                }
+            }
+
+            fn set(&mut self, field_name: &str, value: Value) -> Result<(), String> {
+                match field_name {
+                    #(#set_arms)*
+                    #(#nested_set_arms)*
                     _ => Err(format!("Field '{}' not found. Fields must start with prefix '{}'", field_name, #prefix)),
                 }
             }
 
-            fn get_all(&self) -> &'static [&'static str] {
-                &[#(#field_names),*]
+            fn columns(&self) -> Vec<String> {
+                vec![#(#field_names.to_string()),*]
+            }
+
+            fn prefix(&self) -> &'static str {
+                #prefix
+            }
+
+            fn column_type(&self, field_name: &str) -> Option<&'static str> {
+                match field_name {
+                    #(#type_arms)*
+                    #(#nested_type_arms)*
+                    _ => None,
+                }
             }
         }
     };
@@ -88,20 +180,43 @@ fn extract_prefix(attrs: &[syn::Attribute]) -> Option<String> {
     None
 }
 
-// Helper function to generate conversion based on type
-fn generate_conversion(
-    ty: &Type,
-    field_access: proc_macro2::TokenStream,
-) -> proc_macro2::TokenStream {
+// How a field's Rust type maps onto a Value
+enum FieldKind {
+    Str,
+    F32,
+    F64,
+    Int,
+    Bool,
+    Other,
+}
+
+// The T of an Option<T> field, which holds Value::Null when None
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+    match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) => match args.args.first()? {
+            syn::GenericArgument::Type(inner) => Some(inner),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn classify(ty: &Type) -> FieldKind {
     let ty_str = quote!(#ty).to_string();
 
-    // Handle different types
     if ty_str.contains("String") || ty_str.contains("str") {
-        quote! { Value::Str(#field_access.clone()) }
+        FieldKind::Str
     } else if ty_str.contains("f32") {
-        quote! { Value::Num(#field_access) }
+        FieldKind::F32
     } else if ty_str.contains("f64") {
-        quote! { Value::Num(#field_access as f32) }
+        FieldKind::F64
     } else if ty_str.contains("i32")
         || ty_str.contains("u32")
         || ty_str.contains("i64")
@@ -109,11 +224,124 @@ fn generate_conversion(
         || ty_str.contains("usize")
         || ty_str.contains("isize")
     {
-        quote! { Value::Num(#field_access as f32) }
+        FieldKind::Int
     } else if ty_str.contains("bool") {
-        quote! { Value::Str(#field_access.to_string()) }
+        FieldKind::Bool
     } else {
+        FieldKind::Other
+    }
+}
+
+// Helper function to generate conversion based on type
+fn generate_conversion(
+    ty: &Type,
+    field_access: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    if let Some(inner) = option_inner(ty) {
+        let conversion = generate_conversion(inner, quote!((*value)));
+        return quote! {
+            match &#field_access {
+                Some(value) => #conversion,
+                None => Value::Null,
+            }
+        };
+    }
+
+    match classify(ty) {
+        FieldKind::Str => quote! { Value::Str(#field_access.clone()) },
+        FieldKind::F32 => quote! { Value::Num(#field_access) },
+        FieldKind::F64 | FieldKind::Int => {
+            quote! { Value::Num(::ordered_float::OrderedFloat(#field_access as f32)) }
+        }
+        FieldKind::Bool => quote! { Value::Bool(#field_access) },
         // For unknown types, try to convert to string
-        quote! { Value::Str(format!("{:?}", #field_access)) }
+        FieldKind::Other => quote! { Value::Str(format!("{:?}", #field_access)) },
+    }
+}
+
+// Inverse of generate_conversion, assigning a Value back into the field
+fn generate_assignment(
+    ty: &Type,
+    field_access: proc_macro2::TokenStream,
+    prefixed_name: &str,
+) -> proc_macro2::TokenStream {
+    let mismatch = |expected: &str| {
+        quote! {
+            Err(format!("Field '{}' expects a {}, got {:?}", #prefixed_name, #expected, other))
+        }
+    };
+
+    if let Some(inner) = option_inner(ty) {
+        let assignment = generate_assignment(inner, quote!(set), prefixed_name);
+        return quote! {
+            match value {
+                Value::Null => {
+                    #field_access = None;
+                    Ok(())
+                }
+                value => {
+                    let mut set: #inner = Default::default();
+                    let result = #assignment;
+                    if result.is_ok() {
+                        #field_access = Some(set);
+                    }
+                    result
+                }
+            }
+        };
+    }
+
+    match classify(ty) {
+        FieldKind::Str => {
+            let mismatch = mismatch("string");
+            quote! {
+                match value {
+                    Value::Str(s) => {
+                        #field_access = s;
+                        Ok(())
+                    }
+                    other => #mismatch,
+                }
+            }
+        }
+        FieldKind::F32 => {
+            let mismatch = mismatch("number");
+            quote! {
+                match value {
+                    Value::Num(n) => {
+                        #field_access = n;
+                        Ok(())
+                    }
+                    other => #mismatch,
+                }
+            }
+        }
+        FieldKind::F64 | FieldKind::Int => {
+            let mismatch = mismatch("number");
+            quote! {
+                match value {
+                    Value::Num(n) => {
+                        #field_access = n.into_inner() as #ty;
+                        Ok(())
+                    }
+                    other => #mismatch,
+                }
+            }
+        }
+        FieldKind::Bool => {
+            let mismatch = mismatch("boolean");
+            quote! {
+                match value {
+                    Value::Bool(b) => {
+                        #field_access = b;
+                        Ok(())
+                    }
+                    other => #mismatch,
+                }
+            }
+        }
+        FieldKind::Other => quote! {
+            Err(format!("Field '{}' cannot be set from {:?}", #prefixed_name, value))
+        },
     }
 }
@@ -0,0 +1,62 @@
+use std::collections::{HashMap, VecDeque};
+
+/// Re-serializes the query with sorted keys so semantically identical queries share an entry
+pub fn canonicalize(json: &str) -> Option<String> {
+    serde_json::from_str::<serde_json::Value>(json)
+        .ok()
+        .map(|value| value.to_string())
+}
+
+/// Least-recently-used cache of query results keyed by canonical query
+pub struct QueryCache<T> {
+    capacity: usize,
+    entries: HashMap<String, T>,
+    // Front is the least recently used key
+    recency: VecDeque<String>,
+}
+
+impl<T: Clone> QueryCache<T> {
+    pub fn new(capacity: usize) -> Self {
+        QueryCache {
+            capacity,
+            entries: HashMap::new(),
+            recency: VecDeque::new(),
+        }
+    }
+
+    fn touch(&mut self, key: &str) {
+        if let Some(position) = self.recency.iter().position(|k| k == key) {
+            let key = self.recency.remove(position).unwrap();
+            self.recency.push_back(key);
+        }
+    }
+
+    pub fn get(&mut self, key: &str) -> Option<T> {
+        let rows = self.entries.get(key)?.clone();
+        self.touch(key);
+        Some(rows)
+    }
+
+    /// Drops every entry, e.g. after the datasets they were computed from are reloaded
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+
+    pub fn insert(&mut self, key: String, rows: T) {
+        if self.entries.insert(key.clone(), rows).is_some() {
+            self.touch(&key);
+            return;
+        }
+        self.recency.push_back(key);
+        if self.recency.len() > self.capacity
+            && let Some(evicted) = self.recency.pop_front()
+        {
+            self.entries.remove(&evicted);
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "cache_test.rs"]
+mod cache_test;
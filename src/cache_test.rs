@@ -0,0 +1,42 @@
+use crate::cache::{QueryCache, canonicalize};
+use crate::types::Value;
+use ordered_float::OrderedFloat;
+use std::collections::BTreeMap;
+
+fn rows(n: f32) -> Vec<BTreeMap<String, Value>> {
+    vec![BTreeMap::from([(
+        "sections_avg".to_string(),
+        Value::Num(OrderedFloat::from(n)),
+    )])]
+}
+
+#[test]
+fn test_canonicalize() {
+    let a = canonicalize(r#"{"WHERE": {}, "OPTIONS": {"COLUMNS": ["sections_avg"]}}"#);
+    let b = canonicalize(r#"{"OPTIONS":{"COLUMNS":["sections_avg"]},"WHERE":{}}"#);
+    let c = canonicalize(r#"{"OPTIONS":{"COLUMNS":["sections_dept"]},"WHERE":{}}"#);
+    assert!(a.is_some());
+    assert_eq!(a, b);
+    assert_ne!(a, c);
+    assert_eq!(canonicalize("{"), None);
+}
+
+#[test]
+fn test_hit_and_miss() {
+    let mut cache = QueryCache::new(4);
+    cache.insert("a".to_string(), rows(1.0));
+    assert_eq!(cache.get("a"), Some(rows(1.0)));
+    assert_eq!(cache.get("b"), None);
+}
+
+#[test]
+fn test_evicts_least_recently_used() {
+    let mut cache = QueryCache::new(2);
+    cache.insert("a".to_string(), rows(1.0));
+    cache.insert("b".to_string(), rows(2.0));
+    cache.get("a");
+    cache.insert("c".to_string(), rows(3.0));
+    assert_eq!(cache.get("a"), Some(rows(1.0)));
+    assert_eq!(cache.get("b"), None);
+    assert_eq!(cache.get("c"), Some(rows(3.0)));
+}
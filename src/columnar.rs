@@ -0,0 +1,90 @@
+use crate::types::Value;
+use arrow::array::{ArrayRef, BooleanArray, Float32Array, StringArray};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::{RecordBatch, RecordBatchOptions};
+use parquet::arrow::ArrowWriter;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+// Nulls fit a column of any type
+fn value_type(value: &Value) -> Option<DataType> {
+    match value {
+        Value::Num(_) => Some(DataType::Float32),
+        Value::Str(_) => Some(DataType::Utf8),
+        Value::Bool(_) => Some(DataType::Boolean),
+        Value::Null => None,
+    }
+}
+
+/// The type every row agrees on for the column, or strings when they disagree
+fn column_type(rows: &[BTreeMap<String, Value>], column: &str) -> DataType {
+    let mut types = rows
+        .iter()
+        .filter_map(|row| row.get(column))
+        .filter_map(value_type);
+    let first = types.next().unwrap_or(DataType::Utf8);
+    if types.all(|other| other == first) {
+        first
+    } else {
+        DataType::Utf8
+    }
+}
+
+fn as_string(value: &Value) -> Option<String> {
+    match value {
+        Value::Num(n) => Some(n.to_string()),
+        Value::Str(s) => Some(s.clone()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Null => None,
+    }
+}
+
+/// Writes result rows as one Parquet row group, with a nullable column per key any row has.
+/// Missing keys and Value::Null are both written as nulls.
+pub fn to_parquet(rows: &[BTreeMap<String, Value>]) -> anyhow::Result<Vec<u8>> {
+    let columns: BTreeSet<&String> = rows.iter().flat_map(|row| row.keys()).collect();
+    let mut fields = Vec::with_capacity(columns.len());
+    let mut arrays: Vec<ArrayRef> = Vec::with_capacity(columns.len());
+    for column in columns {
+        let values = rows.iter().map(|row| row.get(column));
+        let data_type = column_type(rows, column);
+        arrays.push(match data_type {
+            DataType::Float32 => Arc::new(
+                values
+                    .map(|value| match value {
+                        Some(Value::Num(n)) => Some(n.0),
+                        _ => None,
+                    })
+                    .collect::<Float32Array>(),
+            ),
+            DataType::Boolean => Arc::new(
+                values
+                    .map(|value| match value {
+                        Some(Value::Bool(b)) => Some(*b),
+                        _ => None,
+                    })
+                    .collect::<BooleanArray>(),
+            ),
+            _ => Arc::new(
+                values
+                    .map(|value| value.and_then(as_string))
+                    .collect::<StringArray>(),
+            ),
+        });
+        fields.push(Field::new(column, data_type, true));
+    }
+
+    let schema = Arc::new(Schema::new(fields));
+    // The row count keeps an empty result, which has no columns, a valid batch
+    let options = RecordBatchOptions::new().with_row_count(Some(rows.len()));
+    let batch = RecordBatch::try_new_with_options(schema.clone(), arrays, &options)?;
+    let mut bytes = Vec::new();
+    let mut writer = ArrowWriter::try_new(&mut bytes, schema, None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+#[path = "columnar_test.rs"]
+mod columnar_test;
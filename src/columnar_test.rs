@@ -0,0 +1,58 @@
+use crate::columnar::to_parquet;
+use crate::types::Value;
+use arrow::array::{Array, AsArray};
+use arrow::datatypes::{DataType, Float32Type};
+use arrow::record_batch::RecordBatch;
+use axum::body::Bytes;
+use ordered_float::OrderedFloat;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use std::collections::BTreeMap;
+
+fn read_back(bytes: Vec<u8>) -> RecordBatch {
+    let mut reader = ParquetRecordBatchReaderBuilder::try_new(Bytes::from(bytes))
+        .unwrap()
+        .build()
+        .unwrap();
+    reader.next().unwrap().unwrap()
+}
+
+#[test]
+fn test_parquet_types() {
+    let row = |dept: &str, avg: f32, mixed: Value| {
+        BTreeMap::from([
+            ("dept".to_string(), Value::Str(dept.to_string())),
+            ("avg".to_string(), Value::Num(OrderedFloat(avg))),
+            ("mixed".to_string(), mixed),
+        ])
+    };
+    let mut rows = vec![
+        row("cpsc", 90.5, Value::Num(OrderedFloat(1.0))),
+        row("math", 80.0, Value::Str("one".into())),
+    ];
+    rows[1].remove("avg");
+
+    let batch = read_back(to_parquet(&rows).unwrap());
+    let schema = batch.schema();
+    let types: Vec<_> = schema
+        .fields()
+        .iter()
+        .map(|field| (field.name().as_str(), field.data_type().clone()))
+        .collect();
+    assert_eq!(
+        types,
+        [
+            ("avg", DataType::Float32),
+            ("dept", DataType::Utf8),
+            // Numbers and strings in one column fall back to strings
+            ("mixed", DataType::Utf8),
+        ]
+    );
+
+    let avg = batch.column(0).as_primitive::<Float32Type>();
+    assert_eq!(avg.value(0), 90.5);
+    assert!(avg.is_null(1));
+    let dept = batch.column(1).as_string::<i32>();
+    assert_eq!((dept.value(0), dept.value(1)), ("cpsc", "math"));
+    let mixed = batch.column(2).as_string::<i32>();
+    assert_eq!((mixed.value(0), mixed.value(1)), ("1", "one"));
+}
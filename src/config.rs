@@ -0,0 +1,219 @@
+use anyhow::{Context, anyhow};
+use axum::http::HeaderValue;
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+
+/// A dataset to load at startup and serve under its id
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatasetSpec {
+    pub id: String,
+    pub kind: String,
+    pub path: String,
+}
+
+impl DatasetSpec {
+    pub fn new(id: &str, kind: &str, path: &str) -> Self {
+        DatasetSpec {
+            id: id.to_string(),
+            kind: kind.to_string(),
+            path: path.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Config {
+    pub sections: String,
+    pub rooms: String,
+    pub port: u16,
+    pub timeout: Duration,
+    /// Registered alongside the sections and rooms datasets
+    pub datasets: Vec<DatasetSpec>,
+    /// Origins allowed by CORS, any origin if empty
+    pub cors_origins: Vec<HeaderValue>,
+    /// Bearer token for the /admin endpoints, which are refused without one
+    pub admin_token: Option<String>,
+    /// Requests per second allowed from each client IP, unlimited if None
+    pub rate_limit: Option<f64>,
+    /// Rejects queries without a WHERE filter unless they set a LIMIT
+    pub require_limit: bool,
+    /// Columns queries may not use, by dataset id
+    pub denied_columns: HashMap<String, BTreeSet<String>>,
+    /// Reports data quality issues in the course datasets instead of serving them
+    pub validate: bool,
+}
+
+static NO_COLUMNS: BTreeSet<String> = BTreeSet::new();
+
+impl Config {
+    /// Every dataset to load, starting with the built-in sections and rooms
+    pub fn registrations(&self) -> Vec<DatasetSpec> {
+        let mut specs = vec![
+            DatasetSpec::new("sections", "courses", &self.sections),
+            DatasetSpec::new("rooms", "rooms", &self.rooms),
+        ];
+        specs.extend(self.datasets.iter().cloned());
+        specs
+    }
+
+    /// The columns denied on a dataset, empty unless `--deny-columns` names it
+    pub fn denied_columns(&self, id: &str) -> &BTreeSet<String> {
+        self.denied_columns.get(id).unwrap_or(&NO_COLUMNS)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            sections: "pair.zip".to_string(),
+            rooms: "campus.zip".to_string(),
+            port: 310,
+            timeout: Duration::from_secs(10),
+            datasets: vec![],
+            cors_origins: vec![],
+            admin_token: None,
+            rate_limit: None,
+            require_limit: false,
+            denied_columns: HashMap::new(),
+            validate: false,
+        }
+    }
+}
+
+// Each option is a CLI flag with an environment variable fallback
+const OPTIONS: [(&str, &str); 10] = [
+    ("--sections", "SECTIONS_ZIP"),
+    ("--rooms", "ROOMS_ZIP"),
+    ("--port", "PORT"),
+    ("--timeout", "QUERY_TIMEOUT"),
+    ("--datasets", "DATASETS"),
+    ("--cors-origins", "CORS_ORIGINS"),
+    ("--admin-token", "ADMIN_TOKEN"),
+    ("--rate-limit", "RATE_LIMIT"),
+    ("--require-limit", "REQUIRE_LIMIT"),
+    ("--deny-columns", "DENY_COLUMNS"),
+];
+
+/// Parses comma-separated `id:kind:path` entries
+fn parse_datasets(value: &str) -> anyhow::Result<Vec<DatasetSpec>> {
+    value
+        .split(',')
+        .map(|entry| match entry.splitn(3, ':').collect::<Vec<_>>()[..] {
+            [id, kind, path] if !id.is_empty() && !kind.is_empty() && !path.is_empty() => {
+                Ok(DatasetSpec::new(id, kind, path))
+            }
+            _ => Err(anyhow!("Invalid dataset {}, expected id:kind:path", entry)),
+        })
+        .collect()
+}
+
+/// Parses comma-separated `id:column` entries
+fn parse_denied_columns(value: &str) -> anyhow::Result<HashMap<String, BTreeSet<String>>> {
+    let mut denied: HashMap<String, BTreeSet<String>> = HashMap::new();
+    for entry in value.split(',') {
+        match entry.split_once(':') {
+            Some((id, column)) if !id.is_empty() && !column.is_empty() => {
+                denied
+                    .entry(id.to_string())
+                    .or_default()
+                    .insert(column.to_string());
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Invalid denied column {}, expected id:column",
+                    entry
+                ));
+            }
+        }
+    }
+    Ok(denied)
+}
+
+/// Parses comma-separated origins such as `https://example.com`
+pub fn parse_origins(value: &str) -> anyhow::Result<Vec<HeaderValue>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|origin| !origin.is_empty())
+        .map(|origin| {
+            HeaderValue::from_str(origin).with_context(|| format!("Invalid origin {}", origin))
+        })
+        .collect()
+}
+
+/// Builds the config from CLI flags, falling back to environment variables and then the defaults
+pub fn parse_config(
+    mut args: impl Iterator<Item = String>,
+    env: impl Fn(&str) -> Option<String>,
+) -> anyhow::Result<Config> {
+    let mut values: HashMap<&str, String> = OPTIONS
+        .iter()
+        .filter_map(|(flag, var)| env(var).map(|value| (*flag, value)))
+        .collect();
+
+    let mut validate = false;
+    while let Some(arg) = args.next() {
+        // The one flag without a value
+        if arg == "--validate" {
+            validate = true;
+            continue;
+        }
+        let Some((flag, _)) = OPTIONS.iter().find(|(flag, _)| *flag == arg) else {
+            return Err(anyhow!("Unknown argument {}", arg));
+        };
+        let value = args
+            .next()
+            .ok_or_else(|| anyhow!("Missing value for {}", arg))?;
+        values.insert(flag, value);
+    }
+
+    let mut config = Config {
+        validate,
+        ..Config::default()
+    };
+    if let Some(sections) = values.remove("--sections") {
+        config.sections = sections;
+    }
+    if let Some(rooms) = values.remove("--rooms") {
+        config.rooms = rooms;
+    }
+    if let Some(port) = values.remove("--port") {
+        config.port = port
+            .parse()
+            .with_context(|| format!("Invalid port {}", port))?;
+    }
+    if let Some(timeout) = values.remove("--timeout") {
+        config.timeout = timeout
+            .parse()
+            .map(Duration::from_secs)
+            .with_context(|| format!("Invalid timeout {}", timeout))?;
+    }
+    if let Some(datasets) = values.remove("--datasets") {
+        config.datasets = parse_datasets(&datasets)?;
+    }
+    if let Some(origins) = values.remove("--cors-origins") {
+        config.cors_origins = parse_origins(&origins)?;
+    }
+    config.admin_token = values.remove("--admin-token");
+    if let Some(rate) = values.remove("--rate-limit") {
+        config.rate_limit = Some(
+            rate.parse()
+                .ok()
+                .filter(|rate: &f64| *rate > 0.0)
+                .ok_or_else(|| anyhow!("Invalid rate limit {}", rate))?,
+        );
+    }
+    if let Some(require) = values.remove("--require-limit") {
+        config.require_limit = require
+            .parse()
+            .with_context(|| format!("Invalid require limit {}", require))?;
+    }
+    if let Some(denied) = values.remove("--deny-columns") {
+        config.denied_columns = parse_denied_columns(&denied)?;
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+#[path = "config_test.rs"]
+mod config_test;
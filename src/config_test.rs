@@ -0,0 +1,174 @@
+use crate::config::{Config, DatasetSpec, parse_config, parse_origins};
+use std::collections::HashMap;
+use std::time::Duration;
+
+fn parse(args: &[&str], env: &[(&str, &str)]) -> anyhow::Result<Config> {
+    let env: HashMap<_, _> = env.iter().cloned().collect();
+    parse_config(args.iter().map(|arg| arg.to_string()), |key| {
+        env.get(key).map(|value| value.to_string())
+    })
+}
+
+#[test]
+fn test_defaults() {
+    assert_eq!(parse(&[], &[]).unwrap(), Config::default());
+}
+
+#[test]
+fn test_flags() {
+    let config = parse(
+        &[
+            "--sections",
+            "a.zip",
+            "--rooms",
+            "b.zip",
+            "--port",
+            "8080",
+            "--timeout",
+            "30",
+        ],
+        &[],
+    )
+    .unwrap();
+    assert_eq!(
+        config,
+        Config {
+            sections: "a.zip".to_string(),
+            rooms: "b.zip".to_string(),
+            port: 8080,
+            timeout: Duration::from_secs(30),
+            datasets: vec![],
+            cors_origins: vec![],
+            admin_token: None,
+            rate_limit: None,
+            require_limit: false,
+            denied_columns: HashMap::new(),
+            validate: false,
+        }
+    );
+}
+
+#[test]
+fn test_validate() {
+    let config = parse(&["--validate", "--port", "8080"], &[]).unwrap();
+    assert!(config.validate);
+    assert_eq!(config.port, 8080);
+    assert!(!parse(&[], &[]).unwrap().validate);
+}
+
+#[test]
+fn test_env() {
+    let config = parse(&[], &[("SECTIONS_ZIP", "a.zip"), ("PORT", "8080")]).unwrap();
+    assert_eq!(config.sections, "a.zip");
+    assert_eq!(config.rooms, "campus.zip");
+    assert_eq!(config.port, 8080);
+}
+
+#[test]
+fn test_flags_override_env() {
+    let config = parse(&["--port", "9000"], &[("PORT", "8080")]).unwrap();
+    assert_eq!(config.port, 9000);
+}
+
+#[test]
+fn test_invalid_arguments() {
+    assert_eq!(
+        parse(&["--port"], &[]).unwrap_err().to_string(),
+        "Missing value for --port"
+    );
+    assert_eq!(
+        parse(&["--verbose"], &[]).unwrap_err().to_string(),
+        "Unknown argument --verbose"
+    );
+    assert_eq!(
+        parse(&["--port", "abc"], &[]).unwrap_err().to_string(),
+        "Invalid port abc"
+    );
+    assert_eq!(
+        parse(&["--rate-limit", "0"], &[]).unwrap_err().to_string(),
+        "Invalid rate limit 0"
+    );
+    assert_eq!(
+        parse(&["--require-limit", "yes"], &[])
+            .unwrap_err()
+            .to_string(),
+        "Invalid require limit yes"
+    );
+    assert_eq!(
+        parse(&[], &[("QUERY_TIMEOUT", "-1")])
+            .unwrap_err()
+            .to_string(),
+        "Invalid timeout -1"
+    );
+}
+
+#[test]
+fn test_datasets() {
+    let config = parse(
+        &[
+            "--datasets",
+            "y2014:courses:2014.zip,y2015:courses:C:/data/2015.zip",
+        ],
+        &[],
+    )
+    .unwrap();
+    assert_eq!(
+        config.registrations(),
+        vec![
+            DatasetSpec::new("sections", "courses", "pair.zip"),
+            DatasetSpec::new("rooms", "rooms", "campus.zip"),
+            DatasetSpec::new("y2014", "courses", "2014.zip"),
+            DatasetSpec::new("y2015", "courses", "C:/data/2015.zip"),
+        ]
+    );
+    assert_eq!(
+        parse(&[], &[("DATASETS", "y2014:2014.zip")])
+            .unwrap_err()
+            .to_string(),
+        "Invalid dataset y2014:2014.zip, expected id:kind:path"
+    );
+}
+
+#[test]
+fn test_cors_origins() {
+    assert_eq!(
+        parse_origins("https://a.example.com, http://localhost:3000").unwrap(),
+        vec!["https://a.example.com", "http://localhost:3000"]
+    );
+    assert!(parse_origins("").unwrap().is_empty());
+    assert_eq!(
+        parse_origins("https://a.example.com,bad\norigin")
+            .unwrap_err()
+            .to_string(),
+        "Invalid origin bad\norigin"
+    );
+    let config = parse(&[], &[("CORS_ORIGINS", "https://a.example.com")]).unwrap();
+    assert_eq!(config.cors_origins, vec!["https://a.example.com"]);
+}
+
+#[test]
+fn test_denied_columns() {
+    let config = parse(
+        &[
+            "--deny-columns",
+            "sections:sections_instructor,sections:sections_pass,rooms:rooms_href",
+        ],
+        &[],
+    )
+    .unwrap();
+    assert_eq!(
+        config.denied_columns("sections").iter().collect::<Vec<_>>(),
+        ["sections_instructor", "sections_pass"]
+    );
+    assert_eq!(
+        config.denied_columns("rooms").iter().collect::<Vec<_>>(),
+        ["rooms_href"]
+    );
+    assert!(config.denied_columns("y2014").is_empty());
+    assert_eq!(
+        parse(&[], &[("DENY_COLUMNS", "sections_instructor")])
+            .unwrap_err()
+            .to_string(),
+        "Invalid denied column sections_instructor, expected id:column"
+    );
+}
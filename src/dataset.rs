@@ -1,21 +1,27 @@
 use crate::types::Dataset;
-use crate::types::Value;
+use crate::types::{DatasetLoader, Row, Value, boxed, import_rows};
+use flate2::read::GzDecoder;
 use macros::Dataset;
 use ordered_float::OrderedFloat;
 use serde::Deserialize;
+use std::fmt;
 use std::fs::File;
 use std::io;
 use std::io::Read;
+use tracing::warn;
 use zip::ZipArchive;
 
 pub const EPSILON: f32 = 1e-4;
 
+/// Year given to sections whose Year isn't a number, e.g. the "overall" rows, as InsightUBC does
+pub const YEAR_SENTINEL: f32 = 1900.0;
+
 #[derive(Debug, Deserialize)]
 pub(crate) struct SectionFile {
     result: Vec<SectionJson>,
 }
 
-#[derive(Debug, Dataset, Clone)]
+#[derive(Debug, Dataset, Clone, Default)]
 #[field_prefix("sections_")]
 pub struct Section {
     pub uuid: String,
@@ -30,66 +36,199 @@ pub struct Section {
     pub audit: OrderedFloat<f32>,
 }
 
+// The aliases accept the lowercase export schema alongside the original one
 #[derive(Deserialize, Debug)]
 struct SectionJson {
-    #[serde(rename = "id")]
+    #[serde(rename = "id", alias = "uuid")]
     uuid: i32,
-    #[serde(rename = "Course")]
+    #[serde(rename = "Course", alias = "course")]
     id: String,
-    #[serde(rename = "Title")]
+    #[serde(rename = "Title", alias = "title")]
     title: String,
-    #[serde(rename = "Professor")]
+    #[serde(rename = "Professor", alias = "instructor")]
     instructor: String,
-    #[serde(rename = "Subject")]
+    #[serde(rename = "Subject", alias = "dept")]
     dept: String,
-    #[serde(rename = "Year")]
+    #[serde(rename = "Year", alias = "year")]
     year: String,
-    #[serde(rename = "Avg")]
+    #[serde(rename = "Avg", alias = "avg")]
     avg: f32,
-    #[serde(rename = "Pass")]
+    #[serde(rename = "Pass", alias = "pass")]
     pass: f32,
-    #[serde(rename = "Fail")]
+    #[serde(rename = "Fail", alias = "fail")]
     fail: f32,
-    #[serde(rename = "Audit")]
+    #[serde(rename = "Audit", alias = "audit")]
     audit: f32,
 }
 
+impl From<SectionJson> for Section {
+    fn from(course: SectionJson) -> Self {
+        Section {
+            uuid: course.uuid.to_string(),
+            id: course.id,
+            title: course.title,
+            instructor: course.instructor,
+            dept: course.dept,
+            year: course
+                .year
+                .parse()
+                .unwrap_or(OrderedFloat::from(YEAR_SENTINEL)),
+            avg: OrderedFloat::from(course.avg),
+            pass: OrderedFloat::from(course.pass),
+            fail: OrderedFloat::from(course.fail),
+            audit: OrderedFloat::from(course.audit),
+        }
+    }
+}
+
+/// Sections of one `{"result": [...]}` file, shared by the zip and gzip loaders.
+/// Parsed straight from the stream, so the raw JSON is never held in memory.
+pub(crate) fn parse_section_file(reader: impl Read) -> serde_json::Result<Vec<Section>> {
+    let section_file: SectionFile = serde_json::from_reader(io::BufReader::new(reader))?;
+    Ok(section_file.result.into_iter().map(Section::from).collect())
+}
+
+fn non_empty(dataset: Vec<Section>, file_name: &str) -> io::Result<Vec<Section>> {
+    if dataset.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("No sections could be loaded from {}", file_name),
+        ));
+    }
+    Ok(dataset)
+}
+
+/// Loads a `.gz` archive or a `.json` export by extension, and anything else as a zip
+pub fn load_any(file_name: &str) -> io::Result<Vec<Section>> {
+    if file_name.ends_with(".gz") {
+        load_dataset_gz(file_name)
+    } else if file_name.ends_with(".json") {
+        load_export(file_name)
+    } else {
+        load_dataset(file_name)
+    }
+}
+
+/// Loads a single gzip-compressed section file, e.g. `courses.json.gz`
+pub fn load_dataset_gz(file_name: &str) -> io::Result<Vec<Section>> {
+    let dataset = parse_section_file(GzDecoder::new(File::open(file_name)?))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    non_empty(dataset, file_name)
+}
+
+/// Loads the JSON array of full rows that `GET /export/{id}` returns
+pub fn load_export(file_name: &str) -> io::Result<Vec<Section>> {
+    let rows = serde_json::from_reader(io::BufReader::new(File::open(file_name)?))?;
+    let dataset =
+        import_rows(rows).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    non_empty(dataset, file_name)
+}
+
 pub fn load_dataset(file_name: &str) -> io::Result<Vec<Section>> {
     let file = File::open(file_name)?;
     let mut archive = ZipArchive::new(file)?;
     let mut dataset = vec![];
 
+    // A damaged entry only loses its own sections
     for i in 0..archive.len() {
-        let mut file = archive.by_index(i)?;
-        let mut json = String::new();
-        file.read_to_string(&mut json)?;
-        let section_file: SectionFile = match serde_json::from_str(&json) {
-            Ok(c) => c,
+        let file = match archive.by_index(i) {
+            Ok(file) => file,
             Err(e) => {
-                println!("Error while parsing {}, {}", file.name(), e);
+                warn!(file = file_name, entry = i, error = %e, "Error while opening entry");
                 continue;
             }
         };
-        for section in section_file.result {
-            dataset.push(section);
+        let name = file.name().to_string();
+        match parse_section_file(file) {
+            Ok(sections) => dataset.extend(sections),
+            Err(e) if e.is_io() => {
+                warn!(file = file_name, entry = %name, error = %e, "Error while reading entry")
+            }
+            Err(e) => {
+                warn!(file = file_name, entry = %name, error = %e, "Error while parsing entry")
+            }
         }
     }
 
-    Ok(dataset
-        .into_iter()
-        .map(|course| Section {
-            uuid: course.uuid.to_string(),
-            id: course.id,
-            title: course.title,
-            instructor: course.instructor,
-            dept: course.dept,
-            year: course.year.parse().unwrap(),
-            avg: OrderedFloat::from(course.avg),
-            pass: OrderedFloat::from(course.pass),
-            fail: OrderedFloat::from(course.fail),
-            audit: OrderedFloat::from(course.audit),
-        })
-        .collect())
+    non_empty(dataset, file_name)
+}
+
+/// Loads "courses" datasets from zips of section JSON, or a gzipped section file
+pub struct CoursesLoader;
+
+impl DatasetLoader for CoursesLoader {
+    fn kind(&self) -> &str {
+        "courses"
+    }
+
+    fn load(&self, path: &str) -> anyhow::Result<Vec<Row>> {
+        Ok(boxed(load_any(path)?))
+    }
+}
+
+/// Years a section can plausibly have, besides YEAR_SENTINEL
+pub const VALID_YEARS: std::ops::RangeInclusive<f32> = 1915.0..=2100.0;
+
+/// A data quality problem with one section
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataIssue {
+    pub uuid: String,
+    pub problem: String,
+}
+
+impl fmt::Display for DataIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Section {}: {}", self.uuid, self.problem)
+    }
+}
+
+/// Flags out of range grades and years, impossible student counts and empty required strings
+pub fn validate_dataset(sections: &[Section]) -> Vec<DataIssue> {
+    let mut issues = Vec::new();
+    for section in sections {
+        let mut flag = |problem: String| {
+            issues.push(DataIssue {
+                uuid: section.uuid.clone(),
+                problem,
+            })
+        };
+
+        for (column, value) in [
+            ("uuid", &section.uuid),
+            ("id", &section.id),
+            ("title", &section.title),
+            ("dept", &section.dept),
+        ] {
+            if value.trim().is_empty() {
+                flag(format!("Empty {}", column));
+            }
+        }
+        if !(0.0..=100.0).contains(&section.avg.0) {
+            flag(format!("Average {} is outside 0 to 100", section.avg));
+        }
+        for (column, count) in [
+            ("pass", section.pass),
+            ("fail", section.fail),
+            ("audit", section.audit),
+        ] {
+            if count.0 < 0.0 || count.0.fract() != 0.0 {
+                flag(format!(
+                    "{} count {} is not a whole number of students",
+                    column, count
+                ));
+            }
+        }
+        if section.pass.0 + section.fail.0 == 0.0 && section.avg.0 != 0.0 {
+            flag(format!(
+                "Average {} with no passing or failing students",
+                section.avg
+            ));
+        }
+        if section.year.0 != YEAR_SENTINEL && !VALID_YEARS.contains(&section.year.0) {
+            flag(format!("Year {} is out of range", section.year));
+        }
+    }
+    issues
 }
 
 #[cfg(test)]
@@ -1,8 +1,236 @@
-use crate::dataset::SectionFile;
+use crate::dataset::{
+    DataIssue, Section, SectionFile, YEAR_SENTINEL, load_any, load_dataset, parse_section_file,
+    validate_dataset,
+};
+use crate::types::{Dataset, export_rows, full_row};
+use ordered_float::OrderedFloat;
 
 #[test]
 fn test_one_course() {
     let json = r#"{"result":[{"tier_eighty_five":32,"tier_ninety":3,"Title":"intr sftwr eng","Section":"101","Detail":"","tier_seventy_two":26,"Other":1,"Low":53,"tier_sixty_four":2,"id":1293,"tier_sixty_eight":14,"tier_zero":0,"tier_seventy_six":36,"tier_thirty":0,"tier_fifty":1,"Professor":"palyart-lamarche, marc","Audit":0,"tier_g_fifty":0,"tier_forty":0,"Withdrew":2,"Year":"2014","tier_twenty":0,"Stddev":6.78,"Enrolled":160,"tier_fifty_five":0,"tier_eighty":38,"tier_sixty":4,"tier_ten":0,"High":94,"Course":"310","Session":"w","Pass":156,"Fail":0,"Avg":78.69,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":22,"tier_ninety":35,"Title":"intr sftwr eng","Section":"201","Detail":"","tier_seventy_two":14,"Other":1,"Low":51,"tier_sixty_four":9,"id":1294,"tier_sixty_eight":13,"tier_zero":0,"tier_seventy_six":21,"tier_thirty":0,"tier_fifty":1,"Professor":"baniassad, elisa","Audit":0,"tier_g_fifty":0,"tier_forty":0,"Withdrew":2,"Year":"2014","tier_twenty":0,"Stddev":9,"Enrolled":153,"tier_fifty_five":0,"tier_eighty":33,"tier_sixty":2,"tier_ten":0,"High":97,"Course":"310","Session":"w","Pass":150,"Fail":0,"Avg":80.35,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":54,"tier_ninety":38,"Title":"intr sftwr eng","Section":"overall","Detail":"","tier_seventy_two":40,"Other":2,"Low":51,"tier_sixty_four":11,"id":1295,"tier_sixty_eight":27,"tier_zero":0,"tier_seventy_six":57,"tier_thirty":0,"tier_fifty":2,"Professor":"","Audit":0,"tier_g_fifty":0,"tier_forty":0,"Withdrew":8,"Year":"2014","tier_twenty":0,"Stddev":7.98,"Enrolled":317,"tier_fifty_five":0,"tier_eighty":71,"tier_sixty":6,"tier_ten":0,"High":97,"Course":"310","Session":"w","Pass":306,"Fail":0,"Avg":79.5,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":40,"tier_ninety":28,"Title":"intr sftwr eng","Section":"101","Detail":"","tier_seventy_two":11,"Other":0,"Low":4,"tier_sixty_four":2,"id":3393,"tier_sixty_eight":6,"tier_zero":2,"tier_seventy_six":16,"tier_thirty":0,"tier_fifty":2,"Professor":"holmes, reid","Audit":0,"tier_g_fifty":3,"tier_forty":0,"Withdrew":16,"Year":"2016","tier_twenty":0,"Stddev":13.34,"Enrolled":158,"tier_fifty_five":2,"tier_eighty":32,"tier_sixty":0,"tier_ten":1,"High":98,"Course":"310","Session":"w","Pass":139,"Fail":3,"Avg":81.17,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":40,"tier_ninety":28,"Title":"intr sftwr eng","Section":"overall","Detail":"","tier_seventy_two":11,"Other":0,"Low":4,"tier_sixty_four":2,"id":3394,"tier_sixty_eight":6,"tier_zero":2,"tier_seventy_six":16,"tier_thirty":0,"tier_fifty":2,"Professor":"","Audit":0,"tier_g_fifty":3,"tier_forty":0,"Withdrew":32,"Year":"2016","tier_twenty":0,"Stddev":13.34,"Enrolled":174,"tier_fifty_five":2,"tier_eighty":32,"tier_sixty":0,"tier_ten":1,"High":98,"Course":"310","Session":"w","Pass":139,"Fail":3,"Avg":81.17,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":8,"tier_ninety":10,"Title":"intr sftwr eng","Section":"911","Detail":"","tier_seventy_two":3,"Other":0,"Low":45,"tier_sixty_four":1,"id":25818,"tier_sixty_eight":1,"tier_zero":0,"tier_seventy_six":6,"tier_thirty":0,"tier_fifty":0,"Professor":"allen, meghan","Audit":0,"tier_g_fifty":1,"tier_forty":1,"Withdrew":0,"Year":"2010","tier_twenty":0,"Stddev":11.03,"Enrolled":32,"tier_fifty_five":0,"tier_eighty":1,"tier_sixty":1,"tier_ten":0,"High":94,"Course":"310","Session":"s","Pass":31,"Fail":1,"Avg":81.88,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":8,"tier_ninety":10,"Title":"intr sftwr eng","Section":"overall","Detail":"","tier_seventy_two":3,"Other":0,"Low":45,"tier_sixty_four":1,"id":25819,"tier_sixty_eight":1,"tier_zero":0,"tier_seventy_six":6,"tier_thirty":0,"tier_fifty":0,"Professor":"","Audit":0,"tier_g_fifty":1,"tier_forty":1,"Withdrew":0,"Year":"2010","tier_twenty":0,"Stddev":11.03,"Enrolled":32,"tier_fifty_five":0,"tier_eighty":1,"tier_sixty":1,"tier_ten":0,"High":94,"Course":"310","Session":"s","Pass":31,"Fail":1,"Avg":81.88,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":12,"tier_ninety":6,"Title":"intr sftwr eng","Section":"921","Detail":"","tier_seventy_two":1,"Other":0,"Low":75,"tier_sixty_four":0,"id":39884,"tier_sixty_eight":0,"tier_zero":0,"tier_seventy_six":3,"tier_thirty":0,"tier_fifty":0,"Professor":"","Audit":0,"tier_g_fifty":0,"tier_forty":0,"Withdrew":0,"Year":"2008","tier_twenty":0,"Stddev":4.33,"Enrolled":47,"tier_fifty_five":0,"tier_eighty":25,"tier_sixty":0,"tier_ten":0,"High":94,"Course":"310","Session":"s","Pass":47,"Fail":0,"Avg":84.6,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":12,"tier_ninety":6,"Title":"intr sftwr eng","Section":"overall","Detail":"","tier_seventy_two":1,"Other":0,"Low":75,"tier_sixty_four":0,"id":39885,"tier_sixty_eight":0,"tier_zero":0,"tier_seventy_six":3,"tier_thirty":0,"tier_fifty":0,"Professor":"","Audit":0,"tier_g_fifty":0,"tier_forty":0,"Withdrew":0,"Year":"2008","tier_twenty":0,"Stddev":4.33,"Enrolled":47,"tier_fifty_five":0,"tier_eighty":25,"tier_sixty":0,"tier_ten":0,"High":94,"Course":"310","Session":"s","Pass":47,"Fail":0,"Avg":84.6,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":13,"tier_ninety":5,"Title":"intr sftwr eng","Section":"911","Detail":"","tier_seventy_two":4,"Other":0,"Low":45,"tier_sixty_four":2,"id":43202,"tier_sixty_eight":2,"tier_zero":0,"tier_seventy_six":15,"tier_thirty":0,"tier_fifty":0,"Professor":"allen, meghan","Audit":0,"tier_g_fifty":1,"tier_forty":1,"Withdrew":2,"Year":"2012","tier_twenty":0,"Stddev":7.32,"Enrolled":69,"tier_fifty_five":0,"tier_eighty":25,"tier_sixty":0,"tier_ten":0,"High":96,"Course":"310","Session":"s","Pass":66,"Fail":1,"Avg":80.34,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":13,"tier_ninety":5,"Title":"intr sftwr eng","Section":"overall","Detail":"","tier_seventy_two":4,"Other":0,"Low":45,"tier_sixty_four":2,"id":43203,"tier_sixty_eight":2,"tier_zero":0,"tier_seventy_six":15,"tier_thirty":0,"tier_fifty":0,"Professor":"","Audit":0,"tier_g_fifty":1,"tier_forty":1,"Withdrew":4,"Year":"2012","tier_twenty":0,"Stddev":7.32,"Enrolled":71,"tier_fifty_five":0,"tier_eighty":25,"tier_sixty":0,"tier_ten":0,"High":96,"Course":"310","Session":"s","Pass":66,"Fail":1,"Avg":80.34,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":13,"tier_ninety":5,"Title":"intr sftwr eng","Section":"101","Detail":"","tier_seventy_two":11,"Other":0,"Low":35,"tier_sixty_four":5,"id":46694,"tier_sixty_eight":7,"tier_zero":0,"tier_seventy_six":19,"tier_thirty":1,"tier_fifty":0,"Professor":"allen, meghan","Audit":0,"tier_g_fifty":1,"tier_forty":0,"Withdrew":0,"Year":"2009","tier_twenty":0,"Stddev":8.63,"Enrolled":98,"tier_fifty_five":1,"tier_eighty":31,"tier_sixty":5,"tier_ten":0,"High":92,"Course":"310","Session":"w","Pass":97,"Fail":1,"Avg":77.63,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":10,"tier_ninety":4,"Title":"intr sftwr eng","Section":"201","Detail":"","tier_seventy_two":9,"Other":0,"Low":30,"tier_sixty_four":4,"id":46695,"tier_sixty_eight":10,"tier_zero":0,"tier_seventy_six":12,"tier_thirty":3,"tier_fifty":3,"Professor":"allen, meghan","Audit":0,"tier_g_fifty":5,"tier_forty":2,"Withdrew":0,"Year":"2009","tier_twenty":0,"Stddev":13.04,"Enrolled":86,"tier_fifty_five":4,"tier_eighty":20,"tier_sixty":5,"tier_ten":0,"High":91,"Course":"310","Session":"w","Pass":81,"Fail":5,"Avg":73.16,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":23,"tier_ninety":9,"Title":"intr sftwr eng","Section":"overall","Detail":"","tier_seventy_two":20,"Other":0,"Low":30,"tier_sixty_four":9,"id":46696,"tier_sixty_eight":17,"tier_zero":0,"tier_seventy_six":31,"tier_thirty":4,"tier_fifty":3,"Professor":"","Audit":0,"tier_g_fifty":6,"tier_forty":2,"Withdrew":0,"Year":"2009","tier_twenty":0,"Stddev":11.11,"Enrolled":184,"tier_fifty_five":5,"tier_eighty":51,"tier_sixty":10,"tier_ten":0,"High":92,"Course":"310","Session":"w","Pass":178,"Fail":6,"Avg":75.54,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":37,"tier_ninety":29,"Title":"intr sftwr eng","Section":"101","Detail":"","tier_seventy_two":12,"Other":0,"Low":45,"tier_sixty_four":14,"id":49892,"tier_sixty_eight":13,"tier_zero":0,"tier_seventy_six":17,"tier_thirty":0,"tier_fifty":0,"Professor":"wohlstadter, eric","Audit":0,"tier_g_fifty":1,"tier_forty":1,"Withdrew":3,"Year":"2013","tier_twenty":0,"Stddev":9.96,"Enrolled":160,"tier_fifty_five":3,"tier_eighty":26,"tier_sixty":5,"tier_ten":0,"High":96,"Course":"310","Session":"w","Pass":156,"Fail":1,"Avg":80.24,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":16,"tier_ninety":9,"Title":"intr sftwr eng","Section":"201","Detail":"","tier_seventy_two":24,"Other":0,"Low":1,"tier_sixty_four":15,"id":49893,"tier_sixty_eight":16,"tier_zero":1,"tier_seventy_six":20,"tier_thirty":1,"tier_fifty":3,"Professor":"allen, meghan","Audit":0,"tier_g_fifty":4,"tier_forty":2,"Withdrew":4,"Year":"2013","tier_twenty":0,"Stddev":12.2,"Enrolled":142,"tier_fifty_five":0,"tier_eighty":25,"tier_sixty":5,"tier_ten":0,"High":99,"Course":"310","Session":"w","Pass":133,"Fail":4,"Avg":74.99,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":53,"tier_ninety":38,"Title":"intr sftwr eng","Section":"overall","Detail":"","tier_seventy_two":36,"Other":0,"Low":1,"tier_sixty_four":29,"id":49894,"tier_sixty_eight":29,"tier_zero":1,"tier_seventy_six":37,"tier_thirty":1,"tier_fifty":3,"Professor":"","Audit":0,"tier_g_fifty":5,"tier_forty":3,"Withdrew":14,"Year":"2013","tier_twenty":0,"Stddev":11.35,"Enrolled":309,"tier_fifty_five":3,"tier_eighty":51,"tier_sixty":10,"tier_ten":0,"High":99,"Course":"310","Session":"w","Pass":289,"Fail":5,"Avg":77.8,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":9,"tier_ninety":1,"Title":"intr sftwr eng","Section":"101","Detail":"","tier_seventy_two":18,"Other":0,"Low":45,"tier_sixty_four":9,"id":52004,"tier_sixty_eight":7,"tier_zero":0,"tier_seventy_six":17,"tier_thirty":0,"tier_fifty":1,"Professor":"","Audit":0,"tier_g_fifty":1,"tier_forty":1,"Withdrew":0,"Year":"2008","tier_twenty":0,"Stddev":7.98,"Enrolled":83,"tier_fifty_five":0,"tier_eighty":18,"tier_sixty":2,"tier_ten":0,"High":90,"Course":"310","Session":"w","Pass":82,"Fail":1,"Avg":75.4,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":10,"tier_ninety":3,"Title":"intr sftwr eng","Section":"201","Detail":"","tier_seventy_two":16,"Other":0,"Low":52,"tier_sixty_four":4,"id":52005,"tier_sixty_eight":9,"tier_zero":0,"tier_seventy_six":17,"tier_thirty":0,"tier_fifty":1,"Professor":"","Audit":0,"tier_g_fifty":0,"tier_forty":0,"Withdrew":2,"Year":"2008","tier_twenty":0,"Stddev":7.78,"Enrolled":88,"tier_fifty_five":1,"tier_eighty":22,"tier_sixty":3,"tier_ten":0,"High":91,"Course":"310","Session":"w","Pass":86,"Fail":0,"Avg":76.52,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":19,"tier_ninety":4,"Title":"intr sftwr eng","Section":"overall","Detail":"","tier_seventy_two":34,"Other":0,"Low":45,"tier_sixty_four":13,"id":52006,"tier_sixty_eight":16,"tier_zero":0,"tier_seventy_six":34,"tier_thirty":0,"tier_fifty":2,"Professor":"","Audit":0,"tier_g_fifty":1,"tier_forty":1,"Withdrew":4,"Year":"2008","tier_twenty":0,"Stddev":7.88,"Enrolled":173,"tier_fifty_five":1,"tier_eighty":40,"tier_sixty":5,"tier_ten":0,"High":91,"Course":"310","Session":"w","Pass":168,"Fail":1,"Avg":75.97,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":12,"tier_ninety":7,"Title":"intr sftwr eng","Section":"101","Detail":"","tier_seventy_two":13,"Other":0,"Low":58,"tier_sixty_four":6,"id":61122,"tier_sixty_eight":10,"tier_zero":0,"tier_seventy_six":23,"tier_thirty":0,"tier_fifty":0,"Professor":"ernst, neil","Audit":0,"tier_g_fifty":0,"tier_forty":0,"Withdrew":1,"Year":"2011","tier_twenty":0,"Stddev":7.58,"Enrolled":102,"tier_fifty_five":1,"tier_eighty":26,"tier_sixty":3,"tier_ten":0,"High":95,"Course":"310","Session":"w","Pass":101,"Fail":0,"Avg":77.78,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":8,"tier_ninety":9,"Title":"intr sftwr eng","Section":"201","Detail":"","tier_seventy_two":14,"Other":0,"Low":45,"tier_sixty_four":3,"id":61123,"tier_sixty_eight":12,"tier_zero":0,"tier_seventy_six":13,"tier_thirty":0,"tier_fifty":2,"Professor":"ernst, neil","Audit":0,"tier_g_fifty":2,"tier_forty":2,"Withdrew":1,"Year":"2011","tier_twenty":0,"Stddev":10.1,"Enrolled":86,"tier_fifty_five":1,"tier_eighty":19,"tier_sixty":2,"tier_ten":0,"High":95,"Course":"310","Session":"w","Pass":83,"Fail":2,"Avg":76.31,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":20,"tier_ninety":16,"Title":"intr sftwr eng","Section":"overall","Detail":"","tier_seventy_two":27,"Other":0,"Low":45,"tier_sixty_four":9,"id":61124,"tier_sixty_eight":22,"tier_zero":0,"tier_seventy_six":36,"tier_thirty":0,"tier_fifty":2,"Professor":"","Audit":0,"tier_g_fifty":2,"tier_forty":2,"Withdrew":4,"Year":"2011","tier_twenty":0,"Stddev":8.82,"Enrolled":190,"tier_fifty_five":2,"tier_eighty":45,"tier_sixty":5,"tier_ten":0,"High":95,"Course":"310","Session":"w","Pass":184,"Fail":2,"Avg":77.11,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":26,"tier_ninety":14,"Title":"intr sftwr eng","Section":"101","Detail":"","tier_seventy_two":31,"Other":1,"Low":50,"tier_sixty_four":11,"id":62385,"tier_sixty_eight":12,"tier_zero":0,"tier_seventy_six":43,"tier_thirty":0,"tier_fifty":2,"Professor":"baniassad, elisa","Audit":0,"tier_g_fifty":0,"tier_forty":0,"Withdrew":2,"Year":"2015","tier_twenty":0,"Stddev":8.29,"Enrolled":188,"tier_fifty_five":4,"tier_eighty":37,"tier_sixty":5,"tier_ten":0,"High":94,"Course":"310","Session":"w","Pass":185,"Fail":0,"Avg":77.13,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":39,"tier_ninety":36,"Title":"intr sftwr eng","Section":"201","Detail":"","tier_seventy_two":14,"Other":1,"Low":45,"tier_sixty_four":9,"id":62386,"tier_sixty_eight":11,"tier_zero":0,"tier_seventy_six":15,"tier_thirty":0,"tier_fifty":0,"Professor":"baniassad, elisa","Audit":0,"tier_g_fifty":3,"tier_forty":3,"Withdrew":1,"Year":"2015","tier_twenty":0,"Stddev":10.34,"Enrolled":167,"tier_fifty_five":3,"tier_eighty":33,"tier_sixty":2,"tier_ten":0,"High":99,"Course":"310","Session":"w","Pass":162,"Fail":3,"Avg":81.18,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":65,"tier_ninety":50,"Title":"intr sftwr eng","Section":"overall","Detail":"","tier_seventy_two":45,"Other":2,"Low":45,"tier_sixty_four":20,"id":62387,"tier_sixty_eight":23,"tier_zero":0,"tier_seventy_six":58,"tier_thirty":0,"tier_fifty":2,"Professor":"","Audit":0,"tier_g_fifty":3,"tier_forty":3,"Withdrew":5,"Year":"2015","tier_twenty":0,"Stddev":9.52,"Enrolled":357,"tier_fifty_five":7,"tier_eighty":70,"tier_sixty":7,"tier_ten":0,"High":99,"Course":"310","Session":"w","Pass":347,"Fail":3,"Avg":79.04,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":12,"tier_ninety":14,"Title":"intr sftwr eng","Section":"921","Detail":"","tier_seventy_two":9,"Other":0,"Low":38,"tier_sixty_four":4,"id":67312,"tier_sixty_eight":9,"tier_zero":0,"tier_seventy_six":12,"tier_thirty":1,"tier_fifty":0,"Professor":"baniassad, elisa","Audit":1,"tier_g_fifty":1,"tier_forty":0,"Withdrew":0,"Year":"2014","tier_twenty":0,"Stddev":10.44,"Enrolled":77,"tier_fifty_five":0,"tier_eighty":13,"tier_sixty":2,"tier_ten":0,"High":100,"Course":"310","Session":"s","Pass":75,"Fail":1,"Avg":79.12,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":12,"tier_ninety":14,"Title":"intr sftwr eng","Section":"overall","Detail":"","tier_seventy_two":9,"Other":0,"Low":38,"tier_sixty_four":4,"id":67313,"tier_sixty_eight":9,"tier_zero":0,"tier_seventy_six":12,"tier_thirty":1,"tier_fifty":0,"Professor":"","Audit":2,"tier_g_fifty":1,"tier_forty":0,"Withdrew":0,"Year":"2014","tier_twenty":0,"Stddev":10.44,"Enrolled":78,"tier_fifty_five":0,"tier_eighty":13,"tier_sixty":2,"tier_ten":0,"High":100,"Course":"310","Session":"s","Pass":75,"Fail":1,"Avg":79.12,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":20,"tier_ninety":9,"Title":"intr sftwr eng","Section":"101","Detail":"","tier_seventy_two":9,"Other":0,"Low":45,"tier_sixty_four":2,"id":72359,"tier_sixty_eight":8,"tier_zero":0,"tier_seventy_six":17,"tier_thirty":0,"tier_fifty":0,"Professor":"allen, meghan","Audit":0,"tier_g_fifty":1,"tier_forty":1,"Withdrew":0,"Year":"2010","tier_twenty":0,"Stddev":8.36,"Enrolled":93,"tier_fifty_five":1,"tier_eighty":26,"tier_sixty":0,"tier_ten":0,"High":99,"Course":"310","Session":"w","Pass":92,"Fail":1,"Avg":80.32,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":7,"tier_ninety":10,"Title":"intr sftwr eng","Section":"201","Detail":"","tier_seventy_two":11,"Other":0,"Low":39,"tier_sixty_four":2,"id":72360,"tier_sixty_eight":4,"tier_zero":0,"tier_seventy_six":8,"tier_thirty":1,"tier_fifty":0,"Professor":"fritz, thomas","Audit":0,"tier_g_fifty":6,"tier_forty":5,"Withdrew":0,"Year":"2010","tier_twenty":0,"Stddev":13.01,"Enrolled":68,"tier_fifty_five":1,"tier_eighty":15,"tier_sixty":4,"tier_ten":0,"High":93,"Course":"310","Session":"w","Pass":62,"Fail":6,"Avg":75.57,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":27,"tier_ninety":19,"Title":"intr sftwr eng","Section":"overall","Detail":"","tier_seventy_two":20,"Other":0,"Low":39,"tier_sixty_four":4,"id":72361,"tier_sixty_eight":12,"tier_zero":0,"tier_seventy_six":25,"tier_thirty":1,"tier_fifty":0,"Professor":"","Audit":0,"tier_g_fifty":7,"tier_forty":6,"Withdrew":0,"Year":"2010","tier_twenty":0,"Stddev":10.8,"Enrolled":161,"tier_fifty_five":2,"tier_eighty":41,"tier_sixty":4,"tier_ten":0,"High":99,"Course":"310","Session":"w","Pass":154,"Fail":7,"Avg":78.32,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":3,"tier_ninety":0,"Title":"intr sftwr eng","Section":"911","Detail":"","tier_seventy_two":2,"Other":0,"Low":62,"tier_sixty_four":1,"id":75605,"tier_sixty_eight":1,"tier_zero":0,"tier_seventy_six":4,"tier_thirty":0,"tier_fifty":0,"Professor":"allen, meghan","Audit":0,"tier_g_fifty":0,"tier_forty":0,"Withdrew":1,"Year":"2009","tier_twenty":0,"Stddev":6.47,"Enrolled":23,"tier_fifty_five":0,"tier_eighty":10,"tier_sixty":1,"tier_ten":0,"High":87,"Course":"310","Session":"s","Pass":22,"Fail":0,"Avg":78.68,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":3,"tier_ninety":0,"Title":"intr sftwr eng","Section":"overall","Detail":"","tier_seventy_two":2,"Other":0,"Low":62,"tier_sixty_four":1,"id":75606,"tier_sixty_eight":1,"tier_zero":0,"tier_seventy_six":4,"tier_thirty":0,"tier_fifty":0,"Professor":"","Audit":0,"tier_g_fifty":0,"tier_forty":0,"Withdrew":2,"Year":"2009","tier_twenty":0,"Stddev":6.47,"Enrolled":24,"tier_fifty_five":0,"tier_eighty":10,"tier_sixty":1,"tier_ten":0,"High":87,"Course":"310","Session":"s","Pass":22,"Fail":0,"Avg":78.68,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":29,"tier_ninety":16,"Title":"intr sftwr eng","Section":"101","Detail":"","tier_seventy_two":20,"Other":0,"Low":31,"tier_sixty_four":8,"id":83431,"tier_sixty_eight":17,"tier_zero":0,"tier_seventy_six":32,"tier_thirty":1,"tier_fifty":0,"Professor":"allen, meghan","Audit":0,"tier_g_fifty":2,"tier_forty":1,"Withdrew":0,"Year":"2012","tier_twenty":0,"Stddev":9.41,"Enrolled":156,"tier_fifty_five":3,"tier_eighty":27,"tier_sixty":2,"tier_ten":0,"High":95,"Course":"310","Session":"w","Pass":154,"Fail":2,"Avg":78.22,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":11,"tier_ninety":4,"Title":"intr sftwr eng","Section":"201","Detail":"","tier_seventy_two":12,"Other":5,"Low":33,"tier_sixty_four":11,"id":83432,"tier_sixty_eight":17,"tier_zero":0,"tier_seventy_six":18,"tier_thirty":2,"tier_fifty":3,"Professor":"allen, meghan","Audit":0,"tier_g_fifty":6,"tier_forty":4,"Withdrew":0,"Year":"2012","tier_twenty":0,"Stddev":11.87,"Enrolled":112,"tier_fifty_five":5,"tier_eighty":15,"tier_sixty":5,"tier_ten":0,"High":93,"Course":"310","Session":"w","Pass":101,"Fail":6,"Avg":72.27,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":40,"tier_ninety":20,"Title":"intr sftwr eng","Section":"overall","Detail":"","tier_seventy_two":32,"Other":5,"Low":31,"tier_sixty_four":19,"id":83433,"tier_sixty_eight":34,"tier_zero":0,"tier_seventy_six":50,"tier_thirty":3,"tier_fifty":3,"Professor":"","Audit":0,"tier_g_fifty":8,"tier_forty":5,"Withdrew":2,"Year":"2012","tier_twenty":0,"Stddev":10.86,"Enrolled":270,"tier_fifty_five":8,"tier_eighty":42,"tier_sixty":7,"tier_ten":0,"High":95,"Course":"310","Session":"w","Pass":255,"Fail":8,"Avg":75.8,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":10,"tier_ninety":6,"Title":"intr sftwr eng","Section":"101","Detail":"","tier_seventy_two":10,"Other":0,"Low":30,"tier_sixty_four":9,"id":90539,"tier_sixty_eight":8,"tier_zero":0,"tier_seventy_six":13,"tier_thirty":1,"tier_fifty":1,"Professor":"shepherd, david","Audit":0,"tier_g_fifty":2,"tier_forty":1,"Withdrew":0,"Year":"2007","tier_twenty":0,"Stddev":11.19,"Enrolled":78,"tier_fifty_five":1,"tier_eighty":11,"tier_sixty":7,"tier_ten":0,"High":94,"Course":"310","Session":"w","Pass":76,"Fail":2,"Avg":74.45,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":9,"tier_ninety":2,"Title":"intr sftwr eng","Section":"201","Detail":"","tier_seventy_two":20,"Other":0,"Low":48,"tier_sixty_four":6,"id":90540,"tier_sixty_eight":8,"tier_zero":0,"tier_seventy_six":21,"tier_thirty":0,"tier_fifty":0,"Professor":"shepherd, david","Audit":0,"tier_g_fifty":2,"tier_forty":2,"Withdrew":0,"Year":"2007","tier_twenty":0,"Stddev":8.7,"Enrolled":92,"tier_fifty_five":3,"tier_eighty":18,"tier_sixty":3,"tier_ten":0,"High":98,"Course":"310","Session":"w","Pass":90,"Fail":2,"Avg":75.72,"Campus":"ubc","Subject":"cpsc"},{"tier_eighty_five":19,"tier_ninety":8,"Title":"intr sftwr eng","Section":"overall","Detail":"","tier_seventy_two":30,"Other":0,"Low":30,"tier_sixty_four":15,"id":90541,"tier_sixty_eight":16,"tier_zero":0,"tier_seventy_six":34,"tier_thirty":1,"tier_fifty":1,"Professor":"","Audit":0,"tier_g_fifty":4,"tier_forty":3,"Withdrew":0,"Year":"2007","tier_twenty":0,"Stddev":9.91,"Enrolled":170,"tier_fifty_five":4,"tier_eighty":29,"tier_sixty":10,"tier_ten":0,"High":98,"Course":"310","Session":"w","Pass":166,"Fail":4,"Avg":75.14,"Campus":"ubc","Subject":"cpsc"}],"rank":522}"#;
     let course: SectionFile = serde_json::from_str(&json).unwrap();
     println!("{:?}", course);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_column_type() {
+    let section = Section {
+        uuid: "1".to_string(),
+        id: "310".to_string(),
+        title: "intr sftwr eng".to_string(),
+        instructor: "".to_string(),
+        dept: "cpsc".to_string(),
+        year: OrderedFloat::from(2014f32),
+        avg: OrderedFloat::from(78.69f32),
+        pass: OrderedFloat::from(156f32),
+        fail: OrderedFloat::from(0f32),
+        audit: OrderedFloat::from(0f32),
+    };
+    assert_eq!(section.column_type("sections_avg"), Some("number"));
+    assert_eq!(section.column_type("sections_year"), Some("number"));
+    assert_eq!(section.column_type("sections_dept"), Some("string"));
+    assert_eq!(section.column_type("sections_uuid"), Some("string"));
+    assert_eq!(section.column_type("avg"), None);
+}
+
+const VALID_SECTION: &str = r#"{"result":[{"id":1,"Course":"310","Title":"intr sftwr eng","Professor":"","Subject":"cpsc","Year":"2014","Avg":78.69,"Pass":156,"Fail":0,"Audit":0}]}"#;
+
+// This file is compiled into two modules, so each copy gets its own archive
+fn temp_zip(name: &str) -> std::path::PathBuf {
+    let module = module_path!().replace("::", "-");
+    std::env::temp_dir().join(format!("{}-{}.zip", module, name))
+}
+
+// Stored entries keep the JSON verbatim so the test can damage one in place
+fn write_zip(path: &std::path::Path, entries: &[(&str, &str)]) {
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(path).unwrap());
+    let options =
+        zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    for (name, contents) in entries {
+        zip.start_file(*name, options).unwrap();
+        std::io::Write::write_all(&mut zip, contents.as_bytes()).unwrap();
+    }
+    zip.finish().unwrap();
+}
+
+#[test]
+fn test_corrupt_entry_is_skipped() {
+    let path = temp_zip("corrupt-entry");
+    let damaged = VALID_SECTION.replace("cpsc", "math");
+    write_zip(
+        &path,
+        &[("good.json", VALID_SECTION), ("bad.json", &damaged)],
+    );
+
+    // Flip the payload of the second entry so its CRC no longer matches
+    let mut bytes = std::fs::read(&path).unwrap();
+    let at = bytes.windows(4).position(|w| w == b"math").unwrap();
+    bytes[at..at + 4].copy_from_slice(b"MATH");
+    std::fs::write(&path, bytes).unwrap();
+
+    let sections = load_dataset(path.to_str().unwrap()).unwrap();
+    assert_eq!(sections.len(), 1);
+    assert_eq!(sections[0].dept, "cpsc");
+}
+
+#[test]
+fn test_no_loadable_sections() {
+    let path = temp_zip("no-sections");
+    write_zip(&path, &[("bad.json", "not json")]);
+    assert!(load_dataset(path.to_str().unwrap()).is_err());
+}
+
+#[test]
+fn test_unparseable_year() {
+    let path = temp_zip("overall-year");
+    let overall = VALID_SECTION.replace(r#""Year":"2014""#, r#""Year":"overall""#);
+    write_zip(&path, &[("overall.json", &overall)]);
+
+    let sections = load_dataset(path.to_str().unwrap()).unwrap();
+    assert_eq!(sections[0].year, OrderedFloat::from(YEAR_SENTINEL));
+}
+
+#[test]
+fn test_gzip_dataset() {
+    let path = temp_zip("gzip").with_extension("json.gz");
+    let mut gz = flate2::write::GzEncoder::new(
+        std::fs::File::create(&path).unwrap(),
+        flate2::Compression::default(),
+    );
+    std::io::Write::write_all(&mut gz, VALID_SECTION.as_bytes()).unwrap();
+    gz.finish().unwrap();
+
+    let sections = load_any(path.to_str().unwrap()).unwrap();
+    assert_eq!(sections.len(), 1);
+    assert_eq!(sections[0].uuid, "1");
+    assert_eq!(sections[0].avg, OrderedFloat::from(78.69f32));
+}
+
+#[test]
+fn test_zip_and_gzip_agree() {
+    // The single section of VALID_SECTION, under a new id
+    let section = VALID_SECTION
+        .strip_prefix(r#"{"result":["#)
+        .and_then(|rest| rest.strip_suffix("]}"))
+        .unwrap();
+    let row = |id: u32| section.replace(r#""id":1"#, &format!(r#""id":{}"#, id));
+    let json = format!(r#"{{"result":[{},{}]}}"#, row(1), row(2));
+
+    let zip_path = temp_zip("streamed");
+    write_zip(&zip_path, &[("courses.json", &json)]);
+    let gz_path = zip_path.with_extension("json.gz");
+    let mut gz = flate2::write::GzEncoder::new(
+        std::fs::File::create(&gz_path).unwrap(),
+        flate2::Compression::default(),
+    );
+    std::io::Write::write_all(&mut gz, json.as_bytes()).unwrap();
+    gz.finish().unwrap();
+
+    let uuids = |path: &std::path::Path| -> Vec<String> {
+        load_any(path.to_str().unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|section| section.uuid)
+            .collect()
+    };
+    assert_eq!(uuids(&zip_path), vec!["1", "2"]);
+    assert_eq!(uuids(&gz_path), uuids(&zip_path));
+}
+
+#[test]
+fn test_alternative_schema() {
+    let lowercase = r#"{"result":[{"uuid":1,"course":"310","title":"intr sftwr eng","instructor":"","dept":"cpsc","year":"2014","avg":78.69,"pass":156,"fail":0,"audit":0}]}"#;
+    let original = parse_section_file(VALID_SECTION.as_bytes()).unwrap();
+    let alternative = parse_section_file(lowercase.as_bytes()).unwrap();
+    assert_eq!(format!("{:?}", alternative), format!("{:?}", original));
+}
+
+#[test]
+fn test_validate_dataset() {
+    let valid = Section {
+        uuid: "1".to_string(),
+        id: "310".to_string(),
+        title: "intr sftwr eng".to_string(),
+        instructor: "".to_string(),
+        dept: "cpsc".to_string(),
+        year: OrderedFloat::from(2014f32),
+        avg: OrderedFloat::from(78.69f32),
+        pass: OrderedFloat::from(156f32),
+        fail: OrderedFloat::from(0f32),
+        audit: OrderedFloat::from(0f32),
+    };
+    let sections = vec![
+        valid.clone(),
+        // The overall rows have no year, which isn't an issue
+        Section {
+            uuid: "2".to_string(),
+            year: OrderedFloat::from(YEAR_SENTINEL),
+            ..valid.clone()
+        },
+        Section {
+            uuid: "3".to_string(),
+            avg: OrderedFloat::from(-5f32),
+            ..valid.clone()
+        },
+        Section {
+            uuid: "4".to_string(),
+            fail: OrderedFloat::from(-1f32),
+            audit: OrderedFloat::from(0.5f32),
+            ..valid.clone()
+        },
+        Section {
+            uuid: "5".to_string(),
+            pass: OrderedFloat::from(0f32),
+            ..valid.clone()
+        },
+        Section {
+            uuid: "6".to_string(),
+            title: " ".to_string(),
+            dept: "".to_string(),
+            ..valid.clone()
+        },
+        Section {
+            uuid: "7".to_string(),
+            year: OrderedFloat::from(2514f32),
+            ..valid.clone()
+        },
+    ];
+
+    let issues: Vec<String> = validate_dataset(&sections)
+        .iter()
+        .map(DataIssue::to_string)
+        .collect();
+    assert_eq!(
+        issues,
+        [
+            "Section 3: Average -5 is outside 0 to 100",
+            "Section 4: fail count -1 is not a whole number of students",
+            "Section 4: audit count 0.5 is not a whole number of students",
+            "Section 5: Average 78.69 with no passing or failing students",
+            "Section 6: Empty title",
+            "Section 6: Empty dept",
+            "Section 7: Year 2514 is out of range",
+        ]
+    );
+}
+
+#[test]
+fn test_export_round_trip() {
+    let path = temp_zip("export").with_extension("json");
+    let zip_path = temp_zip("exported");
+    write_zip(&zip_path, &[("courses.json", VALID_SECTION)]);
+    let sections = load_any(zip_path.to_str().unwrap()).unwrap();
+    let exported = export_rows(&sections).unwrap();
+    std::fs::write(&path, serde_json::to_vec(&exported).unwrap()).unwrap();
+
+    let reloaded = load_any(path.to_str().unwrap()).unwrap();
+    assert_eq!(format!("{:?}", reloaded), format!("{:?}", sections));
+    assert_eq!(full_row(&reloaded[0]).unwrap(), exported[0]);
+
+    // Rows with a column left out, e.g. a denied one, can't be rebuilt
+    let mut partial = exported;
+    partial[0].remove("sections_title");
+    std::fs::write(&path, serde_json::to_vec(&partial).unwrap()).unwrap();
+    let error = load_any(path.to_str().unwrap()).unwrap_err();
+    assert!(error.to_string().contains("missing sections_title"));
+}
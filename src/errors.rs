@@ -0,0 +1,93 @@
+use std::fmt::{Display, Formatter};
+
+/// Errors raised while executing a query, so callers can match on the kind of failure
+#[derive(Debug, Clone, PartialEq)]
+pub enum EngineError {
+    ResultTooLargeError,
+    TypeError {
+        op: &'static str,
+        column: String,
+    },
+    FieldNotFound {
+        field: String,
+        suggestion: Option<String>,
+    },
+}
+
+// Typos further than this from every known column get no suggestion
+const SUGGESTION_THRESHOLD: usize = 3;
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+impl EngineError {
+    /// Missing field error suggesting the closest of the known columns
+    pub fn field_not_found<'a>(field: &str, known: impl IntoIterator<Item = &'a str>) -> Self {
+        let suggestion = known
+            .into_iter()
+            .map(|candidate| (edit_distance(field, candidate), candidate))
+            .filter(|(distance, _)| *distance <= SUGGESTION_THRESHOLD)
+            .min()
+            .map(|(_, candidate)| candidate.to_string());
+        EngineError::FieldNotFound {
+            field: field.to_string(),
+            suggestion,
+        }
+    }
+
+    /// Machine-readable code reported alongside the message in ERROR responses
+    pub fn code(&self) -> &'static str {
+        match self {
+            EngineError::ResultTooLargeError => "RESULT_TOO_LARGE",
+            EngineError::TypeError { .. } => "TYPE_ERROR",
+            EngineError::FieldNotFound { .. } => "FIELD_NOT_FOUND",
+        }
+    }
+}
+
+/// Errors that aren't an `EngineError` come from query validation
+pub fn error_code(error: &anyhow::Error) -> &'static str {
+    match error.downcast_ref::<EngineError>() {
+        Some(engine_error) => engine_error.code(),
+        None => "INVALID_QUERY",
+    }
+}
+
+impl Display for EngineError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EngineError::ResultTooLargeError => write!(f, "Result too large"),
+            EngineError::TypeError { op, column } => {
+                write!(f, r#"Operation "{}" is not valid for {}"#, op, column)
+            }
+            EngineError::FieldNotFound {
+                field,
+                suggestion: Some(suggestion),
+            } => write!(
+                f,
+                "Field {} does not exist; did you mean {}?",
+                field, suggestion
+            ),
+            EngineError::FieldNotFound { field, .. } => {
+                write!(f, "Field {} does not exist", field)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+#[cfg(test)]
+#[path = "errors_test.rs"]
+mod errors_test;
@@ -0,0 +1,28 @@
+use crate::errors::{EngineError, edit_distance};
+
+#[test]
+fn test_edit_distance() {
+    assert_eq!(edit_distance("sections_avg", "sections_avg"), 0);
+    assert_eq!(edit_distance("sections_averag", "sections_avg"), 3);
+    assert_eq!(edit_distance("rooms_seat", "rooms_seats"), 1);
+    assert_eq!(edit_distance("", "abc"), 3);
+}
+
+#[test]
+fn test_suggestion_threshold() {
+    let known = ["sections_avg", "sections_dept"];
+    assert_eq!(
+        EngineError::field_not_found("sections_dpet", known),
+        EngineError::FieldNotFound {
+            field: "sections_dpet".to_string(),
+            suggestion: Some("sections_dept".to_string())
+        }
+    );
+    assert_eq!(
+        EngineError::field_not_found("rooms_seats", known),
+        EngineError::FieldNotFound {
+            field: "rooms_seats".to_string(),
+            suggestion: None
+        }
+    );
+}
@@ -0,0 +1,106 @@
+use crate::index::Indexed;
+use crate::query::{Filter, Query, column_means, parse_filter, validate_query};
+use crate::types::Dataset;
+use serde::Serialize;
+
+// Rows the filter is run over to estimate selectivity
+const SAMPLE_SIZE: usize = 1000;
+
+/// The shape of a filter tree
+#[derive(Debug, Serialize)]
+pub struct FilterPlan {
+    pub op: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<FilterPlan>,
+}
+
+/// How a query would be executed, without running it
+#[derive(Debug, Serialize)]
+pub struct Plan {
+    pub filter: FilterPlan,
+    pub uses_index: bool,
+    /// Rows the filter would be run over
+    pub scanned_rows: usize,
+    pub total_rows: usize,
+    /// Fraction of a sample of the scanned rows that match, None if the sample errors
+    pub selectivity: Option<f32>,
+    pub transformations: bool,
+    pub order: bool,
+}
+
+fn filter_plan(filter: &Filter) -> FilterPlan {
+    let (op, column, children) = match filter {
+        Filter::AND { and } => ("AND", None, and.iter().map(filter_plan).collect()),
+        Filter::OR { or } => ("OR", None, or.iter().map(filter_plan).collect()),
+        Filter::NOT { not } => ("NOT", None, not.filters().iter().map(filter_plan).collect()),
+        Filter::LT { lt } => ("LT", Some(lt.key.clone()), vec![]),
+        Filter::GT { gt } => ("GT", Some(gt.key.clone()), vec![]),
+        Filter::EQ { eq, .. } => ("EQ", Some(eq.key.clone()), vec![]),
+        Filter::RANGE { range } => ("RANGE", Some(range.key.clone()), vec![]),
+        Filter::EQV { eqv } => ("EQV", Some(eqv.key.clone()), vec![]),
+        Filter::IS { is, .. } => ("IS", Some(is.key.clone()), vec![]),
+        Filter::STARTSWITH { startswith } => ("STARTSWITH", Some(startswith.key.clone()), vec![]),
+        Filter::ENDSWITH { endswith } => ("ENDSWITH", Some(endswith.key.clone()), vec![]),
+        Filter::CONTAINS { contains } => ("CONTAINS", Some(contains.key.clone()), vec![]),
+        Filter::IN { r#in } => ("IN", Some(r#in.key.clone()), vec![]),
+        Filter::NIN { nin } => ("NIN", Some(nin.key.clone()), vec![]),
+        Filter::INNUM { innum } => ("INNUM", Some(innum.key.clone()), vec![]),
+        Filter::FURNITUREHAS { furniturehas } => {
+            ("FURNITUREHAS", Some(furniturehas.key.clone()), vec![])
+        }
+        Filter::NEAR { .. } => ("NEAR", None, vec![]),
+        Filter::CMP { cmp } => ("CMP", Some(cmp.left.clone()), vec![]),
+        Filter::ABOVEAVG { aboveavg } => ("ABOVEAVG", Some(aboveavg.clone()), vec![]),
+        Filter::BELOWAVG { belowavg } => ("BELOWAVG", Some(belowavg.clone()), vec![]),
+        Filter::ISINT { isint } => ("ISINT", Some(isint.clone()), vec![]),
+        Filter::HAS { has } => ("HAS", Some(has.clone()), vec![]),
+        Filter::SEARCH { .. } => ("SEARCH", None, vec![]),
+        Filter::EMPTY {} => ("EMPTY", None, vec![]),
+    };
+    FilterPlan {
+        op,
+        column,
+        children,
+    }
+}
+
+/// Describes how execute_indexed_query would run the query, sampling rows instead of scanning them all
+pub fn explain_query<D: Dataset>(query: &Query, dataset: &Indexed<D>) -> anyhow::Result<Plan> {
+    validate_query(query)?;
+
+    let candidates = dataset.candidates(query.filter());
+    let scanned: Vec<&D> = match &candidates {
+        Some(rows) => rows.iter().map(|i| &dataset.rows[*i]).collect(),
+        None => dataset.rows.iter().collect(),
+    };
+
+    let means = column_means(query.filter(), scanned.iter().copied())?;
+    let filter = parse_filter(query.filter(), &means);
+    let sample = &scanned[..scanned.len().min(SAMPLE_SIZE)];
+    let selectivity = sample
+        .iter()
+        .map(|row| filter(*row))
+        .collect::<anyhow::Result<Vec<bool>>>()
+        .ok()
+        .filter(|_| !sample.is_empty())
+        .map(|matches| {
+            let matched = matches.iter().filter(|m| **m).count();
+            matched as f32 / sample.len() as f32
+        });
+
+    Ok(Plan {
+        filter: filter_plan(query.filter()),
+        uses_index: candidates.is_some(),
+        scanned_rows: scanned.len(),
+        total_rows: dataset.rows.len(),
+        selectivity,
+        transformations: query.transformations.is_some(),
+        order: query.options.order.is_some(),
+    })
+}
+
+#[cfg(test)]
+#[path = "explain_test.rs"]
+mod explain_test;
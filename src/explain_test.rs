@@ -0,0 +1,37 @@
+use crate::explain::explain_query;
+use crate::index::Indexed;
+use crate::testing::{section, where_query};
+
+#[test]
+fn test_explain() {
+    let dataset = Indexed::new(vec![
+        section("1", "cpsc", "310", 90.0),
+        section("2", "cpsc", "310", 70.0),
+        section("3", "math", "310", 90.0),
+        section("4", "phys", "310", 60.0),
+    ]);
+
+    let plan = explain_query(
+        &where_query(r#"{"IS": {"sections_dept": "cpsc"}}"#),
+        &dataset,
+    )
+    .unwrap();
+    assert_eq!(plan.filter.op, "IS");
+    assert_eq!(plan.filter.column.as_deref(), Some("sections_dept"));
+    assert!(plan.uses_index);
+    assert_eq!((plan.scanned_rows, plan.total_rows), (2, 4));
+    assert_eq!(plan.selectivity, Some(1.0));
+
+    let filter =
+        r#"{"AND": [{"GT": {"sections_avg": 80}}, {"NOT": {"IS": {"sections_dept": "math"}}}]}"#;
+    let plan = explain_query(&where_query(filter), &dataset).unwrap();
+    assert_eq!(plan.filter.op, "AND");
+    assert_eq!(plan.filter.children[1].children[0].op, "IS");
+    assert!(!plan.uses_index);
+    assert_eq!(plan.scanned_rows, 4);
+    assert_eq!(plan.selectivity, Some(0.25));
+    assert!(!plan.transformations);
+
+    let plan = explain_query(&where_query(r#"{"GT": {"sections_dept": 80}}"#), &dataset).unwrap();
+    assert_eq!(plan.selectivity, None);
+}
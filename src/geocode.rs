@@ -0,0 +1,132 @@
+/// Looks up the coordinates of a street address
+pub trait Geocoder {
+    fn geocode(&self, address: &str) -> anyhow::Result<(f32, f32)>;
+}
+
+/// Leaves every room at 0, 0, for offline builds
+pub struct NoGeocoder;
+
+impl Geocoder for NoGeocoder {
+    fn geocode(&self, _address: &str) -> anyhow::Result<(f32, f32)> {
+        Ok((0.0, 0.0))
+    }
+}
+
+/// Queries a geolocation service answering `GET <base_url><address>` with `{"lat": ..., "lon": ...}`
+#[cfg(feature = "geocode")]
+pub struct HttpGeocoder {
+    pub base_url: String,
+}
+
+/// How long loading waits on an unresponsive geocoder before leaving the room at 0, 0
+#[cfg(feature = "geocode")]
+const GEOCODER_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Joins a `Transfer-Encoding: chunked` body back together
+#[cfg(feature = "geocode")]
+fn decode_chunked(mut body: &str) -> anyhow::Result<String> {
+    use anyhow::anyhow;
+
+    let mut decoded = String::new();
+    loop {
+        let (size, rest) = body
+            .split_once("\r\n")
+            .ok_or_else(|| anyhow!("Malformed chunked geocoder response"))?;
+        // Chunk extensions follow a ';' and are ignored
+        let size = size.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16)
+            .map_err(|_| anyhow!("Malformed chunk size {} in geocoder response", size))?;
+        if size == 0 {
+            return Ok(decoded);
+        }
+        let chunk = rest
+            .get(..size)
+            .ok_or_else(|| anyhow!("Truncated chunk in geocoder response"))?;
+        decoded.push_str(chunk);
+        body = rest[size..].trim_start_matches("\r\n");
+    }
+}
+
+#[cfg(feature = "geocode")]
+impl Geocoder for HttpGeocoder {
+    fn geocode(&self, address: &str) -> anyhow::Result<(f32, f32)> {
+        use anyhow::{Context, anyhow};
+        use std::io::{Read, Write};
+        use std::net::{TcpStream, ToSocketAddrs};
+
+        #[derive(serde::Deserialize)]
+        struct Location {
+            lat: f32,
+            lon: f32,
+        }
+
+        let url = self
+            .base_url
+            .strip_prefix("http://")
+            .ok_or_else(|| anyhow!("Only http:// geocoder URLs are supported"))?;
+        let (host, prefix) = url.split_once('/').unwrap_or((url, ""));
+        let encoded: String = address
+            .bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                    (b as char).to_string()
+                }
+                _ => format!("%{:02X}", b),
+            })
+            .collect();
+
+        let socket = if host.contains(':') {
+            host.to_socket_addrs()
+        } else {
+            (host, 80).to_socket_addrs()
+        }
+        .with_context(|| format!("Failed to resolve geocoder {}", host))?
+        .next()
+        .ok_or_else(|| anyhow!("Geocoder {} has no address", host))?;
+        let mut stream = TcpStream::connect_timeout(&socket, GEOCODER_TIMEOUT)
+            .with_context(|| format!("Failed to connect to geocoder {}", host))?;
+        stream.set_read_timeout(Some(GEOCODER_TIMEOUT))?;
+        stream.set_write_timeout(Some(GEOCODER_TIMEOUT))?;
+        write!(
+            stream,
+            "GET /{}{} HTTP/1.0\r\nHost: {}\r\n\r\n",
+            prefix, encoded, host
+        )?;
+        let mut response = String::new();
+        stream
+            .read_to_string(&mut response)
+            .with_context(|| format!("Failed to read from geocoder {}", host))?;
+
+        let (head, body) = response
+            .split_once("\r\n\r\n")
+            .ok_or_else(|| anyhow!("Malformed geocoder response"))?;
+        let mut lines = head.lines();
+        let status = lines.next().unwrap_or_default();
+        if status.split_whitespace().nth(1) != Some("200") {
+            return Err(anyhow!("Geocoder answered {} for {}", status, address));
+        }
+        let chunked = lines.any(|line| {
+            line.split_once(':').is_some_and(|(name, value)| {
+                name.trim().eq_ignore_ascii_case("transfer-encoding")
+                    && value.trim().eq_ignore_ascii_case("chunked")
+            })
+        });
+        let body = if chunked {
+            decode_chunked(body)?
+        } else {
+            body.to_string()
+        };
+        let location: Location = serde_json::from_str(&body)
+            .with_context(|| format!("Failed to geocode {}", address))?;
+        Ok((location.lat, location.lon))
+    }
+}
+
+/// The HTTP geocoder at GEOCODER_URL with the geocode feature, otherwise NoGeocoder
+pub fn default_geocoder() -> Box<dyn Geocoder> {
+    #[cfg(feature = "geocode")]
+    if let Ok(base_url) = std::env::var("GEOCODER_URL") {
+        return Box::new(HttpGeocoder { base_url });
+    }
+    Box::new(NoGeocoder)
+}
@@ -0,0 +1,99 @@
+use crate::dataset::EPSILON;
+use crate::query::Filter;
+use crate::types::{Dataset, KVPair, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
+
+/// A static dataset plus a (column, value) -> row indices index built at load time
+pub struct Indexed<D> {
+    pub rows: Vec<D>,
+    index: HashMap<String, BTreeMap<Value, Vec<usize>>>,
+}
+
+impl<D: Dataset> Indexed<D> {
+    pub fn new(rows: Vec<D>) -> Self {
+        let mut index: HashMap<String, BTreeMap<Value, Vec<usize>>> = HashMap::new();
+        for (i, row) in rows.iter().enumerate() {
+            for column in row.columns() {
+                if let Ok(value) = row.get(&column) {
+                    index
+                        .entry(column)
+                        .or_default()
+                        .entry(value)
+                        .or_default()
+                        .push(i);
+                }
+            }
+        }
+        Indexed { rows, index }
+    }
+
+    /// The first row whose column equals value, looked up through the index
+    pub fn find(&self, column: &str, value: &Value) -> Option<&D> {
+        let rows = self.index.get(column)?.get(value)?;
+        rows.first().map(|&i| &self.rows[i])
+    }
+
+    // Columns hold a single type besides nulls, which sort first, so the first other key tells
+    // which filters the index can serve
+    fn column(&self, column: &str, string: bool) -> Option<&BTreeMap<Value, Vec<usize>>> {
+        let values = self.index.get(column)?;
+        match values.keys().find(|value| **value != Value::Null)? {
+            Value::Str(_) if string => Some(values),
+            Value::Num(_) if !string => Some(values),
+            _ => None,
+        }
+    }
+
+    /// Sorted indices of the rows that can match a top-level IS, EQ or RANGE, or None if every row must be scanned.
+    /// Candidates are a superset, the filter itself still runs over them.
+    pub fn candidates(&self, filter: &Filter) -> Option<Vec<usize>> {
+        match filter {
+            Filter::IS {
+                is: KVPair { key, value },
+                mode,
+                ..
+            } if mode.is_literal(value) => Some(
+                self.column(key, true)?
+                    .get(&Value::Str(value.clone()))
+                    .cloned()
+                    .unwrap_or_default(),
+            ),
+            // A negative tolerance would invert the range, so it is left to validation too
+            Filter::EQ {
+                eq: KVPair { key, value },
+                tol,
+            } if tol.is_none_or(|tol| tol.0 >= 0.0) => {
+                let tolerance = tol.map_or(EPSILON, |tol| tol.0);
+                let range = Value::Num(value - tolerance)..=Value::Num(value + tolerance);
+                let mut rows: Vec<usize> = self
+                    .column(key, false)?
+                    .range(range)
+                    .flat_map(|(_, rows)| rows.iter().copied())
+                    .collect();
+                rows.sort();
+                Some(rows)
+            }
+            // Inverted bounds would make BTreeMap::range panic, so they are left to validation
+            Filter::RANGE {
+                range: KVPair { key, value },
+            } if value.min.zip(value.max).is_none_or(|(min, max)| min <= max) => {
+                let bound = |bound: Option<_>| {
+                    bound.map_or(Bound::Unbounded, |b| Bound::Included(Value::Num(b)))
+                };
+                let mut rows: Vec<usize> = self
+                    .column(key, false)?
+                    .range((bound(value.min), bound(value.max)))
+                    .flat_map(|(_, rows)| rows.iter().copied())
+                    .collect();
+                rows.sort();
+                Some(rows)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "index_test.rs"]
+mod index_test;
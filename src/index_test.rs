@@ -0,0 +1,105 @@
+use crate::dataset::load_dataset;
+use crate::index::Indexed;
+use crate::query::{execute_indexed_query, execute_paged_query, execute_query};
+use crate::testing::{section, where_query};
+use std::collections::BTreeSet;
+
+#[test]
+fn test_candidates() {
+    let dataset = Indexed::new(vec![
+        section("1", "cpsc", "110", 80.0),
+        section("2", "math", "110", 85.00005),
+        section("3", "cpsc", "110", 85.0),
+        section("4", "math", "110", 90.0),
+    ]);
+    let candidates = |filter: &str| dataset.candidates(where_query(filter).filter());
+    assert_eq!(
+        candidates(r#"{"IS": {"sections_dept": "cpsc"}}"#),
+        Some(vec![0, 2])
+    );
+    assert_eq!(
+        candidates(r#"{"IS": {"sections_dept": "phys"}}"#),
+        Some(vec![])
+    );
+    assert_eq!(
+        candidates(r#"{"EQ": {"sections_avg": 85}}"#),
+        Some(vec![1, 2])
+    );
+    assert_eq!(
+        candidates(r#"{"EQ": {"sections_avg": 85}, "tol": 10}"#),
+        Some(vec![0, 1, 2, 3])
+    );
+    assert_eq!(
+        candidates(r#"{"RANGE": {"sections_avg": {"min": 85}}}"#),
+        Some(vec![1, 2, 3])
+    );
+    assert_eq!(
+        candidates(r#"{"RANGE": {"sections_avg": {"min": 90, "max": 80}}}"#),
+        None
+    );
+    assert_eq!(
+        candidates(r#"{"EQ": {"sections_avg": 85}, "tol": -1}"#),
+        None
+    );
+    // Patterns, mistyped columns and compound filters are left to the scan
+    assert_eq!(candidates(r#"{"IS": {"sections_dept": "cp.*"}}"#), None);
+    assert_eq!(candidates(r#"{"IS": {"sections_avg": "cpsc"}}"#), None);
+    assert_eq!(candidates(r#"{"EQ": {"sections_dept": 85}}"#), None);
+    assert_eq!(
+        candidates(r#"{"NOT": {"IS": {"sections_dept": "cpsc"}}}"#),
+        None
+    );
+}
+
+#[test]
+fn test_invalid_filters_error() {
+    let dataset = Indexed::new(vec![
+        section("1", "cpsc", "110", 80.0),
+        section("2", "math", "110", 90.0),
+    ]);
+    for (filter, message) in [
+        (
+            r#"{"RANGE": {"sections_avg": {"min": 90, "max": 80}}}"#,
+            "RANGE min 90 is greater than its max 80",
+        ),
+        (
+            r#"{"EQ": {"sections_avg": 85}, "tol": -1}"#,
+            "EQ tolerance must be non-negative, got -1",
+        ),
+    ] {
+        let error = execute_paged_query(&where_query(filter), &dataset).unwrap_err();
+        assert_eq!(error.to_string(), message);
+    }
+}
+
+#[test]
+fn test_indexed_matches_scan() {
+    let rows = load_dataset("pair.zip").unwrap();
+    let indexed = Indexed::new(rows.clone());
+
+    for filter in [
+        r#"{"IS": {"sections_dept": "cpsc"}}"#,
+        r#"{"EQ": {"sections_avg": 90}}"#,
+        r#"{"IS": {"sections_avg": "90"}}"#,
+    ] {
+        let query = where_query(filter);
+        let scanned = execute_query(&query, &rows);
+        let candidates = indexed.candidates(query.filter());
+
+        match (scanned, execute_indexed_query(&query, &indexed)) {
+            (Ok(scanned), Ok(results)) => {
+                assert!(!results.is_empty());
+                assert_eq!(
+                    scanned.into_iter().collect::<BTreeSet<_>>(),
+                    results.into_iter().collect::<BTreeSet<_>>()
+                );
+                // The index narrows the rows rather than scanning them all
+                assert!(candidates.unwrap().len() < rows.len());
+            }
+            (Err(scanned), Err(indexed)) => {
+                assert_eq!(scanned.to_string(), indexed.to_string())
+            }
+            _ => panic!("indexed and scanned results differ for {}", filter),
+        }
+    }
+}
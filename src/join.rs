@@ -0,0 +1,63 @@
+use crate::errors::EngineError;
+use crate::types::{Dataset, Value, full_row};
+use anyhow::anyhow;
+use std::collections::{BTreeMap, HashMap};
+
+fn check_column<D: Dataset>(dataset: &[D], column: &str) -> anyhow::Result<()> {
+    match dataset.first() {
+        Some(row) if !row.columns().iter().any(|c| c == column) => Err(
+            EngineError::field_not_found(column, row.columns().iter().map(String::as_str)).into(),
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Inner join of two datasets on left_column == right_column, in left then right order.
+/// Columns keep their dataset prefix so both sides fit in one row. Null keys match nothing,
+/// and a join of more than `max_rows` rows is an error rather than built in memory.
+pub fn join<L: Dataset, R: Dataset>(
+    left: &[L],
+    right: &[R],
+    left_column: &str,
+    right_column: &str,
+    max_rows: usize,
+) -> anyhow::Result<Vec<BTreeMap<String, Value>>> {
+    check_column(left, left_column)?;
+    check_column(right, right_column)?;
+
+    let mut by_key: HashMap<Value, Vec<&R>> = HashMap::new();
+    for item in right {
+        let key = item.get(right_column).map_err(|e| anyhow!(e))?;
+        if key == Value::Null {
+            continue;
+        }
+        by_key.entry(key).or_default().push(item);
+    }
+
+    let mut result = Vec::new();
+    for item in left {
+        let key = item.get(left_column).map_err(|e| anyhow!(e))?;
+        let matches = by_key.get(&key).map_or(0, Vec::len);
+        if result.len() + matches > max_rows {
+            return Err(anyhow!("Join has more than {} rows", max_rows));
+        }
+        for other in by_key.get(&key).into_iter().flatten() {
+            let mut combined = full_row(item)?;
+            for (column, value) in full_row(*other)? {
+                if combined.contains_key(&column) {
+                    return Err(anyhow!(
+                        "Column {} appears on both sides of the join",
+                        column
+                    ));
+                }
+                combined.insert(column, value);
+            }
+            result.push(combined);
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+#[path = "join_test.rs"]
+mod join_test;
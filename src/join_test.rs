@@ -0,0 +1,76 @@
+use crate::errors::EngineError;
+use crate::join::join;
+use crate::rooms_dataset::Room;
+use crate::testing::section;
+use crate::types::Value;
+use ordered_float::OrderedFloat;
+
+fn room(shortname: &str, number: &str) -> Room {
+    Room {
+        fullname: format!("{} building", shortname),
+        shortname: shortname.to_string(),
+        number: number.to_string(),
+        name: format!("{}_{}", shortname, number),
+        address: None,
+        seats: OrderedFloat::from(100f32),
+        r#type: "Tiered Large Group".to_string(),
+        furniture: "".to_string(),
+        href: "".to_string(),
+        lat: OrderedFloat::from(0f32),
+        lon: OrderedFloat::from(0f32),
+    }
+}
+
+#[test]
+fn test_join() {
+    let sections = vec![
+        section("1", "DMP", "110", 80.0),
+        section("2", "ANGU", "110", 80.0),
+        section("3", "MATH", "110", 80.0),
+    ];
+    let rooms = vec![room("DMP", "110"), room("ANGU", "098"), room("DMP", "310")];
+
+    let result = join(&sections, &rooms, "sections_dept", "rooms_shortname", 10).unwrap();
+    let pairs: Vec<(Value, Value)> = result
+        .iter()
+        .map(|row| (row["sections_uuid"].clone(), row["rooms_name"].clone()))
+        .collect();
+    assert_eq!(
+        pairs,
+        vec![
+            (Value::Str("1".into()), Value::Str("DMP_110".into())),
+            (Value::Str("1".into()), Value::Str("DMP_310".into())),
+            (Value::Str("2".into()), Value::Str("ANGU_098".into())),
+        ]
+    );
+    assert_eq!(result[0].len(), 10 + 11);
+
+    let error = join(&sections, &rooms, "sections_dept", "rooms_shortname", 2).unwrap_err();
+    assert_eq!(error.to_string(), "Join has more than 2 rows");
+}
+
+#[test]
+fn test_join_null_keys() {
+    let sections = vec![section("1", "DMP", "110", 80.0)];
+    let rooms = vec![room("DMP", "110")];
+
+    // The fixture rooms have no address, which is a column holding Null rather than a missing one
+    let result = join(&sections, &rooms, "sections_title", "rooms_address", 10).unwrap();
+    assert!(result.is_empty());
+}
+
+#[test]
+fn test_join_missing_column() {
+    let sections = vec![section("1", "DMP", "110", 80.0)];
+    let rooms = vec![room("DMP", "110")];
+
+    let error = join(&sections, &rooms, "sections_dept", "rooms_shortnam", 10).unwrap_err();
+    assert_eq!(
+        error.downcast_ref::<EngineError>(),
+        Some(&EngineError::FieldNotFound {
+            field: "rooms_shortnam".to_string(),
+            suggestion: Some("rooms_shortname".to_string()),
+        })
+    );
+    assert!(join(&sections, &rooms, "sections_depts", "rooms_shortname", 10).is_err());
+}
@@ -1,98 +1,808 @@
-use crate::dataset::{Section, load_dataset};
-use crate::query::{Query, execute_query};
-use crate::rooms_dataset::{Room, load_rooms_dataset};
-use crate::types::QueryResult;
-use axum::http::StatusCode;
-use axum::routing::get;
+use crate::cache::{QueryCache, canonicalize};
+use crate::config::{Config, DatasetSpec, parse_config};
+use crate::dataset::{load_any, validate_dataset};
+use crate::errors::{EngineError, error_code};
+use crate::explain::explain_query;
+use crate::query::{
+    Format, Options, PagedResult, check_bounded, check_denied, check_group_columns,
+    execute_indexed_query, execute_paged_query, parse_query, rename_keys, resolve_case, to_table,
+    validate_query,
+};
+use crate::rate_limit::RateLimiter;
+use crate::registry::{Loaders, Registry};
+use crate::types::{
+    ColumnSchema, DatasetInfo, Page, QueryResult, ValidationResult, export_rows, full_row, schema,
+};
+use arc_swap::ArcSwap;
+use axum::extract::{ConnectInfo, Path, Request, State};
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
 use axum::{Json, Router};
 use prompted::input;
-use std::sync::LazyLock;
-use tower_http::cors::CorsLayer;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, OnceLock};
+use std::time::Instant;
+use tower_http::compression::CompressionLayer;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tower_http::timeout::TimeoutLayer;
+use tower_http::trace::TraceLayer;
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::EnvFilter;
 
+mod cache;
+#[cfg(feature = "parquet")]
+mod columnar;
+mod config;
 mod dataset;
-mod dataset_test;
+mod errors;
+mod explain;
+mod geocode;
+mod index;
+mod join;
+mod metrics;
 mod query;
+mod rate_limit;
+mod registry;
 mod rooms_dataset;
+#[cfg(test)]
 mod testing;
 mod types;
 
-enum DS {
-    SECTION,
-    ROOM,
+// Set once from the command line in main; tests fall back to the defaults
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+fn config() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
 }
 
-static SECTIONS: LazyLock<Vec<Section>, fn() -> Vec<Section>> =
-    LazyLock::new(|| load_dataset("pair.zip").unwrap());
+// Loaded concurrently in main before serving; tests fall back to loading on first use
+static PRELOADED: OnceLock<Arc<Registry>> = OnceLock::new();
+
+// Swapped whole on reload, while queries already running keep the snapshot they started with
+static REGISTRY: LazyLock<ArcSwap<Registry>> = LazyLock::new(|| {
+    ArcSwap::new(PRELOADED.get().cloned().unwrap_or_else(|| {
+        Arc::new(Registry::load(&config().registrations(), &Loaders::default()).unwrap())
+    }))
+});
+
+const QUERY_CACHE_CAPACITY: usize = 128;
+
+static QUERY_CACHE: LazyLock<Mutex<QueryCache<PagedResult>>> =
+    LazyLock::new(|| Mutex::new(QueryCache::new(QUERY_CACHE_CAPACITY)));
 
-static ROOMS: LazyLock<Vec<Room>, fn() -> Vec<Room>> =
-    LazyLock::new(|| load_rooms_dataset("campus.zip").unwrap());
+fn ok_result(
+    options: &Options,
+    result: Vec<BTreeMap<String, types::Value>>,
+    page: Option<Page>,
+    started: Instant,
+    prefix: &str,
+) -> QueryResult {
+    let elapsed_ms = started.elapsed().as_secs_f64() * 1000.0;
+    let rename = |key: &str| options.output_key(prefix, key);
+    match options.format {
+        Format::OBJECT => QueryResult::OK {
+            result: if options.renames_keys() {
+                rename_keys(result, rename)
+            } else {
+                result
+            },
+            page,
+            elapsed_ms,
+        },
+        Format::TABLE => {
+            let (mut columns, rows) = to_table(options, result);
+            if options.renames_keys() {
+                columns = columns.iter().map(|column| rename(column)).collect();
+            }
+            QueryResult::TABLE {
+                columns,
+                rows,
+                page,
+                elapsed_ms,
+            }
+        }
+    }
+}
 
-const PORT: i32 = 310;
+type QueryParams = axum::extract::Query<std::collections::HashMap<String, String>>;
 
-async fn query_courses(
-    dataset: DS,
-    axum::extract::Query(params): axum::extract::Query<std::collections::HashMap<String, String>>,
+async fn query_dataset(
+    id: String,
+    axum::extract::Query(params): QueryParams,
 ) -> Result<Json<QueryResult>, StatusCode> {
+    let dataset = REGISTRY.load().get(&id).ok_or(StatusCode::NOT_FOUND)?;
     let json = params.get("q").ok_or(StatusCode::BAD_REQUEST)?;
-    println!("Received query from URL param: {}", json);
-
-    match serde_json::from_str::<Query>(&json) {
-        Ok(query) => {
-            let result = match dataset {
-                DS::SECTION => execute_query(&query, &SECTIONS),
-                DS::ROOM => execute_query(&query, &ROOMS),
-            };
-            println!("{:#?}", result);
-            let query_result = match result {
-                Ok(ok) => QueryResult::OK { result: ok },
+    debug!(dataset = %id, query = %json, "Received query");
+    metrics::record_query();
+
+    if params
+        .get("explain")
+        .is_some_and(|explain| explain == "true")
+    {
+        // Resolved like execute_paged_query, so mixed-case filters still reach the index
+        let columns = dataset.columns();
+        return Ok(Json(match parse_query(json) {
+            Ok(query) => match check_denied(&query, config().denied_columns(&id))
+                .and_then(|_| explain_query(&resolve_case(&query, &columns), &dataset.rows))
+            {
+                Ok(plan) => QueryResult::PLAN { plan },
                 Err(error) => QueryResult::ERROR {
                     error: error.to_string(),
+                    code: error_code(&error),
                 },
-            };
-            Ok(Json(query_result))
-        }
+            },
+            Err(e) => QueryResult::ERROR {
+                error: e.to_string(),
+                code: "PARSE_ERROR",
+            },
+        }));
+    }
+
+    let started = Instant::now();
+    let query = match parse_query(json) {
+        Ok(query) => Arc::new(query),
         Err(e) => {
-            eprintln!("{}", e);
-            Ok(Json(QueryResult::ERROR {
+            warn!(dataset = %id, query = %json, error = %e, "Unparseable query");
+            metrics::record_error("PARSE_ERROR");
+            return Ok(Json(QueryResult::ERROR {
                 error: e.to_string(),
-            }))
+                code: "PARSE_ERROR",
+            }));
+        }
+    };
+
+    if config().require_limit
+        && let Err(error) = check_bounded(&query)
+    {
+        metrics::record_error("INVALID_QUERY");
+        return Ok(Json(QueryResult::ERROR {
+            error: error.to_string(),
+            code: "INVALID_QUERY",
+        }));
+    }
+    if let Err(error) = check_denied(&query, config().denied_columns(&id)) {
+        metrics::record_error("INVALID_QUERY");
+        return Ok(Json(QueryResult::ERROR {
+            error: error.to_string(),
+            code: "INVALID_QUERY",
+        }));
+    }
+
+    // `nocache` skips the lookup but still refreshes the entry
+    let cache_key = canonicalize(json).map(|json| format!("{}:{}", id, json));
+    if !params.contains_key("nocache")
+        && let Some(key) = &cache_key
+        && let Some((result, page)) = QUERY_CACHE.lock().unwrap().get(key)
+    {
+        info!(
+            dataset = %id,
+            query = %json,
+            rows = result.len(),
+            elapsed_ms = started.elapsed().as_secs_f64() * 1000.0,
+            cached = true,
+            "Query answered"
+        );
+        return Ok(Json(ok_result(
+            &query.options,
+            result,
+            page,
+            started,
+            dataset.prefix(),
+        )));
+    }
+
+    // Run off the async workers so the timeout layer can abandon a slow query
+    let prefix = dataset.prefix();
+    let running = query.clone();
+    let result = tokio::task::spawn_blocking(move || execute_paged_query(&running, &dataset.rows))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let query_result = match result {
+        Ok((result, page)) => {
+            info!(
+                dataset = %id,
+                query = %json,
+                rows = result.len(),
+                elapsed_ms = started.elapsed().as_secs_f64() * 1000.0,
+                cached = false,
+                "Query answered"
+            );
+            if let Some(key) = cache_key {
+                QUERY_CACHE
+                    .lock()
+                    .unwrap()
+                    .insert(key, (result.clone(), page.clone()));
+            }
+            ok_result(&query.options, result, page, started, prefix)
+        }
+        Err(error) => {
+            let code = error_code(&error);
+            warn!(dataset = %id, query = %json, code, error = %error, "Query failed");
+            metrics::record_error(code);
+            QueryResult::ERROR {
+                error: error.to_string(),
+                code,
+            }
         }
+    };
+    Ok(Json(query_result))
+}
+
+/// `format=parquet` answers a successful query with its rows as a Parquet file instead of JSON
+async fn query_response(id: String, params: QueryParams) -> Result<Response, StatusCode> {
+    let parquet = params
+        .get("format")
+        .is_some_and(|format| format == "parquet");
+    let Json(result) = query_dataset(id, params).await?;
+    match result {
+        QueryResult::OK { result, .. } if parquet => parquet_response(&result),
+        result => Ok(Json(result).into_response()),
     }
 }
 
-fn console_ui() -> ! {
-    loop {
-        println!(r#"Type "section" or "room""#);
-        let which = input!();
-        let json = std::fs::read_to_string("test.json").unwrap();
-        match serde_json::from_str::<Query>(&json) {
-            Ok(query) => {
-                let result = match which.to_ascii_lowercase().as_str() {
-                    "section" => execute_query(&query, &SECTIONS),
-                    "room" => execute_query(&query, &ROOMS),
-                    _ => continue,
+#[cfg(feature = "parquet")]
+fn parquet_response(rows: &[BTreeMap<String, types::Value>]) -> Result<Response, StatusCode> {
+    let bytes = columnar::to_parquet(rows).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok((
+        [(header::CONTENT_TYPE, "application/vnd.apache.parquet")],
+        bytes,
+    )
+        .into_response())
+}
+
+#[cfg(not(feature = "parquet"))]
+fn parquet_response(_rows: &[BTreeMap<String, types::Value>]) -> Result<Response, StatusCode> {
+    Err(StatusCode::NOT_IMPLEMENTED)
+}
+
+/// Runs one query against every dataset, leaving out those missing a column it names.
+/// Other errors are reported for their dataset alone.
+async fn search(body: String) -> Result<Json<BTreeMap<String, QueryResult>>, (StatusCode, String)> {
+    metrics::record_query();
+    let query = parse_query(&body).map_err(|e| {
+        metrics::record_error("PARSE_ERROR");
+        (StatusCode::BAD_REQUEST, e.to_string())
+    })?;
+    if config().require_limit {
+        check_bounded(&query).map_err(|e| {
+            metrics::record_error("INVALID_QUERY");
+            (StatusCode::BAD_REQUEST, e.to_string())
+        })?;
+    }
+
+    let registry = REGISTRY.load_full();
+    let results = tokio::task::spawn_blocking(move || {
+        registry
+            .ids()
+            .into_iter()
+            .filter_map(|id| {
+                let dataset = registry.get(id)?;
+                let started = Instant::now();
+                let result = match check_denied(&query, config().denied_columns(id))
+                    .and_then(|_| execute_paged_query(&query, &dataset.rows))
+                {
+                    Ok((result, page)) => {
+                        ok_result(&query.options, result, page, started, dataset.prefix())
+                    }
+                    Err(error) => match error.downcast_ref::<EngineError>() {
+                        Some(EngineError::FieldNotFound { .. }) => return None,
+                        _ => QueryResult::ERROR {
+                            error: error.to_string(),
+                            code: error_code(&error),
+                        },
+                    },
                 };
-                println!("{:#?}", result);
+                Some((id.to_string(), result))
+            })
+            .collect()
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(results))
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BatchRequest {
+    queries: Vec<BatchQuery>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BatchQuery {
+    dataset: String,
+    query: serde_json::Value,
+}
+
+fn error_result(error: &anyhow::Error, code: &'static str) -> QueryResult {
+    metrics::record_error(code);
+    QueryResult::ERROR {
+        error: error.to_string(),
+        code,
+    }
+}
+
+/// Runs one query of a batch like a GET of its dataset, minus the cache
+fn run_batched(item: BatchQuery) -> QueryResult {
+    metrics::record_query();
+    let Some(dataset) = REGISTRY.load().get(&item.dataset) else {
+        let error = anyhow::anyhow!("Unknown dataset {}", item.dataset);
+        return error_result(&error, "DATASET_NOT_FOUND");
+    };
+    let query = match parse_query(&item.query.to_string()) {
+        Ok(query) => query,
+        Err(error) => return error_result(&error, "PARSE_ERROR"),
+    };
+    if config().require_limit
+        && let Err(error) = check_bounded(&query)
+    {
+        return error_result(&error, "INVALID_QUERY");
+    }
+    if let Err(error) = check_denied(&query, config().denied_columns(&item.dataset)) {
+        return error_result(&error, "INVALID_QUERY");
+    }
+
+    let started = Instant::now();
+    match execute_paged_query(&query, &dataset.rows) {
+        Ok((result, page)) => ok_result(&query.options, result, page, started, dataset.prefix()),
+        Err(error) => error_result(&error, error_code(&error)),
+    }
+}
+
+// Above this a batch is refused, so one request can't take over the blocking thread pool
+const BATCH_MAX_QUERIES: usize = 32;
+
+/// Answers each query in order, one failing query only failing its own entry
+async fn batch(Json(request): Json<BatchRequest>) -> Result<Json<Vec<QueryResult>>, StatusCode> {
+    if request.queries.len() > BATCH_MAX_QUERIES {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    // Every query gets its own blocking thread, so they run concurrently
+    let handles: Vec<_> = request
+        .queries
+        .into_iter()
+        .map(|item| tokio::task::spawn_blocking(move || run_batched(item)))
+        .collect();
+    let mut results = Vec::with_capacity(handles.len());
+    for handle in handles {
+        results.push(
+            handle
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        );
+    }
+    Ok(Json(results))
+}
+
+fn dataset_infos(registry: &Registry) -> Vec<DatasetInfo> {
+    registry
+        .ids()
+        .into_iter()
+        .filter_map(|id| {
+            let dataset = registry.get(id)?;
+            Some(DatasetInfo {
+                id: id.to_string(),
+                kind: dataset.kind.clone(),
+                rows: dataset.rows.rows.len(),
+            })
+        })
+        .collect()
+}
+
+async fn list_datasets() -> Json<Vec<DatasetInfo>> {
+    Json(dataset_infos(&REGISTRY.load()))
+}
+
+/// Loads the datasets again and swaps them in, keeping the old ones if any fails to load
+fn reload_registry(registry: &ArcSwap<Registry>, specs: &[DatasetSpec]) -> anyhow::Result<()> {
+    registry.store(Registry::load(specs, &Loaders::default())?.into());
+    Ok(())
+}
+
+async fn reload(headers: HeaderMap) -> Result<Json<Vec<DatasetInfo>>, (StatusCode, String)> {
+    let authorized = config().admin_token.as_ref().is_some_and(|token| {
+        headers
+            .get(header::AUTHORIZATION)
+            .is_some_and(|value| *value == format!("Bearer {}", token))
+    });
+    if !authorized {
+        return Err((StatusCode::UNAUTHORIZED, "Invalid admin token".to_string()));
+    }
+
+    tokio::task::spawn_blocking(|| reload_registry(&REGISTRY, &config().registrations()))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)))?;
+    // Cached results may come from the old data
+    QUERY_CACHE.lock().unwrap().clear();
+    Ok(Json(dataset_infos(&REGISTRY.load())))
+}
+
+/// The name and type of each column of the dataset, less any denied columns
+async fn get_schema(Path(id): Path<String>) -> Result<Json<Vec<ColumnSchema>>, StatusCode> {
+    let dataset = REGISTRY.load().get(&id).ok_or(StatusCode::NOT_FOUND)?;
+    let denied = config().denied_columns(&id);
+    let mut columns = schema(&dataset.rows.rows);
+    columns.retain(|column| !denied.contains(&column.name));
+    Ok(Json(columns))
+}
+
+/// Every column of the section with the given uuid, 404 if there is none
+async fn get_section(
+    Path(uuid): Path<String>,
+) -> Result<Json<BTreeMap<String, types::Value>>, StatusCode> {
+    let dataset = REGISTRY
+        .load()
+        .get("sections")
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let column = format!("{}uuid", dataset.prefix());
+    let row = dataset
+        .rows
+        .find(&column, &types::Value::Str(uuid))
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let denied = config().denied_columns("sections");
+    full_row(row)
+        .map(|mut row| {
+            row.retain(|column, _| !denied.contains(column));
+            Json(row)
+        })
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+// Above this an export is refused rather than built in memory
+const EXPORT_MAX_ROWS: usize = 100_000;
+
+/// Every row of the dataset in full, less any denied columns, as a JSON array a `.json` path can load
+async fn export_dataset(Path(id): Path<String>) -> Result<Response, StatusCode> {
+    let dataset = REGISTRY.load().get(&id).ok_or(StatusCode::NOT_FOUND)?;
+    if dataset.rows.rows.len() > EXPORT_MAX_ROWS {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+    let denied = config().denied_columns(&id);
+    let body = tokio::task::spawn_blocking(move || -> anyhow::Result<Vec<u8>> {
+        let mut rows = export_rows(&dataset.rows.rows)?;
+        for row in &mut rows {
+            row.retain(|column, _| !denied.contains(column));
+        }
+        Ok(serde_json::to_vec(&rows)?)
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(([(header::CONTENT_TYPE, "application/json")], body).into_response())
+}
+
+// Above this a join is refused rather than built in memory
+const JOIN_MAX_ROWS: usize = 100_000;
+
+/// Inner join of two datasets on `?left=<column>&right=<column>`, less any denied columns
+async fn join_datasets(
+    Path((left_id, right_id)): Path<(String, String)>,
+    params: QueryParams,
+) -> Result<Json<Vec<BTreeMap<String, types::Value>>>, (StatusCode, String)> {
+    let registry = REGISTRY.load();
+    let not_found = |id: &str| (StatusCode::NOT_FOUND, format!("Dataset {} not found", id));
+    let left = registry.get(&left_id).ok_or_else(|| not_found(&left_id))?;
+    let right = registry
+        .get(&right_id)
+        .ok_or_else(|| not_found(&right_id))?;
+    let column = |side: &str| {
+        params.get(side).cloned().ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Missing the {} join column", side),
+            )
+        })
+    };
+    let (left_column, right_column) = (column("left")?, column("right")?);
+
+    let mut denied = config().denied_columns(&left_id).clone();
+    denied.extend(config().denied_columns(&right_id).iter().cloned());
+    if let Some(column) = [&left_column, &right_column]
+        .into_iter()
+        .find(|column| denied.contains(*column))
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Column {} is not available", column),
+        ));
+    }
+
+    let rows = tokio::task::spawn_blocking(move || {
+        join::join(
+            &left.rows.rows,
+            &right.rows.rows,
+            &left_column,
+            &right_column,
+            JOIN_MAX_ROWS,
+        )
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    Ok(Json(
+        rows.into_iter()
+            .map(|mut row| {
+                row.retain(|column, _| !denied.contains(column));
+                row
+            })
+            .collect(),
+    ))
+}
+
+/// 200 once every dataset has loaded, 503 if loading failed.
+/// Forces the load on first call, so a probe may wait for it.
+async fn readyz() -> StatusCode {
+    match tokio::task::spawn_blocking(|| LazyLock::force(&REGISTRY).load().ids().len()).await {
+        Ok(_) => StatusCode::OK,
+        // A failed load panics inside the LazyLock, which poisons it for good
+        Err(_) => StatusCode::SERVICE_UNAVAILABLE,
+    }
+}
+
+/// With `?dataset=<id>` the GROUP keys are also checked against that dataset's columns
+async fn validate(
+    axum::extract::Query(params): QueryParams,
+    body: String,
+) -> Result<Json<ValidationResult>, StatusCode> {
+    let columns = match params.get("dataset") {
+        Some(id) => Some(
+            REGISTRY
+                .load()
+                .get(id)
+                .ok_or(StatusCode::NOT_FOUND)?
+                .columns(),
+        ),
+        None => None,
+    };
+    let result = parse_query(&body).and_then(|query| match &columns {
+        Some(columns) => {
+            let query = resolve_case(&query, columns);
+            validate_query(&query).and_then(|_| check_group_columns(&query, columns))
+        }
+        None => validate_query(&query),
+    });
+    Ok(match result {
+        Ok(()) => Json(ValidationResult {
+            valid: true,
+            error: None,
+        }),
+        Err(e) => Json(ValidationResult {
+            valid: false,
+            error: Some(e.to_string()),
+        }),
+    })
+}
+
+const QUERY_TERMINATOR: &str = ";";
+
+/// Joins lines up to EOF or a line holding only the terminator
+fn read_query(lines: impl Iterator<Item = String>) -> String {
+    lines
+        .take_while(|line| line.trim() != QUERY_TERMINATOR)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Set once shutdown starts, so the console stops prompting
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+// A read already blocked on stdin just ends with the process
+fn console_ui() {
+    while !SHUTTING_DOWN.load(Ordering::SeqCst) {
+        println!("Type a dataset id ({})", REGISTRY.load().ids().join(", "));
+        let id = input!().to_ascii_lowercase();
+        let Some(dataset) = REGISTRY.load().get(&id) else {
+            continue;
+        };
+
+        println!(
+            "Enter a query, ending with a line containing only {} (leave empty to use test.json)",
+            QUERY_TERMINATOR
+        );
+        let mut json = read_query(std::io::stdin().lines().map_while(Result::ok));
+        if json.trim().is_empty() {
+            json = std::fs::read_to_string("test.json").unwrap();
+        }
+
+        let started = Instant::now();
+        match parse_query(&json).and_then(|query| execute_indexed_query(&query, &dataset.rows)) {
+            Ok(rows) => {
+                info!(
+                    dataset = %id,
+                    rows = rows.len(),
+                    elapsed_ms = started.elapsed().as_secs_f64() * 1000.0,
+                    "Console query answered"
+                );
+                println!("{:#?}", rows);
+            }
+            Err(e) => warn!(dataset = %id, error = %e, "Console query failed"),
+        }
+    }
+}
+
+fn app() -> Router {
+    router(config())
+}
+
+// Requests without a peer address, e.g. in tests, share one bucket
+async fn rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map_or(Ipv4Addr::UNSPECIFIED.into(), |ConnectInfo(addr)| addr.ip());
+    if !limiter.check(ip, Instant::now()) {
+        return StatusCode::TOO_MANY_REQUESTS.into_response();
+    }
+    next.run(request).await
+}
+
+// Any origin is allowed unless specific origins were configured
+fn cors_layer(origins: &[HeaderValue]) -> CorsLayer {
+    if origins.is_empty() {
+        CorsLayer::new().allow_origin(HeaderValue::from_static("*"))
+    } else {
+        CorsLayer::new().allow_origin(AllowOrigin::list(origins.iter().cloned()))
+    }
+}
+
+fn router(config: &Config) -> Router {
+    let mut router = Router::new()
+        .route("/", get(|| async { "Hello, world!" }))
+        .route("/healthz", get(|| async { StatusCode::OK }))
+        .route("/readyz", get(readyz))
+        .route(
+            "/sections",
+            get(|param| query_response("sections".to_string(), param)),
+        )
+        .route("/sections/{uuid}", get(get_section))
+        .route(
+            "/rooms",
+            get(|param| query_response("rooms".to_string(), param)),
+        )
+        .route(
+            "/query/{id}",
+            get(|Path(id), param| query_response(id, param)),
+        )
+        .route("/datasets", get(list_datasets))
+        .route("/schema/{id}", get(get_schema))
+        .route("/export/{id}", get(export_dataset))
+        .route("/join/{left}/{right}", get(join_datasets))
+        .route("/validate", post(validate))
+        .route("/search", post(search))
+        .route("/batch", post(batch))
+        .route("/metrics", get(|| async { metrics::render() }))
+        .route("/admin/reload", post(reload));
+    if let Some(rate) = config.rate_limit {
+        let limiter = Arc::new(RateLimiter::new(rate));
+        router = router.layer(middleware::from_fn_with_state(limiter, rate_limit));
+    }
+    router
+        .layer(TimeoutLayer::with_status_code(
+            StatusCode::GATEWAY_TIMEOUT,
+            config.timeout,
+        ))
+        .layer(CompressionLayer::new())
+        .layer(cors_layer(&config.cors_origins))
+        .layer(TraceLayer::new_for_http())
+}
+
+/// Ctrl-C, or SIGTERM on Unix
+async fn termination_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to listen for Ctrl-C");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+/// Waits for the signal, then stops the console while axum drains in-flight requests
+async fn shutdown(signal: impl Future<Output = ()>, stopping: &AtomicBool) {
+    signal.await;
+    info!("Shutting down");
+    stopping.store(true, Ordering::SeqCst);
+}
+
+/// Prints the data quality issues of every courses dataset, returning whether there were none
+fn report_issues(config: &Config) -> bool {
+    let mut clean = true;
+    for spec in config
+        .registrations()
+        .iter()
+        .filter(|spec| spec.kind == "courses")
+    {
+        let sections = match load_any(&spec.path) {
+            Ok(sections) => sections,
+            Err(e) => {
+                error!(dataset = %spec.id, error = %e, "Failed to load dataset");
+                clean = false;
+                continue;
             }
-            Err(e) => eprintln!("{}", e),
+        };
+        let issues = validate_dataset(&sections);
+        println!(
+            "{}: {} sections, {} issues",
+            spec.id,
+            sections.len(),
+            issues.len()
+        );
+        for issue in &issues {
+            println!("  {}", issue);
         }
+        clean &= issues.is_empty();
     }
+    clean
 }
 
 #[tokio::main]
 async fn main() {
-    let app = Router::new()
-        .route("/", get(|| async { "Hello, world!" }))
-        .route("/sections", get(|param| query_courses(DS::SECTION, param)))
-        .route("/rooms", get(|param| query_courses(DS::ROOM, param)))
-        .layer(CorsLayer::new().allow_origin("*".parse::<axum::http::HeaderValue>().unwrap()));
+    // RUST_LOG picks the level, e.g. RUST_LOG=debug or RUST_LOG=three_ten_rust=debug
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()))
+        .init();
+    let config = match parse_config(std::env::args().skip(1), |key| std::env::var(key).ok()) {
+        Ok(config) => CONFIG.get_or_init(|| config),
+        Err(e) => {
+            error!(error = %e, "Invalid configuration");
+            std::process::exit(1);
+        }
+    };
+    if config.validate {
+        std::process::exit(if report_issues(config) { 0 } else { 1 });
+    }
 
-    let listener = tokio::net::TcpListener::bind(format!("127.0.0.1:{}", PORT))
+    let started = Instant::now();
+    match Registry::load_concurrently(&config.registrations(), Arc::new(Loaders::default())).await {
+        Ok(registry) => {
+            PRELOADED.get_or_init(|| Arc::new(registry));
+        }
+        Err(e) => {
+            error!("{:#}", e);
+            std::process::exit(1);
+        }
+    }
+    info!(elapsed = ?started.elapsed(), "Datasets ready");
+    let app = app();
+
+    let listener = tokio::net::TcpListener::bind(format!("127.0.0.1:{}", config.port))
         .await
         .unwrap();
 
     std::thread::spawn(console_ui);
 
-    println!("Waiting on port {}", PORT);
-    axum::serve(listener, app).await.unwrap();
+    info!(port = config.port, "Waiting for requests");
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown(termination_signal(), &SHUTTING_DOWN))
+    .await
+    .unwrap();
 }
+
+#[cfg(test)]
+#[path = "main_test.rs"]
+mod main_test;
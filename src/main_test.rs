@@ -0,0 +1,769 @@
+use crate::config::{Config, DatasetSpec};
+use crate::registry::{Loaders, Registry};
+use crate::{QUERY_CACHE, app, read_query, reload_registry, router, shutdown};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use axum::body::Body;
+use axum::http::{Request, StatusCode, header};
+use http_body_util::BodyExt;
+use serde_json::Value;
+use tower::ServiceExt;
+
+fn encode(query: &str) -> String {
+    query
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+async fn get_json(uri: &str) -> Value {
+    let response = app()
+        .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&body).unwrap()
+}
+
+async fn query_code(dataset: &str, query: &str) -> Value {
+    let json = get_json(&format!("/{}?q={}", dataset, encode(query))).await;
+    json["code"].clone()
+}
+
+#[tokio::test]
+async fn test_field_not_found_code() {
+    let query =
+        r#"{"WHERE": {"IS": {"rooms_building": "DMP"}}, "OPTIONS": {"COLUMNS": ["rooms_name"]}}"#;
+    assert_eq!(query_code("rooms", query).await, "FIELD_NOT_FOUND");
+}
+
+#[tokio::test]
+async fn test_type_error_code() {
+    let query = r#"{"WHERE": {"GT": {"rooms_name": 10}}, "OPTIONS": {"COLUMNS": ["rooms_name"]}}"#;
+    assert_eq!(query_code("rooms", query).await, "TYPE_ERROR");
+}
+
+#[tokio::test]
+async fn test_result_too_large_code() {
+    let query = r#"{"WHERE": {}, "OPTIONS": {"COLUMNS": ["sections_uuid"]}}"#;
+    assert_eq!(query_code("sections", query).await, "RESULT_TOO_LARGE");
+}
+
+#[tokio::test]
+async fn test_parse_error_code() {
+    let query = r#"{"WHERE": {}}"#;
+    assert_eq!(query_code("rooms", query).await, "PARSE_ERROR");
+}
+
+#[tokio::test]
+async fn test_key_case_collision_code() {
+    let query = r#"{"WHERE": {}, "OPTIONS": {"COLUMNS": ["rooms_shortname", "max_seats", "maxSeats"], "KEY_CASE": "camel"}, "TRANSFORMATIONS": {"GROUP": ["rooms_shortname"], "APPLY": [{"max_seats": {"MAX": "rooms_seats"}}, {"maxSeats": {"MAX": "rooms_seats"}}]}}"#;
+    assert_eq!(query_code("rooms", query).await, "INVALID_QUERY");
+}
+
+#[tokio::test]
+async fn test_success_has_no_code() {
+    let query =
+        r#"{"WHERE": {"IS": {"rooms_shortname": "DMP"}}, "OPTIONS": {"COLUMNS": ["rooms_name"]}}"#;
+    let json = get_json(&format!("/rooms?q={}", encode(query))).await;
+    assert!(json.get("code").is_none());
+    assert!(!json["result"].as_array().unwrap().is_empty());
+}
+
+fn column(name: &str, r#type: &str) -> Value {
+    serde_json::json!({"name": name, "type": r#type})
+}
+
+#[tokio::test]
+async fn test_sections_schema() {
+    let json = get_json("/schema/sections").await;
+    let columns = json.as_array().unwrap();
+    assert_eq!(columns.len(), 10);
+    assert!(columns.contains(&column("sections_avg", "number")));
+    assert!(columns.contains(&column("sections_year", "number")));
+    assert!(columns.contains(&column("sections_dept", "string")));
+}
+
+#[tokio::test]
+async fn test_rooms_schema() {
+    let json = get_json("/schema/rooms").await;
+    let columns = json.as_array().unwrap();
+    assert_eq!(columns.len(), 11);
+    assert!(columns.contains(&column("rooms_seats", "number")));
+    assert!(columns.contains(&column("rooms_name", "string")));
+    assert!(columns.contains(&column("rooms_type", "string")));
+}
+
+fn lines(input: &str) -> impl Iterator<Item = String> {
+    input.lines().map(str::to_string)
+}
+
+#[test]
+fn test_read_query_until_terminator() {
+    let input = "{\n  \"WHERE\": {}\n}\n;\nsection";
+    assert_eq!(read_query(lines(input)), "{\n  \"WHERE\": {}\n}");
+}
+
+#[test]
+fn test_read_query_until_eof() {
+    let input = "{\"WHERE\": {},\n\"OPTIONS\": {\"COLUMNS\": []}}";
+    assert_eq!(read_query(lines(input)), input);
+}
+
+#[test]
+fn test_read_query_empty() {
+    assert_eq!(read_query(lines(" ;\n{}")), "");
+    assert_eq!(read_query(lines("")), "");
+}
+
+#[tokio::test]
+async fn test_list_datasets() {
+    let json = get_json("/datasets").await;
+    let datasets = json.as_array().unwrap();
+    assert_eq!(datasets.len(), 2);
+    assert_eq!(datasets[0]["id"], "sections");
+    assert_eq!(datasets[0]["kind"], "courses");
+    assert_eq!(datasets[1]["id"], "rooms");
+    assert_eq!(datasets[1]["kind"], "rooms");
+    assert!(
+        datasets
+            .iter()
+            .all(|dataset| dataset["rows"].as_u64().unwrap() > 0)
+    );
+}
+
+async fn content_encoding(accept_encoding: Option<&str>) -> Option<String> {
+    let mut request = Request::get("/schema/sections");
+    if let Some(accept_encoding) = accept_encoding {
+        request = request.header(header::ACCEPT_ENCODING, accept_encoding);
+    }
+    let response = app()
+        .oneshot(
+            request
+                .header(header::ORIGIN, "http://localhost")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    // Compression must not strip the CORS headers
+    assert!(
+        response
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+    );
+    response
+        .headers()
+        .get(header::CONTENT_ENCODING)
+        .map(|value| value.to_str().unwrap().to_string())
+}
+
+#[tokio::test]
+async fn test_gzip_compression() {
+    assert_eq!(
+        content_encoding(Some("gzip")).await.as_deref(),
+        Some("gzip")
+    );
+    assert_eq!(content_encoding(Some("br")).await.as_deref(), Some("br"));
+    assert_eq!(content_encoding(None).await, None);
+}
+
+#[tokio::test]
+async fn test_cors_origins() {
+    let allowed = |origin: &'static str| async move {
+        let config = Config {
+            cors_origins: vec![header::HeaderValue::from_static("https://a.example.com")],
+            ..Config::default()
+        };
+        let response = router(&config)
+            .oneshot(
+                Request::get("/healthz")
+                    .header(header::ORIGIN, origin)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        response
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .cloned()
+    };
+    assert_eq!(
+        allowed("https://a.example.com").await.unwrap(),
+        "https://a.example.com"
+    );
+    assert_eq!(allowed("https://b.example.com").await, None);
+}
+
+#[tokio::test]
+async fn test_query_timeout() {
+    let query = r#"{"WHERE": {"IS": {"sections_title": ".*a.*e.*i.*o.*u.*"}}, "OPTIONS": {"COLUMNS": ["sections_uuid"]}}"#;
+    let config = Config {
+        timeout: Duration::from_millis(1),
+        ..Config::default()
+    };
+    let response = router(&config)
+        .oneshot(
+            Request::get(format!("/sections?q={}", encode(query)))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+}
+
+#[tokio::test]
+async fn test_query_cache() {
+    let query = r#"{"WHERE": {"IS": {"rooms_shortname": "ANGU"}}, "OPTIONS": {"COLUMNS": ["rooms_name", "rooms_seats"]}}"#;
+    let reordered = r#"{"OPTIONS": {"COLUMNS": ["rooms_name", "rooms_seats"]}, "WHERE": {"IS": {"rooms_shortname": "ANGU"}}}"#;
+    let key = format!("rooms:{}", crate::cache::canonicalize(reordered).unwrap());
+
+    let fresh = get_json(&format!("/rooms?q={}", encode(query))).await;
+    let (cached, _) = QUERY_CACHE.lock().unwrap().get(&key).unwrap();
+    assert_eq!(fresh["result"], serde_json::to_value(&cached).unwrap());
+
+    // A cached entry is served as-is, which a stale marker row makes observable
+    let marker = (vec![std::collections::BTreeMap::new()], None);
+    QUERY_CACHE.lock().unwrap().insert(key, marker);
+    let hit = get_json(&format!("/rooms?q={}", encode(reordered))).await;
+    assert_eq!(hit["result"], serde_json::json!([{}]));
+    let bypass = get_json(&format!("/rooms?q={}&nocache", encode(reordered))).await;
+    assert_eq!(bypass["result"], fresh["result"]);
+}
+
+async fn metric(name: &str) -> u64 {
+    let response = app()
+        .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let text = String::from_utf8(body.to_vec()).unwrap();
+    text.lines()
+        .find_map(|line| line.strip_prefix(&format!("{} ", name)))
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_metrics() {
+    let queries = metric("queries_total").await;
+    let parse_errors = metric(r#"query_errors_total{code="PARSE_ERROR"}"#).await;
+    let too_large = metric("result_too_large_total").await;
+
+    let ok =
+        r#"{"WHERE": {"IS": {"rooms_shortname": "DMP"}}, "OPTIONS": {"COLUMNS": ["rooms_name"]}}"#;
+    query_code("rooms", ok).await;
+    query_code("rooms", r#"{"WHERE": {}}"#).await;
+    query_code(
+        "sections",
+        r#"{"WHERE": {}, "OPTIONS": {"COLUMNS": ["sections_uuid"]}}"#,
+    )
+    .await;
+
+    // Other tests share the counters, so only check that ours were counted
+    assert!(metric("queries_total").await >= queries + 3);
+    assert!(metric(r#"query_errors_total{code="PARSE_ERROR"}"#).await > parse_errors);
+    assert!(metric("result_too_large_total").await > too_large);
+}
+
+#[tokio::test]
+async fn test_query_by_id() {
+    let query =
+        r#"{"WHERE": {"IS": {"rooms_shortname": "DMP"}}, "OPTIONS": {"COLUMNS": ["rooms_name"]}}"#;
+    let by_id = get_json(&format!("/query/rooms?q={}", encode(query))).await;
+    let by_route = get_json(&format!("/rooms?q={}", encode(query))).await;
+    assert!(!by_id["result"].as_array().unwrap().is_empty());
+    assert_eq!(by_id["result"], by_route["result"]);
+
+    let response = app()
+        .oneshot(
+            Request::get(format!("/query/books?q={}", encode(query)))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_explain() {
+    let query =
+        r#"{"WHERE": {"IS": {"rooms_shortname": "DMP"}}, "OPTIONS": {"COLUMNS": ["rooms_name"]}}"#;
+    let json = get_json(&format!("/rooms?explain=true&q={}", encode(query))).await;
+    assert_eq!(json["plan"]["filter"]["op"], "IS");
+    assert_eq!(json["plan"]["uses_index"], true);
+    assert!(json.get("result").is_none());
+
+    let query =
+        r#"{"WHERE": {"IS": {"rooms_ShortName": "DMP"}}, "OPTIONS": {"COLUMNS": ["rooms_name"]}}"#;
+    let json = get_json(&format!("/rooms?explain=true&q={}", encode(query))).await;
+    assert_eq!(json["plan"]["uses_index"], true);
+}
+
+#[tokio::test]
+async fn test_health_and_readiness() {
+    for uri in ["/healthz", "/readyz"] {
+        let response = app()
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
+
+#[tokio::test]
+async fn test_pagination() {
+    let paged = |options: &str| {
+        format!(
+            r#"{{"WHERE": {{"IS": {{"rooms_shortname": "DMP"}}}}, "OPTIONS": {{"COLUMNS": ["rooms_name"], "ORDER": "rooms_name"{}}}}}"#,
+            options
+        )
+    };
+    let all = get_json(&format!("/rooms?q={}", encode(&paged("")))).await;
+    assert!(all.get("total").is_none() && all.get("has_more").is_none());
+    let all = all["result"].as_array().unwrap().clone();
+    assert!(all.len() > 2);
+
+    let first = get_json(&format!("/rooms?q={}", encode(&paged(r#", "LIMIT": 2"#)))).await;
+    assert_eq!(first["result"].as_array().unwrap()[..], all[..2]);
+    assert_eq!(first["total"], all.len());
+    assert_eq!(first["offset"], 0);
+    assert_eq!(first["has_more"], true);
+
+    let options = format!(r#", "LIMIT": 2, "OFFSET": {}"#, all.len() - 2);
+    let last = get_json(&format!("/rooms?q={}", encode(&paged(&options)))).await;
+    assert_eq!(last["result"].as_array().unwrap()[..], all[all.len() - 2..]);
+    assert_eq!(last["has_more"], false);
+}
+
+// A gzipped section file with one section per uuid
+fn write_sections(path: &std::path::Path, uuids: &[u32]) {
+    let sections: Vec<String> = uuids
+        .iter()
+        .map(|uuid| {
+            format!(
+                r#"{{"id":{},"Course":"310","Title":"intr sftwr eng","Professor":"","Subject":"cpsc","Year":"2014","Avg":78.69,"Pass":156,"Fail":0,"Audit":0}}"#,
+                uuid
+            )
+        })
+        .collect();
+    let mut gz = flate2::write::GzEncoder::new(
+        std::fs::File::create(path).unwrap(),
+        flate2::Compression::default(),
+    );
+    let json = format!(r#"{{"result":[{}]}}"#, sections.join(","));
+    std::io::Write::write_all(&mut gz, json.as_bytes()).unwrap();
+    gz.finish().unwrap();
+}
+
+#[test]
+fn test_reload_registry() {
+    let path = std::env::temp_dir().join("main-test-reload.json.gz");
+    let specs = [DatasetSpec::new(
+        "fixture",
+        "courses",
+        path.to_str().unwrap(),
+    )];
+    let rows = |registry: &Registry| registry.get("fixture").unwrap().rows.rows.len();
+
+    write_sections(&path, &[1, 2]);
+    let registry =
+        arc_swap::ArcSwap::from_pointee(Registry::load(&specs, &Loaders::default()).unwrap());
+    let before = registry.load_full();
+
+    write_sections(&path, &[1, 2, 3]);
+    reload_registry(&registry, &specs).unwrap();
+    assert_eq!(rows(&registry.load()), 3);
+    // A query that started before the reload still sees its snapshot
+    assert_eq!(rows(&before), 2);
+
+    // A failed reload keeps serving the last good data
+    std::fs::remove_file(&path).unwrap();
+    assert!(reload_registry(&registry, &specs).is_err());
+    assert_eq!(rows(&registry.load()), 3);
+}
+
+#[tokio::test]
+async fn test_reload_requires_token() {
+    let response = app()
+        .oneshot(
+            Request::post("/admin/reload")
+                .header(header::AUTHORIZATION, "Bearer guess")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_rate_limit() {
+    let config = Config {
+        rate_limit: Some(2.0),
+        ..Config::default()
+    };
+    let app = router(&config);
+    let status = |port: u16| {
+        let app = app.clone();
+        async move {
+            let mut request = Request::get("/healthz").body(Body::empty()).unwrap();
+            let peer = std::net::SocketAddr::from(([10, 0, 0, 1], port));
+            request
+                .extensions_mut()
+                .insert(axum::extract::ConnectInfo(peer));
+            app.oneshot(request).await.unwrap().status()
+        }
+    };
+    assert_eq!(status(1000).await, StatusCode::OK);
+    // The bucket is per IP, so a new port doesn't reset it
+    assert_eq!(status(1001).await, StatusCode::OK);
+    assert_eq!(status(1002).await, StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn test_table_format() {
+    let query = r#"{"WHERE": {"IS": {"rooms_name": "DMP_110"}}, "OPTIONS": {"COLUMNS": ["rooms_name", "rooms_seats"], "FORMAT": "table"}}"#;
+    let json = get_json(&format!("/rooms?q={}", encode(query))).await;
+    assert!(json.get("result").is_none());
+    assert_eq!(
+        json["columns"],
+        serde_json::json!(["rooms_name", "rooms_seats"])
+    );
+    assert_eq!(json["rows"][0][0], "DMP_110");
+}
+
+#[tokio::test]
+async fn test_elapsed_ms() {
+    let query =
+        r#"{"WHERE": {"IS": {"rooms_name": "DMP_110"}}, "OPTIONS": {"COLUMNS": ["rooms_name"]}}"#;
+    let json = get_json(&format!("/rooms?q={}&nocache", encode(query))).await;
+    assert!(json["elapsed_ms"].as_f64().unwrap() >= 0.0);
+
+    let invalid = get_json(&format!("/rooms?q={}", encode("{}"))).await;
+    assert!(invalid.get("elapsed_ms").is_none());
+}
+
+#[tokio::test]
+async fn test_shutdown_on_signal() {
+    let stopping = AtomicBool::new(false);
+    let (send, receive) = tokio::sync::oneshot::channel::<()>();
+    let shutdown = shutdown(
+        async {
+            receive.await.unwrap();
+        },
+        &stopping,
+    );
+    send.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(1), shutdown)
+        .await
+        .unwrap();
+    assert!(stopping.load(Ordering::SeqCst));
+}
+
+#[tokio::test]
+async fn test_key_case() {
+    let query = r#"{"WHERE": {"IS": {"rooms_shortname": "DMP"}}, "OPTIONS": {"COLUMNS": ["rooms_name", "rooms_seats"], "ORDER": "rooms_seats", "KEY_CASE": "camel"}}"#;
+    let json = get_json(&format!("/rooms?q={}", encode(query))).await;
+    let row = json["result"][0].as_object().unwrap();
+    let keys: Vec<_> = row.keys().map(String::as_str).collect();
+    assert_eq!(keys, vec!["roomsName", "roomsSeats"]);
+
+    let table = query.replace(r#""camel""#, r#""camel", "FORMAT": "table""#);
+    let json = get_json(&format!("/rooms?q={}", encode(&table))).await;
+    assert_eq!(
+        json["columns"],
+        serde_json::json!(["roomsName", "roomsSeats"])
+    );
+}
+
+async fn post_json(uri: &str, body: &str) -> Value {
+    let response = app()
+        .oneshot(
+            Request::post(uri)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn test_validate_against_dataset() {
+    let query = r#"{"WHERE": {}, "OPTIONS": {"COLUMNS": ["rooms_bogus"]}, "TRANSFORMATIONS": {"GROUP": ["rooms_bogus"], "APPLY": []}}"#;
+    assert_eq!(post_json("/validate", query).await["valid"], true);
+    let json = post_json("/validate?dataset=rooms", query).await;
+    assert_eq!(json["valid"], false);
+    assert_eq!(json["error"], "Field rooms_bogus does not exist");
+
+    let query = r#"{"WHERE": {}, "OPTIONS": {"COLUMNS": ["rooms_ShortName"]}, "TRANSFORMATIONS": {"GROUP": ["rooms_ShortName"], "APPLY": []}}"#;
+    assert_eq!(
+        post_json("/validate?dataset=rooms", query).await["valid"],
+        true
+    );
+}
+
+#[tokio::test]
+async fn test_search() {
+    // Sections has no rooms_ columns, so it is left out rather than failing the request
+    let json = post_json(
+        "/search",
+        r#"{"WHERE": {"IS": {"rooms_shortname": "DMP"}}, "OPTIONS": {"COLUMNS": ["rooms_name"]}}"#,
+    )
+    .await;
+    assert!(json.get("sections").is_none());
+    assert!(!json["rooms"]["result"].as_array().unwrap().is_empty());
+
+    // Any other error is reported for its dataset alone
+    let json = post_json(
+        "/search",
+        r#"{"WHERE": {"GT": {"rooms_name": 1}}, "OPTIONS": {"COLUMNS": ["rooms_name"]}}"#,
+    )
+    .await;
+    assert!(json.get("sections").is_none());
+    assert_eq!(json["rooms"]["code"], "TYPE_ERROR");
+}
+
+#[tokio::test]
+async fn test_strip_prefix() {
+    let query = r#"{"WHERE": {"IS": {"rooms_shortname": "DMP"}}, "OPTIONS": {"COLUMNS": ["rooms_name", "rooms_seats"], "ORDER": "rooms_seats", "STRIP_PREFIX": true}}"#;
+    let json = get_json(&format!("/rooms?q={}", encode(query))).await;
+    let rows = json["result"].as_array().unwrap();
+    let keys: Vec<_> = rows[0].as_object().unwrap().keys().collect();
+    assert_eq!(keys, vec!["name", "seats"]);
+    // ORDER named the prefixed column but still applies
+    let seats: Vec<_> = rows
+        .iter()
+        .map(|row| row["seats"].as_f64().unwrap())
+        .collect();
+    assert!(seats.is_sorted());
+}
+
+#[tokio::test]
+async fn test_batch() {
+    let json = post_json(
+        "/batch",
+        r#"{"queries": [
+            {"dataset": "rooms", "query": {"WHERE": {"IS": {"rooms_shortname": "DMP"}}, "OPTIONS": {"COLUMNS": ["rooms_name"]}}},
+            {"dataset": "sections", "query": {"WHERE": {"GT": {"sections_avg": "high"}}, "OPTIONS": {"COLUMNS": ["sections_avg"]}}},
+            {"dataset": "books", "query": {"OPTIONS": {"COLUMNS": ["books_title"]}}},
+            {"dataset": "sections", "query": {"WHERE": {"GT": {"sections_avg": 99}}, "OPTIONS": {"COLUMNS": ["sections_avg"]}}}
+        ]}"#,
+    )
+    .await;
+    let results = json.as_array().unwrap();
+    assert_eq!(results.len(), 4);
+    assert!(!results[0]["result"].as_array().unwrap().is_empty());
+    assert_eq!(results[1]["code"], "PARSE_ERROR");
+    assert_eq!(results[2]["code"], "DATASET_NOT_FOUND");
+    assert!(results[3]["result"].is_array());
+}
+
+#[tokio::test]
+async fn test_batch_limits() {
+    let not_found = r#"query_errors_total{code="DATASET_NOT_FOUND"}"#;
+    let before = metric(not_found).await;
+    let json = post_json(
+        "/batch",
+        r#"{"queries": [{"dataset": "books", "query": {"OPTIONS": {"COLUMNS": ["books_title"]}}}]}"#,
+    )
+    .await;
+    assert_eq!(json[0]["code"], "DATASET_NOT_FOUND");
+    assert!(metric(not_found).await > before);
+
+    let item = r#"{"dataset": "books", "query": {}}"#;
+    let body = format!(r#"{{"queries": [{}]}}"#, vec![item; 33].join(", "));
+    let response = app()
+        .oneshot(
+            Request::post("/batch")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_section_by_uuid() {
+    let query = r#"{"WHERE": {"IS": {"sections_dept": "cpsc"}}, "OPTIONS": {"COLUMNS": ["sections_uuid", "sections_avg"], "LIMIT": 1}}"#;
+    let json = get_json(&format!("/sections?q={}", encode(query))).await;
+    let expected = &json["result"][0];
+    let uuid = expected["sections_uuid"].as_str().unwrap();
+
+    let section = get_json(&format!("/sections/{}", uuid)).await;
+    assert_eq!(section["sections_uuid"], expected["sections_uuid"]);
+    assert_eq!(section["sections_avg"], expected["sections_avg"]);
+    assert_eq!(section["sections_dept"], "cpsc");
+    assert!(section.as_object().unwrap().contains_key("sections_title"));
+
+    let response = app()
+        .oneshot(
+            Request::get("/sections/no-such-uuid")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_export() {
+    let rows = get_json("/export/rooms").await;
+    let rows = rows.as_array().unwrap();
+    let datasets = get_json("/datasets").await;
+    let rooms = datasets
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|dataset| dataset["id"] == "rooms")
+        .unwrap();
+    assert_eq!(rows.len() as u64, rooms["rows"].as_u64().unwrap());
+    assert!(rows.iter().all(|row| row["rooms_name"].is_string()));
+
+    let response = app()
+        .oneshot(Request::get("/export/nope").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_join() {
+    let rows = get_json("/join/sections/rooms?left=sections_avg&right=rooms_seats").await;
+    let rows = rows.as_array().unwrap();
+    assert!(!rows.is_empty());
+    assert!(
+        rows.iter()
+            .all(|row| row["sections_avg"] == row["rooms_seats"] && row["rooms_name"].is_string())
+    );
+
+    for (uri, status) in [
+        (
+            "/join/sections/nope?left=sections_avg&right=rooms_seats",
+            StatusCode::NOT_FOUND,
+        ),
+        (
+            "/join/sections/rooms?left=sections_avg",
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            "/join/sections/rooms?left=sections_avg&right=rooms_seat",
+            StatusCode::BAD_REQUEST,
+        ),
+    ] {
+        let response = app()
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), status, "{}", uri);
+    }
+}
+
+#[cfg(feature = "parquet")]
+#[tokio::test]
+async fn test_parquet_format() {
+    use arrow::array::AsArray;
+    use arrow::datatypes::Float32Type;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    let query = r#"{"WHERE": {"IS": {"rooms_shortname": "DMP"}}, "OPTIONS": {"COLUMNS": ["rooms_name", "rooms_seats"], "ORDER": "rooms_name"}}"#;
+    let json = get_json(&format!("/rooms?q={}", encode(query))).await;
+
+    let response = app()
+        .oneshot(
+            Request::get(format!("/rooms?q={}&format=parquet", encode(query)))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "application/vnd.apache.parquet"
+    );
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let batch = ParquetRecordBatchReaderBuilder::try_new(body)
+        .unwrap()
+        .build()
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap();
+
+    let expected = json["result"].as_array().unwrap();
+    assert_eq!(batch.num_rows(), expected.len());
+    let names = batch.column(0).as_string::<i32>();
+    let seats = batch.column(1).as_primitive::<Float32Type>();
+    for (i, row) in expected.iter().enumerate() {
+        assert_eq!(names.value(i), row["rooms_name"]);
+        assert_eq!(seats.value(i) as f64, row["rooms_seats"].as_f64().unwrap());
+    }
+}
+
+// Collects formatted log lines, shared between the subscriber and the test
+#[derive(Clone, Default)]
+struct Captured(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl std::io::Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for Captured {
+    type Writer = Captured;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+#[tokio::test]
+async fn test_query_log() {
+    let captured = Captured::default();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(captured.clone())
+        .with_ansi(false)
+        .finish();
+    // The test runtime runs the handler on this thread, where the subscriber is the default
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let query =
+        r#"{"WHERE": {"IS": {"rooms_shortname": "DMP"}}, "OPTIONS": {"COLUMNS": ["rooms_name"]}}"#;
+    let json = get_json(&format!("/rooms?q={}&nocache", encode(query))).await;
+    let rows = json["result"].as_array().unwrap().len();
+
+    let logs = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+    let line = logs
+        .lines()
+        .find(|line| line.contains("Query answered"))
+        .unwrap();
+    assert!(line.contains("dataset=rooms"));
+    assert!(line.contains(&format!("rows={}", rows)));
+    assert!(line.contains("elapsed_ms="));
+    assert!(line.contains("rooms_shortname"));
+}
@@ -0,0 +1,46 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Every code a query can fail with
+const ERROR_CODES: [&str; 6] = [
+    "PARSE_ERROR",
+    "INVALID_QUERY",
+    "RESULT_TOO_LARGE",
+    "TYPE_ERROR",
+    "FIELD_NOT_FOUND",
+    "DATASET_NOT_FOUND",
+];
+
+static QUERIES: AtomicU64 = AtomicU64::new(0);
+static ERRORS: [AtomicU64; ERROR_CODES.len()] = [const { AtomicU64::new(0) }; ERROR_CODES.len()];
+
+pub fn record_query() {
+    QUERIES.fetch_add(1, Ordering::Relaxed);
+}
+
+fn errors(code: &str) -> Option<&'static AtomicU64> {
+    let i = ERROR_CODES.iter().position(|known| *known == code)?;
+    Some(&ERRORS[i])
+}
+
+pub fn record_error(code: &str) {
+    if let Some(count) = errors(code) {
+        count.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Counters in the Prometheus text exposition format
+pub fn render() -> String {
+    let mut out = String::new();
+    writeln!(out, "# TYPE queries_total counter").unwrap();
+    writeln!(out, "queries_total {}", QUERIES.load(Ordering::Relaxed)).unwrap();
+    writeln!(out, "# TYPE query_errors_total counter").unwrap();
+    for (code, count) in ERROR_CODES.iter().zip(&ERRORS) {
+        let count = count.load(Ordering::Relaxed);
+        writeln!(out, "query_errors_total{{code=\"{}\"}} {}", code, count).unwrap();
+    }
+    writeln!(out, "# TYPE result_too_large_total counter").unwrap();
+    let too_large = errors("RESULT_TOO_LARGE").unwrap().load(Ordering::Relaxed);
+    writeln!(out, "result_too_large_total {}", too_large).unwrap();
+    out
+}
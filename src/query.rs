@@ -1,47 +1,413 @@
 use crate::dataset::{EPSILON};
-use crate::types::{Dataset, KVPair, Value};
+use crate::errors::EngineError;
+use crate::index::Indexed;
+use crate::types::{Dataset, KVPair, Page, Value};
 use anyhow::anyhow;
-use itertools::Itertools;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
 use ordered_float::OrderedFloat;
-use regex::Regex;
-use serde::Deserialize;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::sync::{LazyLock, Mutex};
-use crate::types::Value::{Num, Str};
+use crate::types::Value::{Bool, Null, Num, Str};
 
 type FilterFunc<'a, D> = Box<dyn Fn(&D) -> anyhow::Result<bool> + 'a>;
+/// Column means that ABOVEAVG and BELOWAVG compare against, computed before filtering
+pub type Means = HashMap<String, f64>;
+pub type PagedResult = (Vec<BTreeMap<String, Value>>, Option<Page>);
+type ResultRow = BTreeMap<String, Value>;
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "UPPERCASE", deny_unknown_fields)]
 pub struct Query {
-    pub r#where: Filter,
+    pub r#where: Option<Filter>,
     pub options: Options,
     pub transformations: Option<Transformations>,
 }
 
-#[derive(Deserialize, Debug)]
+static MATCH_ALL: Filter = Filter::EMPTY {};
+
+fn json_type(value: &serde_json::Value) -> &'static str {
+    match value {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "boolean",
+        serde_json::Value::Number(_) => "number",
+        serde_json::Value::String(_) => "string",
+        serde_json::Value::Array(_) => "array",
+        serde_json::Value::Object(_) => "object",
+    }
+}
+
+/// Finds an LT, GT or EQ given a non-numeric value, which the untagged Filter can only report
+/// as matching no variant
+fn numeric_mismatch(filter: &serde_json::Value) -> Option<String> {
+    let serde_json::Value::Object(filter) = filter else {
+        return None;
+    };
+    filter
+        .iter()
+        .find_map(|(op, argument)| match (op.as_str(), argument) {
+            ("AND" | "OR" | "NOT", serde_json::Value::Array(filters)) => {
+                filters.iter().find_map(numeric_mismatch)
+            }
+            ("NOT", not) => numeric_mismatch(not),
+            ("LT" | "GT" | "EQ", serde_json::Value::Object(comparison)) => comparison
+                .iter()
+                .find(|(_, value)| !value.is_number())
+                .map(|(column, value)| {
+                    format!(
+                        "{} requires a numeric value for '{}', got {}",
+                        op,
+                        column,
+                        json_type(value)
+                    )
+                }),
+            _ => None,
+        })
+}
+
+/// Deserializes a query, rewording serde's unknown field errors to name the allowed keys
+pub fn parse_query(json: &str) -> anyhow::Result<Query> {
+    serde_json::from_str(json).map_err(|e| {
+        // serde_json's recursion limit bounds the walk, as the query already parsed as JSON
+        if let Ok(value) = serde_json::from_str::<serde_json::Value>(json)
+            && let Some(message) = value.get("WHERE").and_then(numeric_mismatch)
+        {
+            return anyhow!(message);
+        }
+        let message = e.to_string();
+        let Some((key, expected)) = message
+            .strip_prefix("unknown field `")
+            .and_then(|rest| rest.split_once("`, expected "))
+        else {
+            return e.into();
+        };
+        // serde appends the position, quotes each key and says "`A` or `B`" for two
+        let expected = expected.split(" at line ").next().unwrap_or(expected);
+        let allowed = expected
+            .trim_start_matches("one of ")
+            .replace("` or `", ", ")
+            .replace('`', "");
+        anyhow!("Unknown key {}, allowed keys are {}", key, allowed)
+    })
+}
+
+impl Query {
+    /// The WHERE filter, where a missing WHERE matches every row like an empty one
+    pub fn filter(&self) -> &Filter {
+        self.r#where.as_ref().unwrap_or(&MATCH_ALL)
+    }
+}
+
+/// Builds a Query in Rust rather than JSON. Executing it validates it like a parsed one.
+/// Only tests construct queries this way, the server always parses them.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct QueryBuilder {
+    filter: Option<Filter>,
+    options: Options,
+    transformations: Option<Transformations>,
+}
+
+#[cfg(test)]
+impl QueryBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The WHERE filter, replacing any given before
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Appends a column to COLUMNS
+    pub fn column(mut self, column: impl Into<String>) -> Self {
+        self.options.columns.push(column.into());
+        self
+    }
+
+    /// Orders ascending by one key
+    pub fn order(self, key: impl Into<String>) -> Self {
+        self.order_by(Order::ONE(key.into()))
+    }
+
+    pub fn order_by(mut self, order: Order) -> Self {
+        self.options.order = Some(order);
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.options.limit = Some(limit);
+        self
+    }
+
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.options.offset = Some(offset);
+        self
+    }
+
+    pub fn transformations(mut self, transformations: Transformations) -> Self {
+        self.transformations = Some(transformations);
+        self
+    }
+
+    pub fn build(self) -> Query {
+        Query {
+            r#where: self.filter,
+            options: self.options,
+            transformations: self.transformations,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "UPPERCASE", deny_unknown_fields)]
 pub struct Transformations {
     pub group: Vec<String>,
-    pub apply: Vec<KVPair<KVPair<String>>>,
+    // Omitting APPLY groups without aggregating, yielding the distinct group keys
+    #[serde(default)]
+    pub apply: Vec<KVPair<KVPair<ApplyArg>>>,
+    /// Decimal places numeric aggregates are rounded to, DEFAULT_PRECISION if omitted
+    pub precision: Option<u32>,
+}
+
+pub const DEFAULT_PRECISION: u32 = 2;
+// f32 results only hold about 7 significant digits anyway
+const MAX_PRECISION: u32 = 7;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum ApplyArg {
+    COLUMN(String),
+    PERCENTILE {
+        column: String,
+        p: OrderedFloat<f32>,
+    },
+    SORTED {
+        column: String,
+        by: String,
+    },
+    /// ARGMAX and ARGMIN's value column, and the column identifying the item they return
+    IDENTIFIED {
+        column: String,
+        id: String,
+    },
+    /// CONCAT options, joining with ", " and keeping duplicates by default
+    JOINED {
+        column: String,
+        #[serde(default)]
+        separator: Option<String>,
+        #[serde(default)]
+        distinct: bool,
+    },
+    /// COUNTWHERE's condition, evaluated against each item of the group
+    FILTERED {
+        filter: Filter,
+    },
+}
+
+impl ApplyArg {
+    pub fn column(&self) -> anyhow::Result<&String> {
+        match self {
+            ApplyArg::COLUMN(column) => Ok(column),
+            ApplyArg::PERCENTILE { column, .. } => Ok(column),
+            ApplyArg::SORTED { column, .. } => Ok(column),
+            ApplyArg::IDENTIFIED { column, .. } => Ok(column),
+            ApplyArg::JOINED { column, .. } => Ok(column),
+            ApplyArg::FILTERED { .. } => Err(anyhow!("Only COUNTWHERE takes a filter")),
+        }
+    }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Default, Clone)]
 #[serde(rename_all = "UPPERCASE", deny_unknown_fields)]
 pub struct Options {
     pub columns: Vec<String>,
     pub order: Option<Order>,
+    /// Accumulate SUM and AVG in f64 instead of f32
+    #[serde(default)]
+    pub f64: bool,
+    /// Maximum number of rows to return, applied after ORDER
+    pub limit: Option<usize>,
+    /// Number of ordered rows to skip before the page starts
+    pub offset: Option<usize>,
+    /// A previous page's `next_cursor`, starting this page right after it
+    pub cursor: Option<String>,
+    #[serde(default)]
+    pub format: Format,
+    /// Renames the keys of the response, while COLUMNS and ORDER keep the dataset names
+    pub key_case: Option<KeyCase>,
+    /// Drops the dataset's field prefix from response keys, e.g. `sections_avg` -> `avg`
+    #[serde(default)]
+    pub strip_prefix: bool,
+    /// Drops rows whose projected values repeat an earlier row's
+    #[serde(default)]
+    pub distinct: bool,
+    /// With DISTINCT, numbers in the same bucket of this width count as equal
+    pub distinct_tolerance: Option<OrderedFloat<f32>>,
+    /// Sorts rows whose ORDER key is null last if true or first if false, instead of failing.
+    /// Rows only lack a key outright with `"COLUMNS": ["*"]` over mixed rows, and sort the same way.
+    pub nulls_last: Option<bool>,
+    /// Adds a column ranking each row within its partition, computed before any TRANSFORMATIONS
+    pub percent_rank: Option<PercentRank>,
+}
+
+/// Each row's percentile rank, 0 to 100, by a numeric column among the rows sharing its partition
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PercentRank {
+    /// The added column, which COLUMNS and ORDER can then name
+    pub name: String,
+    pub column: String,
+    /// Rows with equal values in this column are ranked together, or every row if omitted
+    pub partition: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyCase {
+    /// `sections_avg` becomes `sectionsAvg`
+    CAMEL,
+    /// `maxSeats` becomes `max_seats`
+    SNAKE,
+}
+
+impl KeyCase {
+    pub fn apply(&self, key: &str) -> String {
+        let mut renamed = String::with_capacity(key.len());
+        let mut upper_next = false;
+        for c in key.chars() {
+            match self {
+                KeyCase::CAMEL if c == '_' => upper_next = true,
+                KeyCase::CAMEL if upper_next => {
+                    renamed.extend(c.to_uppercase());
+                    upper_next = false;
+                }
+                KeyCase::SNAKE if c.is_uppercase() => {
+                    renamed.push('_');
+                    renamed.extend(c.to_lowercase());
+                }
+                _ => renamed.push(c),
+            }
+        }
+        renamed
+    }
+}
+
+/// Renames every key of the rows, applied last so the query itself still uses the dataset names
+pub fn rename_keys(
+    rows: Vec<BTreeMap<String, Value>>,
+    rename: impl Fn(&str) -> String,
+) -> Vec<BTreeMap<String, Value>> {
+    rows.into_iter()
+        .map(|row| {
+            row.into_iter()
+                .map(|(key, value)| (rename(&key), value))
+                .collect()
+        })
+        .collect()
+}
+
+/// Shape of the result rows in the response
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// One object per row
+    #[default]
+    OBJECT,
+    /// A `columns` header plus `rows` as arrays aligned to it
+    TABLE,
+}
+
+/// Splits rows into a header in COLUMNS order and the values aligned to it
+pub fn to_table(
+    options: &Options,
+    rows: Vec<BTreeMap<String, Value>>,
+) -> (Vec<String>, Vec<Vec<Value>>) {
+    let columns = match rows.first() {
+        Some(row) if options.selects_all() => row.keys().cloned().collect(),
+        None if options.selects_all() => vec![],
+        // Rows hold mixed-case COLUMNS under the dataset's spelling
+        Some(row) => options
+            .columns
+            .iter()
+            .map(|column| {
+                if row.contains_key(column) {
+                    return column.clone();
+                }
+                let key = row.keys().find(|key| key.eq_ignore_ascii_case(column));
+                key.unwrap_or(column).clone()
+            })
+            .collect(),
+        None => options.columns.clone(),
+    };
+    let rows = rows
+        .into_iter()
+        .map(|mut row| {
+            columns
+                .iter()
+                // Projection has already checked every row has every column
+                .map(|column| row.remove(column).unwrap())
+                .collect()
+        })
+        .collect();
+    (columns, rows)
 }
 
-#[derive(Deserialize, Debug)]
+/// `"COLUMNS": ["*"]` keeps every column, or every GROUP and APPLY key when transforming
+pub const ALL_COLUMNS: &str = "*";
+
+impl Options {
+    pub fn selects_all(&self) -> bool {
+        self.columns.iter().any(|column| column == ALL_COLUMNS)
+    }
+
+    /// Whether STRIP_PREFIX or KEY_CASE change the response keys
+    pub fn renames_keys(&self) -> bool {
+        self.strip_prefix || self.key_case.is_some()
+    }
+
+    /// The response key for a result key, stripping `prefix` before changing the case
+    pub fn output_key(&self, prefix: &str, key: &str) -> String {
+        let key = match key.strip_prefix(prefix) {
+            Some(stripped) if self.strip_prefix && !prefix.is_empty() => stripped,
+            _ => key,
+        };
+        match self.key_case {
+            Some(key_case) => key_case.apply(key),
+            None => key.to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
 #[serde(untagged, deny_unknown_fields)]
 pub enum Order {
     ONE(String),
-    MANY { dir: String, keys: Vec<String> },
+    /// A single key with a direction, shorter than MANY with one key
+    KEYED {
+        key: String,
+        dir: String,
+    },
+    MANY {
+        dir: String,
+        keys: Vec<String>,
+    },
 }
 
-#[derive(Deserialize, Debug)]
+impl Order {
+    pub fn keys(&self) -> &[String] {
+        match self {
+            Order::ONE(key) | Order::KEYED { key, .. } => std::slice::from_ref(key),
+            Order::MANY { keys, .. } => keys,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
 #[serde(untagged, deny_unknown_fields)]
 pub enum Filter {
     AND {
@@ -54,7 +420,7 @@ pub enum Filter {
     },
     NOT {
         #[serde(rename = "NOT")]
-        not: Box<Filter>,
+        not: NotArg,
     },
     LT {
         #[serde(rename = "LT")]
@@ -67,35 +433,270 @@ pub enum Filter {
     EQ {
         #[serde(rename = "EQ")]
         eq: KVPair<OrderedFloat<f32>>,
+        // Overrides EPSILON, with 0 meaning exact equality
+        #[serde(default)]
+        tol: Option<OrderedFloat<f32>>,
+    },
+    /// Rows whose numeric value lies within inclusive bounds, either of which may be left open
+    RANGE {
+        #[serde(rename = "RANGE")]
+        range: KVPair<RangeBounds>,
+    },
+    /// Equality of any value type, numbers within EPSILON and strings exactly
+    EQV {
+        #[serde(rename = "EQV")]
+        eqv: KVPair<Value>,
     },
     IS {
         #[serde(rename = "IS")]
         is: KVPair<String>,
+        #[serde(default)]
+        mode: IsMode,
+        /// Inline flags for regex mode, any of REGEX_FLAGS
+        #[serde(default)]
+        flags: Option<String>,
+    },
+    /// Plain string matches, clearer and cheaper than the equivalent IS wildcards
+    STARTSWITH {
+        #[serde(rename = "STARTSWITH")]
+        startswith: KVPair<String>,
+    },
+    ENDSWITH {
+        #[serde(rename = "ENDSWITH")]
+        endswith: KVPair<String>,
+    },
+    CONTAINS {
+        #[serde(rename = "CONTAINS")]
+        contains: KVPair<String>,
+    },
+    /// Rows whose string value is one of the listed values
+    IN {
+        #[serde(rename = "IN")]
+        r#in: KVPair<Vec<String>>,
+    },
+    /// Rows whose string value is none of the listed values
+    NIN {
+        #[serde(rename = "NIN")]
+        nin: KVPair<Vec<String>>,
+    },
+    /// Rows whose numeric value equals any listed value, within EPSILON like EQ
+    INNUM {
+        #[serde(rename = "INNUM")]
+        innum: KVPair<Vec<OrderedFloat<f32>>>,
+    },
+    /// Rows whose furniture-style text has any listed keyword among its tokens
+    FURNITUREHAS {
+        #[serde(rename = "FURNITUREHAS")]
+        furniturehas: KVPair<Vec<String>>,
+    },
+    /// Rows whose lat/lon columns are within radius metres of a point
+    NEAR {
+        #[serde(rename = "NEAR")]
+        near: NearArgs,
+    },
+    /// Compares two numeric columns of the same row
+    CMP {
+        #[serde(rename = "CMP")]
+        cmp: CmpArgs,
+    },
+    /// Rows whose numeric column is above the mean of the rows being filtered
+    ABOVEAVG {
+        #[serde(rename = "ABOVEAVG")]
+        aboveavg: String,
+    },
+    BELOWAVG {
+        #[serde(rename = "BELOWAVG")]
+        belowavg: String,
+    },
+    /// Rows whose numeric column holds a whole number, within EPSILON
+    ISINT {
+        #[serde(rename = "ISINT")]
+        isint: String,
+    },
+    /// Rows whose column holds a value, rather than null
+    HAS {
+        #[serde(rename = "HAS")]
+        has: String,
+    },
+    /// Case-insensitive substring match against every string column
+    SEARCH {
+        #[serde(rename = "SEARCH")]
+        search: String,
     },
     EMPTY {},
 }
 
-fn parse_and<'a, D: Dataset + 'a>(and: &'a Vec<Filter>) -> FilterFunc<'a, D> {
-    let filters: Vec<_> = and.iter().map(|filter| parse_filter(filter)).collect();
-    Box::new(move |course| {
-        Ok(filters
-            .iter()
-            .map(|filter| filter(course))
-            .collect::<Result<Vec<bool>, _>>()?
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct NearArgs {
+    pub lat: OrderedFloat<f32>,
+    pub lon: OrderedFloat<f32>,
+    pub radius: OrderedFloat<f32>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RangeBounds {
+    #[serde(default)]
+    pub min: Option<OrderedFloat<f32>>,
+    #[serde(default)]
+    pub max: Option<OrderedFloat<f32>>,
+}
+
+impl RangeBounds {
+    pub fn contains(&self, value: OrderedFloat<f32>) -> bool {
+        self.min.is_none_or(|min| value >= min) && self.max.is_none_or(|max| value <= max)
+    }
+}
+
+/// NOT takes one filter, or an array negated as a whole, i.e. NOT of their AND
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum NotArg {
+    ONE(Box<Filter>),
+    MANY(Vec<Filter>),
+}
+
+impl NotArg {
+    pub fn filters(&self) -> &[Filter] {
+        match self {
+            NotArg::ONE(filter) => std::slice::from_ref(filter),
+            NotArg::MANY(filters) => filters,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CmpArgs {
+    pub left: String,
+    pub right: String,
+    pub op: CmpOp,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub enum CmpOp {
+    LT,
+    GT,
+    EQ,
+}
+
+fn parse_cmp(cmp: &CmpArgs, course: &impl Dataset) -> anyhow::Result<bool> {
+    let number = |col: &String| match course.get(col) {
+        Ok(Num(n)) => Ok(Some(n)),
+        // Booleans compare as 0 and 1, like in LT and GT
+        Ok(Bool(b)) => Ok(Some(OrderedFloat(f32::from(u8::from(b))))),
+        Ok(Null) => Ok(None),
+        Ok(_) => Err(anyhow::Error::from(EngineError::TypeError {
+            op: "cmp",
+            column: col.clone(),
+        })),
+        Err(_) => Err(EngineError::field_not_found(
+            col,
+            course.columns().iter().map(String::as_str),
+        )
+        .into()),
+    };
+    let (Some(left), Some(right)) = (number(&cmp.left)?, number(&cmp.right)?) else {
+        return Ok(false);
+    };
+    Ok(match cmp.op {
+        CmpOp::LT => left < right,
+        CmpOp::GT => left > right,
+        CmpOp::EQ => (left - right).abs() < EPSILON,
+    })
+}
+
+const EARTH_RADIUS_METRES: f64 = 6_371_000.0;
+
+/// Great-circle distance in metres between two lat/lon points
+fn haversine(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = (lon2 - lon1).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_METRES * a.sqrt().asin()
+}
+
+fn parse_near(near: &NearArgs, course: &impl Dataset) -> anyhow::Result<bool> {
+    let coordinate = |suffix: &str| {
+        let column = course
+            .columns()
             .into_iter()
-            .all(|b| b))
+            .find(|column| column.ends_with(suffix))
+            .ok_or_else(|| anyhow!("NEAR requires lat and lon columns"))?;
+        match course.get(&column) {
+            Ok(Num(n)) => Ok(Some(n.0 as f64)),
+            Ok(Null) => Ok(None),
+            _ => Err(anyhow::Error::from(EngineError::TypeError {
+                op: "near",
+                column,
+            })),
+        }
+    };
+    let (Some(lat), Some(lon)) = (coordinate("_lat")?, coordinate("_lon")?) else {
+        return Ok(false);
+    };
+    let distance = haversine(near.lat.0 as f64, near.lon.0 as f64, lat, lon);
+    Ok(distance <= near.radius.0 as f64)
+}
+
+/// How an IS pattern is matched
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum IsMode {
+    /// `*` matches any run of characters, anchored at both ends, and everything else itself
+    #[default]
+    WILDCARD,
+    /// An unanchored regex
+    REGEX,
+    /// Plain string equality
+    EXACT,
+}
+
+/// Case-insensitive, multiline, dot matches newline and verbose
+pub const REGEX_FLAGS: &str = "imsx";
+
+impl IsMode {
+    /// Whether the pattern can only match the string equal to it
+    pub fn is_literal(&self, pattern: &str) -> bool {
+        match self {
+            IsMode::WILDCARD => !pattern.contains('*'),
+            IsMode::REGEX => false,
+            IsMode::EXACT => true,
+        }
+    }
+}
+
+// AND and OR short-circuit per row, so an error in a later subfilter is only
+// reported if some row gets far enough to evaluate it
+fn parse_and<'a, D: Dataset + 'a>(and: &'a [Filter], means: &'a Means) -> FilterFunc<'a, D> {
+    let filters: Vec<_> = and
+        .iter()
+        .map(|filter| parse_filter(filter, means))
+        .collect();
+    Box::new(move |course| {
+        for filter in filters.iter() {
+            if !filter(course)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
     })
 }
 
-fn parse_or<'a, D: Dataset + 'a>(or: &'a Vec<Filter>) -> FilterFunc<'a, D> {
-    let filters: Vec<_> = or.iter().map(|filter| parse_filter(filter)).collect();
+fn parse_or<'a, D: Dataset + 'a>(or: &'a [Filter], means: &'a Means) -> FilterFunc<'a, D> {
+    let filters: Vec<_> = or
+        .iter()
+        .map(|filter| parse_filter(filter, means))
+        .collect();
     Box::new(move |course| {
-        Ok(filters
-            .iter()
-            .map(|filter| filter(course))
-            .collect::<Result<Vec<bool>, _>>()?
-            .into_iter()
-            .any(|b| b))
+        for filter in filters.iter() {
+            if filter(course)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
     })
 }
 
@@ -111,84 +712,734 @@ fn parse_comparison(
     } = args;
     match course.get(col) {
         Ok(Num(i)) => Ok(predicate(i, *val)),
-        Ok(_) => Err(anyhow!("Operation {} is not valid for {}", op, col)),
-        Err(_) => Err(anyhow!("Field {} does not exist", col)),
+        // Booleans compare as 0 and 1
+        Ok(Bool(b)) => Ok(predicate(OrderedFloat(f32::from(u8::from(b))), *val)),
+        Ok(Null) => Ok(false),
+        Ok(_) => Err(EngineError::TypeError {
+            op,
+            column: col.clone(),
+        }
+        .into()),
+        Err(_) => Err(EngineError::field_not_found(
+            col,
+            course.columns().iter().map(String::as_str),
+        )
+        .into()),
     }
 }
 
-static REGEX_CACHE: LazyLock<
-    Mutex<HashMap<String, Result<Regex, regex::Error>>>,
-    fn() -> Mutex<HashMap<String, Result<Regex, regex::Error>>>,
-> = LazyLock::new(|| Mutex::new(HashMap::<String, Result<Regex, regex::Error>>::new()));
-
-fn parse_filter<'a, D: Dataset + 'a>(filter: &'a Filter) -> FilterFunc<'a, D> {
-    match filter {
-        Filter::AND { and } => parse_and::<'a>(and),
-        Filter::OR { or } => parse_or::<'a>(or),
-        Filter::NOT { not } => Box::new(|course| Ok(!parse_filter(not)(course)?)),
-        Filter::LT { lt } => {
-            Box::new(move |course| parse_comparison(&lt, course, |a, b| a < b, "lt"))
-        }
-        Filter::GT { gt } => {
-            Box::new(move |course| parse_comparison(&gt, course, |a, b| a > b, "gt"))
-        }
-        Filter::EQ { eq } => Box::new(move |course| {
-            parse_comparison(&eq, course, |a, b| (a - b).abs() < EPSILON, "eq")
-        }),
-        Filter::IS { is } => Box::new(move |course| {
-            let KVPair {
-                key: col,
-                value: val,
-            } = is;
-            match course.get(col) {
-                Ok(Str(s)) => {
-                    let mut cache = REGEX_CACHE.lock().unwrap();
-                    let regex = cache
-                        .entry(val.clone())
-                        .or_insert_with(|| Regex::new(&format!("^{}$", val)))
-                        .clone()?;
-
-                    Ok(regex.is_match(&s))
+fn parse_string_match(
+    args: &KVPair<String>,
+    course: &impl Dataset,
+    predicate: impl FnOnce(&str, &str) -> bool,
+    op: &'static str,
+) -> anyhow::Result<bool> {
+    let KVPair {
+        key: col,
+        value: val,
+    } = args;
+    match course.get(col) {
+        Ok(Str(s)) => Ok(predicate(&s, val)),
+        Ok(Null) => Ok(false),
+        Ok(_) => Err(EngineError::TypeError {
+            op,
+            column: col.clone(),
+        }
+        .into()),
+        Err(_) => Err(EngineError::field_not_found(
+            col,
+            course.columns().iter().map(String::as_str),
+        )
+        .into()),
+    }
+}
+
+// Shared by IN and NIN, which only differ in whether membership is negated
+fn parse_membership(
+    args: &KVPair<Vec<String>>,
+    course: &impl Dataset,
+    negate: bool,
+    op: &'static str,
+) -> anyhow::Result<bool> {
+    let KVPair {
+        key: col,
+        value: list,
+    } = args;
+    match course.get(col) {
+        Ok(Str(s)) => Ok(list.contains(&s) != negate),
+        Ok(Null) => Ok(false),
+        Ok(_) => Err(EngineError::TypeError {
+            op,
+            column: col.clone(),
+        }
+        .into()),
+        Err(_) => Err(EngineError::field_not_found(
+            col,
+            course.columns().iter().map(String::as_str),
+        )
+        .into()),
+    }
+}
+
+// Scraped furniture reads like "Classroom-Movable Tables & Chairs"
+const FURNITURE_SEPARATORS: [char; 5] = ['-', '&', ',', '/', ';'];
+
+/// Whether a keyword is one of the text's separated phrases, or a word within one
+fn has_keyword(text: &str, keyword: &str) -> bool {
+    let keyword = keyword.trim().to_lowercase();
+    text.to_lowercase()
+        .split(FURNITURE_SEPARATORS)
+        .map(str::trim)
+        .any(|phrase| phrase == keyword || phrase.split_whitespace().any(|word| word == keyword))
+}
+
+fn parse_furniture_has(args: &KVPair<Vec<String>>, course: &impl Dataset) -> anyhow::Result<bool> {
+    let KVPair {
+        key: col,
+        value: keywords,
+    } = args;
+    match course.get(col) {
+        Ok(Str(s)) => Ok(keywords.iter().any(|keyword| has_keyword(&s, keyword))),
+        Ok(Null) => Ok(false),
+        Ok(_) => Err(EngineError::TypeError {
+            op: "furniturehas",
+            column: col.clone(),
+        }
+        .into()),
+        Err(_) => Err(EngineError::field_not_found(
+            col,
+            course.columns().iter().map(String::as_str),
+        )
+        .into()),
+    }
+}
+
+static REGEX_CACHE: LazyLock<
+    Mutex<HashMap<String, Result<Regex, regex::Error>>>,
+    fn() -> Mutex<HashMap<String, Result<Regex, regex::Error>>>,
+> = LazyLock::new(|| Mutex::new(HashMap::<String, Result<Regex, regex::Error>>::new()));
+
+/// Bounds on a compiled IS pattern, so a hostile regex fails to compile rather than
+/// exhausting memory
+pub const REGEX_SIZE_LIMIT: usize = 1 << 20;
+pub const REGEX_DFA_SIZE_LIMIT: usize = 1 << 20;
+
+fn compile_regex(source: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(source)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .dfa_size_limit(REGEX_DFA_SIZE_LIMIT)
+        .build()
+}
+
+fn average_columns<'f>(filter: &'f Filter, columns: &mut HashSet<&'f str>) {
+    match filter {
+        Filter::AND { and: filters } | Filter::OR { or: filters } => filters
+            .iter()
+            .for_each(|filter| average_columns(filter, columns)),
+        Filter::NOT { not } => not
+            .filters()
+            .iter()
+            .for_each(|filter| average_columns(filter, columns)),
+        Filter::ABOVEAVG { aboveavg: column } | Filter::BELOWAVG { belowavg: column } => {
+            columns.insert(column);
+        }
+        _ => {}
+    }
+}
+
+/// Means of every column the filter's ABOVEAVG and BELOWAVG use, over all the given rows
+pub fn column_means<'a, D: Dataset + 'a>(
+    filter: &Filter,
+    rows: impl Iterator<Item = &'a D>,
+) -> anyhow::Result<Means> {
+    let mut columns = HashSet::new();
+    average_columns(filter, &mut columns);
+    if columns.is_empty() {
+        return Ok(Means::new());
+    }
+
+    let mut sums: HashMap<&str, (f64, usize)> = columns
+        .into_iter()
+        .map(|column| (column, (0.0, 0)))
+        .collect();
+    for row in rows {
+        for (column, (sum, count)) in sums.iter_mut() {
+            match row.get(column) {
+                Ok(Num(n)) => {
+                    *sum += n.0 as f64;
+                    *count += 1;
+                }
+                // Nulls have no value to average
+                Ok(Null) => {}
+                Ok(_) => {
+                    return Err(EngineError::TypeError {
+                        op: "avg",
+                        column: column.to_string(),
+                    }
+                    .into());
+                }
+                Err(_) => {
+                    return Err(EngineError::field_not_found(
+                        column,
+                        row.columns().iter().map(String::as_str),
+                    )
+                    .into());
+                }
+            }
+        }
+    }
+    // No rows gives a NaN mean, which no value is above or below
+    Ok(sums
+        .into_iter()
+        .map(|(column, (sum, count))| (column.to_string(), sum / count as f64))
+        .collect())
+}
+
+fn parse_average(
+    col: &String,
+    course: &impl Dataset,
+    means: &Means,
+    predicate: fn(f64, f64) -> bool,
+) -> anyhow::Result<bool> {
+    let mean = means
+        .get(col)
+        .ok_or_else(|| anyhow!("No mean was computed for {}", col))?;
+    match course.get(col) {
+        Ok(Num(n)) => Ok(predicate(n.0 as f64, *mean)),
+        Ok(Null) => Ok(false),
+        Ok(_) => Err(EngineError::TypeError {
+            op: "avg",
+            column: col.clone(),
+        }
+        .into()),
+        Err(_) => Err(EngineError::field_not_found(
+            col,
+            course.columns().iter().map(String::as_str),
+        )
+        .into()),
+    }
+}
+
+fn parse_eqv(args: &KVPair<Value>, course: &impl Dataset) -> anyhow::Result<bool> {
+    let KVPair {
+        key: col,
+        value: val,
+    } = args;
+    match (course.get(col), val) {
+        (Ok(Num(n)), Num(value)) => Ok(n == *value || (n - value).abs() < EPSILON),
+        (Ok(Str(s)), Str(value)) => Ok(s == *value),
+        (Ok(Bool(b)), Bool(value)) => Ok(b == *value),
+        (Ok(Null), _) | (Ok(_), Null) => Ok(false),
+        (Ok(_), _) => Err(EngineError::TypeError {
+            op: "eqv",
+            column: col.clone(),
+        }
+        .into()),
+        (Err(_), _) => Err(EngineError::field_not_found(
+            col,
+            course.columns().iter().map(String::as_str),
+        )
+        .into()),
+    }
+}
+
+fn parse_range(args: &KVPair<RangeBounds>, course: &impl Dataset) -> anyhow::Result<bool> {
+    let KVPair {
+        key: col,
+        value: bounds,
+    } = args;
+    match course.get(col) {
+        Ok(Num(n)) => Ok(bounds.contains(n)),
+        Ok(Null) => Ok(false),
+        Ok(_) => Err(EngineError::TypeError {
+            op: "range",
+            column: col.clone(),
+        }
+        .into()),
+        Err(_) => Err(EngineError::field_not_found(
+            col,
+            course.columns().iter().map(String::as_str),
+        )
+        .into()),
+    }
+}
+
+fn parse_has(col: &str, course: &impl Dataset) -> anyhow::Result<bool> {
+    match course.get(col) {
+        Ok(value) => Ok(value != Null),
+        Err(_) => Err(EngineError::field_not_found(
+            col,
+            course.columns().iter().map(String::as_str),
+        )
+        .into()),
+    }
+}
+
+fn parse_is_int(col: &str, course: &impl Dataset) -> anyhow::Result<bool> {
+    match course.get(col) {
+        Ok(Num(n)) => Ok((n.0 - n.0.round()).abs() < EPSILON),
+        Ok(Null) => Ok(false),
+        Ok(_) => Err(EngineError::TypeError {
+            op: "isint",
+            column: col.to_string(),
+        }
+        .into()),
+        Err(_) => Err(EngineError::field_not_found(
+            col,
+            course.columns().iter().map(String::as_str),
+        )
+        .into()),
+    }
+}
+
+/// Builds the row predicate, with `means` from column_means for ABOVEAVG and BELOWAVG
+pub fn parse_filter<'a, D: Dataset + 'a>(
+    filter: &'a Filter,
+    means: &'a Means,
+) -> FilterFunc<'a, D> {
+    match filter {
+        Filter::AND { and } => parse_and::<'a>(and, means),
+        Filter::OR { or } => parse_or::<'a>(or, means),
+        Filter::NOT { not } => {
+            let all = parse_and(not.filters(), means);
+            Box::new(move |course| Ok(!all(course)?))
+        }
+        Filter::LT { lt } => {
+            Box::new(move |course| parse_comparison(&lt, course, |a, b| a < b, "lt"))
+        }
+        Filter::GT { gt } => {
+            Box::new(move |course| parse_comparison(&gt, course, |a, b| a > b, "gt"))
+        }
+        Filter::EQ { eq, tol } => {
+            let tolerance = tol.map_or(EPSILON, |tol| tol.0);
+            Box::new(move |course| {
+                parse_comparison(
+                    &eq,
+                    course,
+                    |a, b| a == b || (a - b).abs() < tolerance,
+                    "eq",
+                )
+            })
+        }
+        Filter::IS { is, mode, flags } => Box::new(move |course| {
+            let KVPair {
+                key: col,
+                value: val,
+            } = is;
+            let s = match course.get(col) {
+                Ok(Str(s)) => s,
+                // Booleans match their "true" or "false" spelling
+                Ok(Bool(b)) => b.to_string(),
+                Ok(Null) => return Ok(false),
+                Ok(_) => {
+                    return Err(EngineError::TypeError {
+                        op: "is",
+                        column: col.clone(),
+                    }
+                    .into());
+                }
+                Err(_) => {
+                    return Err(EngineError::field_not_found(
+                        col,
+                        course.columns().iter().map(String::as_str),
+                    )
+                    .into());
+                }
+            };
+            // Exact patterns, and wildcard ones without a `*`, never touch a regex
+            if mode.is_literal(val) {
+                return Ok(s == *val);
+            }
+
+            let source = match (mode, flags) {
+                // Everything but `*` matches literally, e.g. the `+` in "C++"
+                (IsMode::WILDCARD, _) => {
+                    let parts: Vec<_> = val.split('*').map(regex::escape).collect();
+                    format!("^{}$", parts.join(".*"))
+                }
+                (_, Some(flags)) if !flags.is_empty() => format!("(?{}){}", flags, val),
+                _ => val.clone(),
+            };
+            // The source already reflects the mode and flags, so it doubles as the cache key
+            let mut cache = REGEX_CACHE.lock().unwrap();
+            let regex = cache
+                .entry(source.clone())
+                .or_insert_with(|| compile_regex(&source))
+                .clone()?;
+
+            Ok(regex.is_match(&s))
+        }),
+        Filter::STARTSWITH { startswith } => Box::new(move |course| {
+            parse_string_match(
+                startswith,
+                course,
+                |s, val| s.starts_with(val),
+                "startswith",
+            )
+        }),
+        Filter::ENDSWITH { endswith } => Box::new(move |course| {
+            parse_string_match(endswith, course, |s, val| s.ends_with(val), "endswith")
+        }),
+        Filter::CONTAINS { contains } => Box::new(move |course| {
+            parse_string_match(contains, course, |s, val| s.contains(val), "contains")
+        }),
+        Filter::IN { r#in } => Box::new(move |course| parse_membership(r#in, course, false, "in")),
+        Filter::NIN { nin } => Box::new(move |course| parse_membership(nin, course, true, "nin")),
+        Filter::INNUM { innum } => {
+            // One EQ per listed value, built once rather than per row
+            let pairs: Vec<_> = innum
+                .value
+                .iter()
+                .map(|value| KVPair {
+                    key: innum.key.clone(),
+                    value: *value,
+                })
+                .collect();
+            Box::new(move |course| {
+                for pair in pairs.iter() {
+                    let equal = |a: OrderedFloat<f32>, b: OrderedFloat<f32>| {
+                        a == b || (a - b).abs() < EPSILON
+                    };
+                    if parse_comparison(pair, course, equal, "innum")? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            })
+        }
+        Filter::FURNITUREHAS { furniturehas } => {
+            Box::new(move |course| parse_furniture_has(furniturehas, course))
+        }
+        Filter::NEAR { near } => Box::new(move |course| parse_near(near, course)),
+        Filter::CMP { cmp } => Box::new(move |course| parse_cmp(cmp, course)),
+        Filter::SEARCH { search } => {
+            let needle = search.to_lowercase();
+            Box::new(move |course| {
+                Ok(course
+                    .columns()
+                    .iter()
+                    .any(|column| match course.get(column) {
+                        Ok(Str(s)) => s.to_lowercase().contains(&needle),
+                        _ => false,
+                    }))
+            })
+        }
+        Filter::ABOVEAVG { aboveavg } => Box::new(move |course| {
+            parse_average(aboveavg, course, means, |value, mean| value > mean)
+        }),
+        Filter::BELOWAVG { belowavg } => Box::new(move |course| {
+            parse_average(belowavg, course, means, |value, mean| value < mean)
+        }),
+        Filter::RANGE { range } => Box::new(move |course| parse_range(range, course)),
+        Filter::EQV { eqv } => Box::new(move |course| parse_eqv(eqv, course)),
+        Filter::ISINT { isint } => Box::new(move |course| parse_is_int(isint, course)),
+        Filter::HAS { has } => Box::new(move |course| parse_has(has, course)),
+        Filter::EMPTY {} => Box::new(|_| Ok(true)),
+    }
+}
+
+macro_rules! sort {
+    ($key:ident, $a:ident, $b:ident) => {
+        $a.get($key)
+            .unwrap()
+            .partial_cmp($b.get($key).unwrap())
+            .unwrap()
+    };
+}
+
+// Works in f64 and, unless wide, rounds back to f32 after every step, which matches plain f32 arithmetic.
+// Nulls are skipped, so a group of only nulls has no result.
+fn compute_aggregate(
+    mut init: f64,
+    func: impl Fn(f64, f64) -> f64,
+    op: &'static str,
+    column: &String,
+    data: &Vec<&BTreeMap<String, Value>>,
+    wide: bool,
+) -> anyhow::Result<Option<f64>> {
+    let mut any = false;
+    for item in data {
+        let num = match item
+            .get(column)
+            .ok_or_else(|| EngineError::field_not_found(column, item.keys().map(String::as_str)))?
+        {
+            Num(num) => num,
+            Null => continue,
+            _ => {
+                return Err(EngineError::TypeError {
+                    op,
+                    column: column.clone(),
+                }
+                .into());
+            }
+        };
+        any = true;
+        init = func(init, num.0 as f64);
+        if !wide {
+            init = init as f32 as f64;
+        }
+    }
+    Ok(any.then_some(init))
+}
+
+/// Linearly interpolates between the closest ranks of the sorted values, skipping nulls
+fn compute_percentile(
+    p: OrderedFloat<f32>,
+    column: &String,
+    data: &Vec<&BTreeMap<String, Value>>,
+) -> anyhow::Result<Option<OrderedFloat<f32>>> {
+    let mut values = data
+        .iter()
+        .filter_map(|item| match item.get(column) {
+            Some(Num(num)) => Some(Ok(*num)),
+            Some(Null) => None,
+            Some(_) => Some(Err(EngineError::TypeError {
+                op: "percentile",
+                column: column.clone(),
+            }
+            .into())),
+            None => Some(Err(EngineError::field_not_found(
+                column,
+                item.keys().map(String::as_str),
+            )
+            .into())),
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if values.is_empty() {
+        return Ok(None);
+    }
+    values.sort();
+
+    let rank = p / 100.0 * (values.len() - 1) as f32;
+    let lower = values[rank.floor() as usize];
+    let upper = values[rank.ceil() as usize];
+    Ok(Some(lower + (upper - lower) * (rank - rank.floor())))
+}
+
+/// The aggregate's value, or None when every item's value was null
+fn numeric_aggregate(
+    function: &String,
+    argument: &ApplyArg,
+    items: &Vec<&BTreeMap<String, Value>>,
+    wide: bool,
+) -> anyhow::Result<Option<f64>> {
+    if let ApplyArg::FILTERED { filter } = argument
+        && function == "COUNTWHERE"
+    {
+        return compute_count_where(filter, items).map(Some);
+    }
+    let column = argument.column()?;
+    // Nulls are skipped, so COUNT and AVG only count the items with a value
+    let present = items
+        .iter()
+        .filter(|item| item.get(column) != Some(&Null))
+        .count();
+    let n = if wide {
+        present as f64
+    } else {
+        present as f32 as f64
+    };
+
+    match function.as_str() {
+        "COUNT" => Ok(Some(n)),
+        "COUNTNONEMPTY" => compute_count_nonempty(column, items).map(Some),
+        "AVG" => compute_aggregate(0.0, |acc, val| acc + val / n, "avg", column, items, wide),
+        "SUM" => compute_aggregate(0.0, |acc, val| acc + val, "sum", column, items, wide),
+        // Overflows to infinity once the product leaves the f32 range (f64 with F64)
+        "PRODUCT" => compute_aggregate(1.0, |acc, val| acc * val, "product", column, items, wide),
+        "MAX" => compute_aggregate(f64::NEG_INFINITY, f64::max, "max", column, items, wide),
+        "MIN" => compute_aggregate(f64::INFINITY, f64::min, "min", column, items, wide),
+        "PERCENTILE" => match argument {
+            ApplyArg::PERCENTILE { p, .. } => {
+                compute_percentile(*p, column, items).map(|p| p.map(|p| p.0 as f64))
+            }
+            _ => Err(anyhow!(
+                r#"PERCENTILE requires {{"column": ..., "p": ...}}"#
+            )),
+        },
+        "COUNTWHERE" => Err(anyhow!(r#"COUNTWHERE requires {{"filter": ...}}"#)),
+        _ => Err(anyhow!("Unknown function {}", function)),
+    }
+}
+
+/// Number of items matching the filter
+fn compute_count_where(
+    filter: &Filter,
+    items: &Vec<&BTreeMap<String, Value>>,
+) -> anyhow::Result<f64> {
+    // ABOVEAVG and BELOWAVG compare against the group's own mean
+    let means = column_means(filter, items.iter().copied())?;
+    let matches = parse_filter::<BTreeMap<String, Value>>(filter, &means);
+    let mut count = 0;
+    for item in items.iter() {
+        count += usize::from(matches(item)?);
+    }
+    Ok(count as f64)
+}
+
+/// Number of items whose column holds a non-empty string, a finite number or a boolean
+fn compute_count_nonempty(
+    column: &String,
+    items: &Vec<&BTreeMap<String, Value>>,
+) -> anyhow::Result<f64> {
+    let mut count = 0;
+    for item in items.iter() {
+        let present = match item.get(column) {
+            Some(Str(s)) => !s.is_empty(),
+            Some(Num(n)) => n.is_finite(),
+            Some(Bool(_)) => true,
+            Some(Null) => false,
+            None => {
+                return Err(
+                    EngineError::field_not_found(column, item.keys().map(String::as_str)).into(),
+                );
+            }
+        };
+        count += usize::from(present);
+    }
+    Ok(count as f64)
+}
+
+/// Value of the column in the group's first or last item with one, ordered by `by` when given
+fn compute_first_last(
+    argument: &ApplyArg,
+    items: &[&BTreeMap<String, Value>],
+    last: bool,
+) -> anyhow::Result<Value> {
+    let column = argument.column()?;
+    let mut items = items.to_vec();
+    if let ApplyArg::SORTED { by, .. } = argument {
+        for item in items.iter() {
+            if !item.contains_key(by) {
+                return Err(
+                    EngineError::field_not_found(by, item.keys().map(String::as_str)).into(),
+                );
+            }
+        }
+        // Stable, so ties keep their dataset order
+        items.sort_by(|a, b| sort!(by, a, b));
+    }
+
+    // Nulls are skipped, and a group of only nulls gets null
+    let mut values = items
+        .iter()
+        .map(|item| {
+            item.get(column).ok_or_else(|| {
+                EngineError::field_not_found(column, item.keys().map(String::as_str))
+            })
+        })
+        .filter(|value| !matches!(value, Ok(Null)));
+    let value = if last {
+        values.next_back()
+    } else {
+        values.next()
+    };
+    Ok(value.transpose()?.cloned().unwrap_or(Null))
+}
+
+/// The id of the group's item with the highest or lowest value, the first such item on ties.
+/// Items with a null value are skipped, and a group of only those gets null.
+fn compute_arg_extreme(
+    argument: &ApplyArg,
+    items: &Vec<&BTreeMap<String, Value>>,
+    max: bool,
+) -> anyhow::Result<Value> {
+    let ApplyArg::IDENTIFIED { column, id } = argument else {
+        return Err(anyhow!(
+            r#"ARGMAX and ARGMIN require {{"column": ..., "id": ...}}"#
+        ));
+    };
+    let mut best: Option<(OrderedFloat<f32>, &Value)> = None;
+    for item in items.iter() {
+        let get = |column: &String| {
+            item.get(column).ok_or_else(|| {
+                EngineError::field_not_found(column, item.keys().map(String::as_str))
+            })
+        };
+        let value = match get(column)? {
+            Num(n) => *n,
+            Null => continue,
+            _ => {
+                return Err(EngineError::TypeError {
+                    op: if max { "argmax" } else { "argmin" },
+                    column: column.clone(),
                 }
-                Ok(_) => Err(anyhow!(r#"Operation "is" is not valid for {}"#, col)),
-                Err(_) => Err(anyhow!("Field {} does not exist", col)),
+                .into());
             }
-        }),
-        Filter::EMPTY {} => Box::new(|_| Ok(true)),
+        };
+        let item_id = get(id)?;
+        let better = best.is_none_or(|(extreme, _)| {
+            if max {
+                value > extreme
+            } else {
+                value < extreme
+            }
+        });
+        if better {
+            best = Some((value, item_id));
+        }
     }
+    Ok(best.map_or(Null, |(_, id)| id.clone()))
 }
 
-macro_rules! sort {
-    ($key:ident, $a:ident, $b:ident) => {
-        $a.get($key)
-            .unwrap()
-            .partial_cmp($b.get($key).unwrap())
-            .unwrap()
+// Longest string CONCAT may produce, so a huge group can't blow up the response
+const CONCAT_MAX_LEN: usize = 10_000;
+
+/// Joins the group's non-empty, non-null values in sorted order
+fn compute_concat(
+    argument: &ApplyArg,
+    items: &Vec<&BTreeMap<String, Value>>,
+) -> anyhow::Result<Value> {
+    let column = argument.column()?;
+    let (separator, distinct) = match argument {
+        ApplyArg::JOINED {
+            separator,
+            distinct,
+            ..
+        } => (separator.as_deref().unwrap_or(", "), *distinct),
+        _ => (", ", false),
     };
-}
 
-fn compute_aggregate(
-    mut init: OrderedFloat<f32>,
-    func: impl Fn(OrderedFloat<f32>, OrderedFloat<f32>) -> OrderedFloat<f32>,
-    op: &'static str,
-    column: &String,
-    data: &Vec<&BTreeMap<String, Value>>,
-) -> anyhow::Result<OrderedFloat<f32>> {
-    for item in data {
-        let Num(num) = item
-            .get(column)
-            .ok_or_else(|| anyhow!("Column {} does not exist", column))?
-        else {
-            return Err(anyhow!("Invalid operation {} on column {}", op, column));
-        };
-        init = func(init, *num)
+    let mut values = vec![];
+    for item in items {
+        match item.get(column) {
+            Some(Str(s)) if s.is_empty() => {}
+            Some(Null) => {}
+            Some(Str(s)) => values.push(s.as_str()),
+            Some(_) => {
+                return Err(EngineError::TypeError {
+                    op: "concat",
+                    column: column.clone(),
+                }
+                .into());
+            }
+            None => {
+                return Err(
+                    EngineError::field_not_found(column, item.keys().map(String::as_str)).into(),
+                );
+            }
+        }
+    }
+    values.sort();
+    if distinct {
+        values.dedup();
+    }
+
+    let joined = values.join(separator);
+    if joined.len() > CONCAT_MAX_LEN {
+        return Err(anyhow!(
+            "CONCAT of {} is longer than {} characters",
+            column,
+            CONCAT_MAX_LEN
+        ));
     }
-    Ok(init)
+    Ok(Str(joined))
 }
 
 fn handle_transformations(
     transformations: &Transformations,
-    columns_result: &Vec<BTreeMap<String, Value>>,
+    columns_result: &[BTreeMap<String, Value>],
+    wide: bool,
 ) -> anyhow::Result<Vec<BTreeMap<String, Value>>> {
     for column in columns_result.iter() {
         for transformation in transformations.group.iter() {
@@ -197,20 +1448,23 @@ fn handle_transformations(
             }
         }
     }
-    let grouped = columns_result.iter().into_group_map_by(|course| {
-        transformations
+    // Ordered by group keys so the output order is the same on every run
+    let mut grouped: BTreeMap<BTreeMap<String, Value>, Vec<_>> = BTreeMap::new();
+    for course in columns_result.iter() {
+        let group_keys = transformations
             .group
             .iter()
             .map(|group| (group.clone(), course.get(group).unwrap().clone()))
-            .collect::<BTreeMap<_, _>>()
-    });
+            .collect();
+        grouped.entry(group_keys).or_default().push(course);
+    }
+
+    let scale = 10f64.powi(transformations.precision.unwrap_or(DEFAULT_PRECISION) as i32);
 
     // Apply aggregates to each group
     grouped
         .into_iter()
         .map(|(group_keys, items)| {
-            let n = OrderedFloat(items.len() as f32);
-
             // Compute all aggregates and add to group result
             transformations
                 .apply
@@ -222,42 +1476,22 @@ fn handle_transformations(
                     } = aggregate;
                     let KVPair {
                         key: function,
-                        value: column,
+                        value: argument,
                     } = inner;
-
                     let result = match function.as_str() {
-                        "COUNT" => Ok(n),
-                        "AVG" => compute_aggregate(
-                            OrderedFloat(0.0),
-                            |acc, val| acc + val / n,
-                            "avg",
-                            column,
-                            &items,
-                        ),
-                        "SUM" => compute_aggregate(
-                            OrderedFloat(0.0),
-                            |acc, val| acc + val,
-                            "sum",
-                            column,
-                            &items,
-                        ),
-                        "MAX" => compute_aggregate(
-                            OrderedFloat(f32::NEG_INFINITY),
-                            |acc, val| std::cmp::max(acc, val),
-                            "max",
-                            column,
-                            &items,
-                        ),
-                        "MIN" => compute_aggregate(
-                            OrderedFloat(f32::INFINITY),
-                            |acc, val| std::cmp::min(acc, val),
-                            "min",
-                            column,
-                            &items,
-                        ),
-                        _ => Err(anyhow!("Unknown function {}", function)),
-                    }
-                    .map(|result| Num(OrderedFloat::from((result * 100.0).round() / 100.0)))?;
+                        "FIRST" => compute_first_last(argument, &items, false),
+                        "LAST" => compute_first_last(argument, &items, true),
+                        "CONCAT" => compute_concat(argument, &items),
+                        "ARGMAX" => compute_arg_extreme(argument, &items, true),
+                        "ARGMIN" => compute_arg_extreme(argument, &items, false),
+                        _ => numeric_aggregate(function, argument, &items, wide).map(|result| {
+                            result.map_or(Null, |result| {
+                                Num(OrderedFloat::from(
+                                    ((result * scale).round() / scale) as f32,
+                                ))
+                            })
+                        }),
+                    }?;
 
                     acc.insert(apply_key.clone(), result);
                     Ok(acc)
@@ -266,70 +1500,693 @@ fn handle_transformations(
         .collect::<anyhow::Result<Vec<_>>>()
 }
 
+/// Whether the row has a value to order by, a null counting as missing
+fn has_order_value(row: &ResultRow, key: &str) -> bool {
+    row.get(key).is_some_and(|value| *value != Null)
+}
+
+/// Compares rows by each ORDER key in turn, in the ORDER direction.
+/// Rows with a null or missing key go last or first by `nulls_last`, whatever the direction.
+fn order_cmp(
+    order: &Order,
+    nulls_last: Option<bool>,
+) -> anyhow::Result<impl Fn(&ResultRow, &ResultRow) -> Ordering + '_> {
+    let reverse = match order {
+        Order::ONE(_) => false,
+        Order::KEYED { dir, .. } | Order::MANY { dir, .. } => match dir.as_str() {
+            "UP" => false,
+            "DOWN" => true,
+            _ => {
+                return Err(anyhow!("Invalid ordering {}, expected UP or DOWN", dir));
+            }
+        },
+    };
+    Ok(move |a: &ResultRow, b: &ResultRow| {
+        for key in order.keys() {
+            let (has_a, has_b) = (has_order_value(a, key), has_order_value(b, key));
+            let ordering = if has_a != has_b {
+                let missing_last = has_b.cmp(&has_a);
+                if nulls_last == Some(false) {
+                    missing_last.reverse()
+                } else {
+                    missing_last
+                }
+            } else if !has_a {
+                Ordering::Equal
+            } else if reverse {
+                sort!(key, b, a)
+            } else {
+                sort!(key, a, b)
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    })
+}
+
 fn handle_order(
     order: &Order,
-    columns_result: &mut Vec<BTreeMap<String, Value>>,
+    nulls_last: Option<bool>,
+    columns_result: &mut [ResultRow],
 ) -> anyhow::Result<()> {
-    match order {
-        Order::ONE(order) => {
-            let all_have_column = columns_result.iter().all(|row| row.contains_key(order));
+    let cmp = order_cmp(order, nulls_last)?;
+    // Without NULLS_LAST every row needs a value for every key
+    for key in order.keys().iter().filter(|_| nulls_last.is_none()) {
+        let Some(row) = columns_result.iter().find(|row| !has_order_value(row, key)) else {
+            continue;
+        };
+        return Err(match order {
+            _ if row.contains_key(key) => anyhow!(
+                "Order column '{}' is null in some rows, which requires NULLS_LAST",
+                key
+            ),
+            Order::ONE(_) | Order::KEYED { .. } => {
+                anyhow!("Order column '{}' not found in results", key)
+            }
+            Order::MANY { .. } => anyhow!("Key {} not found", key),
+        });
+    }
+    // Sorting by the whole row first breaks ties the same way whatever order the rows came in
+    columns_result.sort();
+    columns_result.sort_by(&cmp);
+    Ok(())
+}
+
+/// Where a page ends: the ORDER key values of its last row, and how many rows with exactly
+/// those values it had returned. Rows with equal keys keep a stable order, so skipping that
+/// many of them resumes with no gaps or overlaps.
+#[derive(Serialize, Deserialize)]
+struct Cursor {
+    // The last row's ORDER keys, without any it lacked under NULLS_LAST
+    after: ResultRow,
+    skip: usize,
+}
+
+impl Cursor {
+    /// The cursor for a page ending just before `end` in the sorted rows
+    fn at(
+        order: &Order,
+        rows: &[ResultRow],
+        end: usize,
+        cmp: impl Fn(&ResultRow, &ResultRow) -> Ordering,
+    ) -> Cursor {
+        let last = &rows[end - 1];
+        let first_equal = rows[..end].partition_point(|row| cmp(row, last) == Ordering::Less);
+        Cursor {
+            after: order
+                .keys()
+                .iter()
+                .filter_map(|key| Some((key.clone(), last.get(key)?.clone())))
+                .collect(),
+            skip: end - first_equal,
+        }
+    }
+
+    fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap())
+    }
+
+    fn decode(order: &Order, cursor: &str) -> anyhow::Result<Cursor> {
+        let invalid = || anyhow!("Invalid cursor {}", cursor);
+        let bytes = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+        let cursor: Cursor = serde_json::from_slice(&bytes).map_err(|_| invalid())?;
+        if cursor.after.keys().any(|key| !order.keys().contains(key)) {
+            return Err(invalid());
+        }
+        Ok(cursor)
+    }
+
+    /// Index of the first row after the cursor, found by binary search over the sorted rows
+    fn start(&self, rows: &[ResultRow], cmp: impl Fn(&ResultRow, &ResultRow) -> Ordering) -> usize {
+        let first_equal = rows.partition_point(|row| cmp(row, &self.after) == Ordering::Less);
+        first_equal.saturating_add(self.skip).min(rows.len())
+    }
+}
+
+/// Deepest AND/OR/NOT nesting accepted, so hostile queries can't overflow the stack
+pub const MAX_FILTER_DEPTH: usize = 50;
+
+// Gives up as soon as the limit is passed, so the walk itself stays shallow
+fn check_depth(filter: &Filter, depth: usize) -> anyhow::Result<()> {
+    if depth > MAX_FILTER_DEPTH {
+        return Err(anyhow!("Filter nesting too deep"));
+    }
+    match filter {
+        Filter::AND { and: filters } | Filter::OR { or: filters } => filters
+            .iter()
+            .try_for_each(|filter| check_depth(filter, depth + 1)),
+        Filter::NOT { not } => not
+            .filters()
+            .iter()
+            .try_for_each(|filter| check_depth(filter, depth + 1)),
+        _ => Ok(()),
+    }
+}
+
+fn validate_logic(op: &'static str, filters: &[Filter]) -> anyhow::Result<()> {
+    // An empty AND would match everything and an empty OR nothing, which is never intended
+    if filters.is_empty() {
+        return Err(anyhow!("{} requires at least one filter", op));
+    }
+    filters.iter().try_for_each(validate_filter)
+}
 
-            if !all_have_column {
-                return Err(anyhow!("Order column '{}' not found in results", order));
+fn validate_filter(filter: &Filter) -> anyhow::Result<()> {
+    match filter {
+        Filter::AND { and } => validate_logic("AND", and),
+        Filter::OR { or } => validate_logic("OR", or),
+        Filter::NOT {
+            not: NotArg::ONE(not),
+        } => validate_filter(not),
+        Filter::NOT {
+            not: NotArg::MANY(filters),
+        } => validate_logic("NOT", filters),
+        Filter::EQ { tol: Some(tol), .. } if tol.0 < 0.0 => {
+            Err(anyhow!("EQ tolerance must be non-negative, got {}", tol))
+        }
+        Filter::RANGE {
+            range:
+                KVPair {
+                    value:
+                        RangeBounds {
+                            min: None,
+                            max: None,
+                        },
+                    ..
+                },
+        } => Err(anyhow!("RANGE requires a min or a max")),
+        Filter::RANGE {
+            range:
+                KVPair {
+                    value:
+                        RangeBounds {
+                            min: Some(min),
+                            max: Some(max),
+                        },
+                    ..
+                },
+        } if min > max => Err(anyhow!("RANGE min {} is greater than its max {}", min, max)),
+        Filter::IS {
+            mode,
+            flags: Some(flags),
+            ..
+        } => {
+            if *mode != IsMode::REGEX {
+                return Err(anyhow!(r#"IS flags require "mode": "regex""#));
+            }
+            match flags.chars().find(|flag| !REGEX_FLAGS.contains(*flag)) {
+                Some(flag) => Err(anyhow!(
+                    "Unknown regex flag {}, expected any of {}",
+                    flag,
+                    REGEX_FLAGS
+                )),
+                None => Ok(()),
             }
-            columns_result.sort_by(|a, b| sort!(order, a, b));
         }
-        Order::MANY { dir, keys } => {
-            let reverse = match dir.as_str() {
-                "UP" => false,
-                "DOWN" => true,
-                _ => {
-                    return Err(anyhow!("Invalid ordering {}, expected UP or DOWN", dir));
-                }
-            };
-            for key in keys.iter() {
-                for row in columns_result.iter() {
-                    if !row.contains_key(key) {
-                        return Err(anyhow!("Key {} not found", key));
-                    }
+        _ => Ok(()),
+    }
+}
+
+/// Server policy rejecting queries that would return the whole dataset without a LIMIT
+pub fn check_bounded(query: &Query) -> anyhow::Result<()> {
+    if matches!(query.filter(), Filter::EMPTY {}) && query.options.limit.is_none() {
+        return Err(anyhow!("Unbounded query requires a LIMIT"));
+    }
+    Ok(())
+}
+
+// Collects the columns a filter names, returning true if it also reads columns it doesn't name
+fn filter_columns<'f>(filter: &'f Filter, columns: &mut Vec<&'f str>) -> bool {
+    // Every subfilter is walked, even once one reads all columns
+    let nested = |filters: &'f [Filter], columns: &mut Vec<&'f str>| {
+        let mut reads_all = false;
+        for filter in filters {
+            reads_all |= filter_columns(filter, columns);
+        }
+        reads_all
+    };
+    let column = match filter {
+        Filter::AND { and: filters } | Filter::OR { or: filters } => {
+            return nested(filters, columns);
+        }
+        Filter::NOT { not } => return nested(not.filters(), columns),
+        Filter::LT { lt: pair } | Filter::GT { gt: pair } | Filter::EQ { eq: pair, .. } => {
+            &pair.key
+        }
+        Filter::IS { is: pair, .. }
+        | Filter::STARTSWITH { startswith: pair }
+        | Filter::ENDSWITH { endswith: pair }
+        | Filter::CONTAINS { contains: pair } => &pair.key,
+        Filter::RANGE { range } => &range.key,
+        Filter::EQV { eqv } => &eqv.key,
+        Filter::IN { r#in: pair }
+        | Filter::NIN { nin: pair }
+        | Filter::FURNITUREHAS { furniturehas: pair } => &pair.key,
+        Filter::INNUM { innum } => &innum.key,
+        Filter::CMP { cmp } => {
+            columns.push(&cmp.right);
+            &cmp.left
+        }
+        Filter::ABOVEAVG { aboveavg: column }
+        | Filter::BELOWAVG { belowavg: column }
+        | Filter::ISINT { isint: column }
+        | Filter::HAS { has: column } => column,
+        // NEAR finds its lat and lon columns by suffix, and SEARCH reads every string column
+        Filter::NEAR { .. } | Filter::SEARCH { .. } => return true,
+        Filter::EMPTY {} => return false,
+    };
+    columns.push(column);
+    false
+}
+
+/// Server policy rejecting queries that use a denied column anywhere.
+/// Queries reading columns they don't name, like `*` or SEARCH, may use every column.
+pub fn check_denied(query: &Query, denied: &BTreeSet<String>) -> anyhow::Result<()> {
+    if denied.is_empty() {
+        return Ok(());
+    }
+    let mut columns: Vec<&str> = query.options.columns.iter().map(String::as_str).collect();
+    let mut reads_all = filter_columns(query.filter(), &mut columns) || query.options.selects_all();
+    if let Some(order) = &query.options.order {
+        columns.extend(order.keys().iter().map(String::as_str));
+    }
+    if let Some(rank) = &query.options.percent_rank {
+        columns.push(&rank.column);
+        columns.extend(rank.partition.as_deref());
+    }
+    if let Some(transformations) = &query.transformations {
+        columns.extend(transformations.group.iter().map(String::as_str));
+        for apply in &transformations.apply {
+            match &apply.value.value {
+                ApplyArg::FILTERED { filter } => reads_all |= filter_columns(filter, &mut columns),
+                ApplyArg::SORTED { column, by } => columns.extend([column.as_str(), by.as_str()]),
+                ApplyArg::IDENTIFIED { column, id } => {
+                    columns.extend([column.as_str(), id.as_str()])
                 }
+                arg => columns.extend(arg.column().ok().map(String::as_str)),
             }
+        }
+    }
 
-            let sort_funcs: Vec<_> = keys
+    let used = if reads_all {
+        denied.iter().next()
+    } else {
+        // Mixed-case names resolve to the same columns, so they are denied too
+        columns.into_iter().find_map(|column| {
+            denied
                 .iter()
-                .map(|key| {
-                    Box::new(|a: &BTreeMap<String, Value>, b: &BTreeMap<String, Value>| {
-                        if reverse {
-                            sort!(key, b, a)
-                        } else {
-                            sort!(key, a, b)
-                        }
-                    })
+                .find(|denied| denied.eq_ignore_ascii_case(column))
+        })
+    };
+    match used {
+        Some(column) => Err(anyhow!("Column {} is not available", column)),
+        None => Ok(()),
+    }
+}
+
+/// Rejects STRIP_PREFIX and KEY_CASE renaming two response keys to the same key, which would
+/// keep only one of the columns. The dataset prefix is the part of a non-apply key up to its `_`.
+fn check_output_keys(query: &Query) -> anyhow::Result<()> {
+    if !query.options.renames_keys() {
+        return Ok(());
+    }
+    let apply_keys: Vec<&String> = query
+        .transformations
+        .iter()
+        .flat_map(|transformations| transformations.apply.iter().map(|apply| &apply.key))
+        .collect();
+    // `*` keeps the dataset's own columns, which rename apart, or the GROUP and APPLY keys
+    let keys: Vec<&String> = match &query.transformations {
+        _ if !query.options.selects_all() => query.options.columns.iter().collect(),
+        Some(transformations) => transformations
+            .group
+            .iter()
+            .chain(apply_keys.clone())
+            .collect(),
+        None => return Ok(()),
+    };
+    let prefix = keys
+        .iter()
+        .filter(|key| !apply_keys.contains(key))
+        .find_map(|key| key.split_once('_'))
+        .map_or(String::new(), |(prefix, _)| format!("{}_", prefix));
+
+    let mut renamed = HashMap::new();
+    for key in keys {
+        let output = query.options.output_key(&prefix, key);
+        if let Some(other) = renamed.insert(output.clone(), key) {
+            return Err(anyhow!(
+                "Columns {} and {} would both be returned as {}",
+                other,
+                key,
+                output
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Structural checks that don't need a dataset, so queries can be validated without running them
+pub fn validate_query(query: &Query) -> anyhow::Result<()> {
+    check_depth(query.filter(), 1)?;
+    validate_filter(query.filter())?;
+
+    if query.options.columns.is_empty() {
+        return Err(anyhow!("COLUMNS must be a non-empty array"));
+    }
+    let selects_all = query.options.selects_all();
+    if selects_all && query.options.columns.len() > 1 {
+        return Err(anyhow!(
+            "{} cannot be combined with other COLUMNS",
+            ALL_COLUMNS
+        ));
+    }
+    // A repeated column would be a repeated key in an object row
+    let mut columns = HashSet::new();
+    if let Some(column) = query.options.columns.iter().find(|c| !columns.insert(*c)) {
+        return Err(anyhow!("Duplicate column '{}' in COLUMNS", column));
+    }
+    check_output_keys(query)?;
+
+    if let Some(tolerance) = query.options.distinct_tolerance {
+        if !query.options.distinct {
+            return Err(anyhow!("DISTINCT_TOLERANCE requires DISTINCT"));
+        }
+        if tolerance.0 <= 0.0 || !tolerance.0.is_finite() {
+            return Err(anyhow!(
+                "DISTINCT_TOLERANCE must be positive, got {}",
+                tolerance
+            ));
+        }
+    }
+
+    if let Some(rank) = &query.options.percent_rank
+        && rank.name.is_empty()
+    {
+        return Err(anyhow!("PERCENT_RANK name must not be empty"));
+    }
+
+    if query.options.cursor.is_some() {
+        if query.options.order.is_none() {
+            return Err(anyhow!("CURSOR requires ORDER"));
+        }
+        if query.options.offset.is_some() {
+            return Err(anyhow!("CURSOR cannot be combined with OFFSET"));
+        }
+    }
+
+    // Ordering runs after projection, which has already dropped every other column
+    if let Some(order) = &query.options.order
+        && !selects_all
+    {
+        for key in order.keys() {
+            if !query.options.columns.contains(key) {
+                return Err(anyhow!("Cannot order by {}: not in COLUMNS", key));
+            }
+        }
+    }
+
+    if let Some(transformations) = &query.transformations {
+        if transformations.group.is_empty() {
+            return Err(anyhow!("GROUP must be a non-empty array"));
+        }
+        // Group keys must name a dataset field, e.g. "sections_dept"
+        for group in transformations.group.iter() {
+            match group.split_once('_') {
+                Some((prefix, field)) if !prefix.is_empty() && !field.is_empty() => {}
+                _ => return Err(anyhow!("Unknown group {}", group)),
+            }
+        }
+
+        if let Some(precision) = transformations.precision
+            && precision > MAX_PRECISION
+        {
+            return Err(anyhow!(
+                "PRECISION must be at most {}, got {}",
+                MAX_PRECISION,
+                precision
+            ));
+        }
+
+        // A repeated apply key would silently overwrite the earlier aggregate in the result row
+        let mut apply_keys = HashSet::new();
+        for KVPair { key, value } in transformations.apply.iter() {
+            if !apply_keys.insert(key) {
+                return Err(anyhow!("Duplicate apply key '{}'", key));
+            }
+            if let ApplyArg::PERCENTILE { p, .. } = &value.value
+                && !(0.0..=100.0).contains(&p.0)
+            {
+                return Err(anyhow!("Percentile must be between 0 and 100, got {}", p));
+            }
+            if let ApplyArg::FILTERED { filter } = &value.value {
+                check_depth(filter, 1)?;
+                validate_filter(filter)?;
+            }
+        }
+
+        // After grouping, only group keys and apply keys survive in each row
+        for column in query.options.columns.iter().filter(|_| !selects_all) {
+            if !transformations.group.contains(column) && !apply_keys.contains(column) {
+                return Err(anyhow!("Column {} must be in GROUP or APPLY", column));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Rejects GROUP keys the dataset has no column for, which validate_query can't know without one
+pub fn check_group_columns(query: &Query, columns: &[String]) -> anyhow::Result<()> {
+    let Some(transformations) = &query.transformations else {
+        return Ok(());
+    };
+    match transformations
+        .group
+        .iter()
+        .find(|group| !columns.contains(group))
+    {
+        Some(group) => {
+            Err(EngineError::field_not_found(group, columns.iter().map(String::as_str)).into())
+        }
+        None => Ok(()),
+    }
+}
+
+// The column names COLUMNS, ORDER, PERCENT_RANK, GROUP, APPLY and an indexed WHERE use to look
+// up row keys
+fn column_keys_mut(query: &mut Query) -> Vec<&mut String> {
+    let mut keys: Vec<&mut String> = query.options.columns.iter_mut().collect();
+    // Other filters resolve mixed case through Dataset::get, but the index looks columns up by name
+    match &mut query.r#where {
+        Some(Filter::IS { is: pair, .. }) => keys.push(&mut pair.key),
+        Some(Filter::EQ { eq: pair, .. }) => keys.push(&mut pair.key),
+        Some(Filter::RANGE { range }) => keys.push(&mut range.key),
+        _ => {}
+    }
+    match &mut query.options.order {
+        Some(Order::ONE(key) | Order::KEYED { key, .. }) => keys.push(key),
+        Some(Order::MANY { keys: order, .. }) => keys.extend(order.iter_mut()),
+        None => {}
+    }
+    if let Some(rank) = &mut query.options.percent_rank {
+        keys.push(&mut rank.column);
+        keys.extend(rank.partition.as_mut());
+    }
+    if let Some(transformations) = &mut query.transformations {
+        keys.extend(transformations.group.iter_mut());
+        for apply in &mut transformations.apply {
+            match &mut apply.value.value {
+                ApplyArg::COLUMN(column)
+                | ApplyArg::PERCENTILE { column, .. }
+                | ApplyArg::JOINED { column, .. } => keys.push(column),
+                ApplyArg::SORTED { column, by: other }
+                | ApplyArg::IDENTIFIED { column, id: other } => keys.extend([column, other]),
+                // Filters resolve mixed case through Dataset::get
+                ApplyArg::FILTERED { .. } => {}
+            }
+        }
+    }
+    keys
+}
+
+/// The query with each column name that only differs from a dataset column in case spelled
+/// like the column, as rows are keyed by the dataset's spelling
+pub fn resolve_case(query: &Query, columns: &[String]) -> Query {
+    let mut resolved = query.clone();
+    let apply_keys: HashSet<String> = query
+        .transformations
+        .iter()
+        .flat_map(|transformations| transformations.apply.iter().map(|apply| apply.key.clone()))
+        .collect();
+    for key in column_keys_mut(&mut resolved) {
+        if apply_keys.contains(key.as_str()) || columns.contains(key) {
+            continue;
+        }
+        if let Some(column) = columns
+            .iter()
+            .find(|column| column.eq_ignore_ascii_case(key))
+        {
+            key.clone_from(column);
+        }
+    }
+    resolved
+}
+
+/// Runs the query over every row; D may be `Box<dyn Dataset>` to mix dataset types.
+/// The server goes through the index, so this is the unindexed baseline tests compare against.
+#[cfg(test)]
+pub fn execute_query<D: Dataset>(query: &Query, dataset: &[D]) -> anyhow::Result<Vec<ResultRow>> {
+    let columns = dataset.first().map(Dataset::columns).unwrap_or_default();
+    let query = resolve_case(query, &columns);
+    run_query(&query, dataset.iter()).map(|(rows, _)| rows)
+}
+
+/// Like execute_query, but only scans the rows the index says can match
+pub fn execute_indexed_query<D: Dataset>(
+    query: &Query,
+    dataset: &Indexed<D>,
+) -> anyhow::Result<Vec<ResultRow>> {
+    execute_paged_query(query, dataset).map(|(rows, _)| rows)
+}
+
+/// execute_indexed_query, also returning the page details when LIMIT or OFFSET was given
+pub fn execute_paged_query<D: Dataset>(
+    query: &Query,
+    dataset: &Indexed<D>,
+) -> anyhow::Result<PagedResult> {
+    let columns = dataset
+        .rows
+        .first()
+        .map(Dataset::columns)
+        .unwrap_or_default();
+    let query = &resolve_case(query, &columns);
+    // Invalid filters are reported before the index looks at them
+    validate_query(query)?;
+    match dataset.candidates(query.filter()) {
+        Some(rows) => run_query(query, rows.into_iter().map(|i| &dataset.rows[i])),
+        None => run_query(query, dataset.rows.iter()),
+    }
+}
+
+// Exact Value equality would keep 90.0 and 90.00001 apart
+#[derive(PartialEq, Eq, Hash)]
+enum DistinctKey {
+    Bucket(i64),
+    Exact(Value),
+}
+
+/// Keeps the first of each set of equal rows, bucketing numbers when a tolerance is given
+fn distinct_rows(
+    rows: Vec<BTreeMap<String, Value>>,
+    tolerance: Option<OrderedFloat<f32>>,
+) -> Vec<BTreeMap<String, Value>> {
+    let mut seen = HashSet::new();
+    rows.into_iter()
+        .filter(|row| {
+            let key: Vec<_> = row
+                .values()
+                .map(|value| match (value, tolerance) {
+                    (Num(n), Some(tolerance)) => {
+                        DistinctKey::Bucket((n.0 as f64 / tolerance.0 as f64).round() as i64)
+                    }
+                    _ => DistinctKey::Exact(value.clone()),
                 })
                 .collect();
+            seen.insert(key)
+        })
+        .collect()
+}
 
-            columns_result.sort_by(|a, b| {
-                for sort_func in sort_funcs.iter() {
-                    match sort_func(a, b) {
-                        Ordering::Equal => continue,
-                        other => return other,
-                    }
+/// Adds the PERCENT_RANK column, the share of the partition's other rows with a lower value,
+/// so the partition's lowest row gets 0 and its highest 100. Rows without a value get null.
+fn add_percent_rank(rank: &PercentRank, rows: &mut [ResultRow]) -> anyhow::Result<()> {
+    let mut keys = Vec::with_capacity(rows.len());
+    let mut partitions: HashMap<Value, Vec<OrderedFloat<f32>>> = HashMap::new();
+    for row in rows.iter() {
+        if row.contains_key(&rank.name) {
+            return Err(anyhow!(
+                "PERCENT_RANK name {} is already a column",
+                rank.name
+            ));
+        }
+        let get = |column: &String| {
+            row.get(column)
+                .ok_or_else(|| EngineError::field_not_found(column, row.keys().map(String::as_str)))
+        };
+        let partition = match &rank.partition {
+            Some(partition) => get(partition)?.clone(),
+            None => Null,
+        };
+        let value = match get(&rank.column)? {
+            Num(n) => Some(*n),
+            Null => None,
+            _ => {
+                return Err(EngineError::TypeError {
+                    op: "percent_rank",
+                    column: rank.column.clone(),
                 }
-                Ordering::Equal
-            });
+                .into());
+            }
+        };
+        if let Some(value) = value {
+            partitions.entry(partition.clone()).or_default().push(value);
         }
+        keys.push((partition, value));
+    }
+    for values in partitions.values_mut() {
+        values.sort();
+    }
+
+    for (row, (partition, value)) in rows.iter_mut().zip(keys) {
+        let percent = match value {
+            Some(value) => {
+                let values = &partitions[&partition];
+                let below = values.partition_point(|other| *other < value);
+                // A partition of one row has no others to be above
+                let others = (values.len() - 1).max(1);
+                Num(OrderedFloat(below as f32 / others as f32 * 100.0))
+            }
+            None => Null,
+        };
+        row.insert(rank.name.clone(), percent);
     }
     Ok(())
 }
 
-pub fn execute_query<D: Dataset>(
+/// Keeps only the requested columns of each row
+fn project(columns: &[String], rows: Vec<ResultRow>) -> anyhow::Result<Vec<ResultRow>> {
+    rows.into_iter()
+        .map(|course| -> anyhow::Result<ResultRow> {
+            let mut map = BTreeMap::new();
+            for column in columns {
+                map.insert(
+                    column.clone(),
+                    course
+                        .get(column)
+                        .ok_or_else(|| {
+                            EngineError::field_not_found(column, course.keys().map(String::as_str))
+                        })?
+                        .clone(),
+                );
+            }
+            Ok(map)
+        })
+        .collect()
+}
+
+fn run_query<'a, D: Dataset + 'a>(
     query: &Query,
-    dataset: &Vec<D>,
-) -> anyhow::Result<Vec<BTreeMap<String, Value>>> {
-    let filter = parse_filter(&query.r#where);
+    dataset: impl Iterator<Item = &'a D> + Clone,
+) -> anyhow::Result<PagedResult> {
+    validate_query(query)?;
+
+    // The index only narrows a top-level IS, EQ or RANGE, so these are every row whenever a mean is needed
+    let means = column_means(query.filter(), dataset.clone())?;
+    let filter = parse_filter(query.filter(), &means);
 
     let mut filter_result = dataset
-        .into_iter()
         .filter_map(|item| -> Option<anyhow::Result<_>> {
             match filter(item) {
                 Ok(true) => Some(Ok(item)),
@@ -342,7 +2199,7 @@ pub fn execute_query<D: Dataset>(
             Ok(collected
                 .into_iter()
                 .map(|item| {
-                    item.get_all()
+                    item.columns()
                         .iter()
                         .map(|key| (key.to_string(), item.get(key).unwrap()))
                         .collect::<BTreeMap<_, _>>()
@@ -350,36 +2207,68 @@ pub fn execute_query<D: Dataset>(
                 .collect::<Vec<_>>())
         })?;
 
+    if let Some(rank) = &query.options.percent_rank {
+        add_percent_rank(rank, &mut filter_result)?;
+    }
+
     if let Some(transform) = &query.transformations {
-        filter_result = handle_transformations(transform, &filter_result)?;
+        filter_result = handle_transformations(transform, &filter_result, query.options.f64)?;
     }
 
     if filter_result.len() > 5000 {
-        return Err(anyhow!("Result too large"));
+        return Err(EngineError::ResultTooLargeError.into());
     }
 
-    let mut columns_result = filter_result
-        .into_iter()
-        .map(|course| -> anyhow::Result<BTreeMap<String, Value>> {
-            let mut map = BTreeMap::new();
-            for column in &query.options.columns {
-                map.insert(
-                    column.clone(),
-                    course
-                        .get(column)
-                        .ok_or_else(|| anyhow!("Unknown column {}", column))?
-                        .clone(),
-                );
-            }
-            Ok(map)
-        })
-        .collect::<anyhow::Result<Vec<_>>>()?;
+    // Rows already hold exactly the dataset's columns, or the group and apply keys
+    let mut columns_result = if query.options.selects_all() {
+        filter_result
+    } else {
+        project(&query.options.columns, filter_result)?
+    };
+    if query.options.distinct {
+        columns_result = distinct_rows(columns_result, query.options.distinct_tolerance);
+    }
 
     if let Some(order) = &query.options.order {
-        handle_order(order, &mut columns_result)?;
+        handle_order(order, query.options.nulls_last, &mut columns_result)?;
     }
 
-    Ok(columns_result)
+    // Paging happens last, so the 5000 row limit above still applies to the total
+    let Options {
+        limit,
+        offset,
+        ref cursor,
+        ..
+    } = query.options;
+    if limit.is_none() && offset.is_none() && cursor.is_none() {
+        return Ok((columns_result, None));
+    }
+    let total = columns_result.len();
+    let start = match (cursor, &query.options.order) {
+        (Some(cursor), Some(order)) => Cursor::decode(order, cursor)?
+            .start(&columns_result, order_cmp(order, query.options.nulls_last)?),
+        _ => offset.unwrap_or(0).min(total),
+    };
+    let end = limit.map_or(total, |limit| start.saturating_add(limit).min(total));
+    let next_cursor = match &query.options.order {
+        Some(order) if start < end && end < total => Some(
+            Cursor::at(
+                order,
+                &columns_result,
+                end,
+                order_cmp(order, query.options.nulls_last)?,
+            )
+            .encode(),
+        ),
+        _ => None,
+    };
+    let page = Page {
+        total,
+        offset: start,
+        has_more: end < total,
+        next_cursor,
+    };
+    Ok((columns_result.drain(start..end).collect(), Some(page)))
 }
 
 #[cfg(test)]
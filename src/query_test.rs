@@ -1,8 +1,16 @@
-use crate::dataset::load_dataset;
-use crate::query::{Filter, Query, execute_query};
-use crate::types::KVPair;
+use crate::dataset::{Section, load_dataset};
+use crate::errors::EngineError;
+use crate::index::Indexed;
+use crate::rooms_dataset::Room;
+use crate::query::{
+    Filter, MAX_FILTER_DEPTH, NotArg, Query, execute_query, parse_query, to_table, validate_query,
+    check_bounded, KeyCase, execute_paged_query, check_denied, Order, QueryBuilder,
+};
+use crate::testing::section;
+use crate::types::{Dataset, KVPair, Value};
 use ordered_float::OrderedFloat;
 use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet};
 
 #[test]
 fn test_simple() {
@@ -25,8 +33,8 @@ fn test_simple() {
         key: "courses_avg".to_string(),
         value: OrderedFloat::from(97f32),
     };
-    let actual = match deserialized.r#where {
-        Filter::GT { gt: ref g } => g,
+    let actual = match deserialized.filter() {
+        Filter::GT { gt } => gt,
         _ => panic!("not gt"),
     };
     assert_eq!(*actual, expected);
@@ -82,7 +90,7 @@ fn test_complex() {
     let query: Query = serde_json::from_str(&json).unwrap();
     assert_eq!(
         format!("{:?}", query),
-        r#"Query { where: Some(OR { or: [AND { and: [GT { gt: {"courses_avg": 90.0} }, IS { is: {"courses_dept": "adhe"} }] }, EQ { eq: {"courses_avg": 95.0} }] }), options: Options { columns: ["courses_dept", "courses_id", "courses_avg"], order: Some("courses_avg") } }"#
+        r#"Query { where: Some(OR { or: [AND { and: [GT { gt: KVPair { key: "courses_avg", value: 90.0 } }, IS { is: KVPair { key: "courses_dept", value: "adhe" }, mode: WILDCARD, flags: None }] }, EQ { eq: KVPair { key: "courses_avg", value: 95.0 }, tol: None }] }), options: Options { columns: ["courses_dept", "courses_id", "courses_avg"], order: Some(ONE("courses_avg")), f64: false, limit: None, offset: None, cursor: None, format: OBJECT, key_case: None, strip_prefix: false, distinct: false, distinct_tolerance: None, nulls_last: None, percent_rank: None }, transformations: None }"#
     );
 
     let dataset = load_dataset("pair.zip").unwrap();
@@ -150,3 +158,2435 @@ fn test_regex() {
     let regex = Regex::new(&*format!("^{}$", pattern)).unwrap();
     assert!(regex.is_match("cpsc"));
 }
+
+#[test]
+fn test_validate_well_formed() {
+    let json = r#"{
+    "WHERE":{
+       "GT":{
+          "sections_avg":97
+       }
+    },
+    "OPTIONS":{
+       "COLUMNS":[
+          "sections_dept",
+          "maxAvg"
+       ],
+       "ORDER":"maxAvg"
+    },
+    "TRANSFORMATIONS":{
+       "GROUP":[
+          "sections_dept"
+       ],
+       "APPLY":[
+          {
+             "maxAvg":{
+                "MAX":"sections_avg"
+             }
+          }
+       ]
+    }
+} "#;
+    let query: Query = serde_json::from_str(json).unwrap();
+    assert!(validate_query(&query).is_ok());
+}
+
+#[test]
+fn test_validate_empty_columns() {
+    let json = r#"{
+    "WHERE":{},
+    "OPTIONS":{
+       "COLUMNS":[]
+    }
+} "#;
+    let query: Query = serde_json::from_str(json).unwrap();
+    let error = validate_query(&query).unwrap_err();
+    assert_eq!(error.to_string(), "COLUMNS must be a non-empty array");
+}
+
+#[test]
+fn test_validate_empty_group() {
+    let json = r#"{
+    "WHERE":{},
+    "OPTIONS":{
+       "COLUMNS":[
+          "sections_dept"
+       ]
+    },
+    "TRANSFORMATIONS":{
+       "GROUP":[],
+       "APPLY":[]
+    }
+} "#;
+    let query: Query = serde_json::from_str(json).unwrap();
+    let error = validate_query(&query).unwrap_err();
+    assert_eq!(error.to_string(), "GROUP must be a non-empty array");
+}
+
+#[test]
+fn test_validate_unknown_group() {
+    let json = r#"{
+    "WHERE":{},
+    "OPTIONS":{
+       "COLUMNS":[
+          "dept"
+       ]
+    },
+    "TRANSFORMATIONS":{
+       "GROUP":[
+          "dept"
+       ],
+       "APPLY":[]
+    }
+} "#;
+    let query: Query = serde_json::from_str(json).unwrap();
+    let error = validate_query(&query).unwrap_err();
+    assert_eq!(error.to_string(), "Unknown group dept");
+}
+
+#[test]
+fn test_duplicate_apply_key() {
+    let json = r#"{
+    "WHERE":{},
+    "OPTIONS":{
+       "COLUMNS":[
+          "sections_dept",
+          "avgGrade"
+       ]
+    },
+    "TRANSFORMATIONS":{
+       "GROUP":[
+          "sections_dept"
+       ],
+       "APPLY":[
+          {
+             "avgGrade":{
+                "AVG":"sections_avg"
+             }
+          },
+          {
+             "avgGrade":{
+                "MAX":"sections_avg"
+             }
+          }
+       ]
+    }
+} "#;
+    let query: Query = serde_json::from_str(json).unwrap();
+    let error = execute_query(&query, &Vec::<Section>::new()).unwrap_err();
+    assert_eq!(error.to_string(), "Duplicate apply key 'avgGrade'");
+}
+
+#[test]
+fn test_group_projection() {
+    let json = r#"{
+    "WHERE":{},
+    "OPTIONS":{
+       "COLUMNS":[
+          "sections_dept",
+          "maxAvg"
+       ],
+       "ORDER":"sections_dept"
+    },
+    "TRANSFORMATIONS":{
+       "GROUP":[
+          "sections_dept"
+       ],
+       "APPLY":[
+          {
+             "maxAvg":{
+                "MAX":"sections_avg"
+             }
+          }
+       ]
+    }
+} "#;
+    let query: Query = serde_json::from_str(json).unwrap();
+    let dataset = vec![
+        section("1", "cpsc", "110", 80.0),
+        section("2", "cpsc", "210", 90.0),
+        section("3", "math", "100", 70.0),
+    ];
+    let result = execute_query(&query, &dataset).unwrap();
+    assert_eq!(result.len(), 2);
+    assert_eq!(result[0]["sections_dept"], Value::Str("cpsc".to_string()));
+    assert_eq!(result[0]["maxAvg"], Value::Num(OrderedFloat::from(90f32)));
+    assert_eq!(result[1]["maxAvg"], Value::Num(OrderedFloat::from(70f32)));
+}
+
+#[test]
+fn test_ungrouped_projection() {
+    let json = r#"{
+    "WHERE":{},
+    "OPTIONS":{
+       "COLUMNS":[
+          "sections_dept",
+          "sections_title"
+       ]
+    },
+    "TRANSFORMATIONS":{
+       "GROUP":[
+          "sections_dept"
+       ],
+       "APPLY":[]
+    }
+} "#;
+    let query: Query = serde_json::from_str(json).unwrap();
+    let dataset = vec![section("1", "cpsc", "110", 80.0)];
+    let error = execute_query(&query, &dataset).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Column sections_title must be in GROUP or APPLY"
+    );
+}
+
+fn execute_error(json: &str, dataset: &[Section]) -> EngineError {
+    let query: Query = serde_json::from_str(json).unwrap();
+    let error = execute_query(&query, dataset).unwrap_err();
+    error.downcast_ref::<EngineError>().unwrap().clone()
+}
+
+#[test]
+fn test_type_error() {
+    let json = r#"{
+    "WHERE":{
+       "GT":{
+          "sections_dept":90
+       }
+    },
+    "OPTIONS":{
+       "COLUMNS":[
+          "sections_dept"
+       ]
+    }
+} "#;
+    let dataset = vec![section("1", "cpsc", "110", 80.0)];
+    assert_eq!(
+        execute_error(json, &dataset),
+        EngineError::TypeError {
+            op: "gt",
+            column: "sections_dept".to_string()
+        }
+    );
+}
+
+#[test]
+fn test_field_not_found() {
+    let json = r#"{
+    "WHERE":{
+       "IS":{
+          "sections_professor":"smith"
+       }
+    },
+    "OPTIONS":{
+       "COLUMNS":[
+          "sections_dept"
+       ]
+    }
+} "#;
+    let dataset = vec![section("1", "cpsc", "110", 80.0)];
+    assert_eq!(
+        execute_error(json, &dataset),
+        EngineError::FieldNotFound {
+            field: "sections_professor".to_string(),
+            suggestion: None
+        }
+    );
+}
+
+#[test]
+fn test_unknown_column() {
+    let json = r#"{
+    "WHERE":{},
+    "OPTIONS":{
+       "COLUMNS":[
+          "sections_professor"
+       ]
+    }
+} "#;
+    let dataset = vec![section("1", "cpsc", "110", 80.0)];
+    assert_eq!(
+        execute_error(json, &dataset),
+        EngineError::FieldNotFound {
+            field: "sections_professor".to_string(),
+            suggestion: None
+        }
+    );
+}
+
+#[test]
+fn test_result_too_large() {
+    let json = r#"{
+    "WHERE":{},
+    "OPTIONS":{
+       "COLUMNS":[
+          "sections_uuid"
+       ]
+    }
+} "#;
+    let dataset = (0..5001)
+        .map(|i| section(&i.to_string(), "cpsc", "110", 80.0))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        execute_error(json, &dataset),
+        EngineError::ResultTooLargeError
+    );
+}
+
+#[test]
+fn test_group_without_apply() {
+    let dataset = vec![
+        section("1", "cpsc", "110", 80.0),
+        section("2", "cpsc", "110", 85.0),
+        section("3", "cpsc", "210", 90.0),
+        section("4", "math", "110", 70.0),
+        section("5", "math", "110", 75.0),
+    ];
+    for transformations in [
+        r#"{"GROUP": ["sections_dept", "sections_id"], "APPLY": []}"#,
+        r#"{"GROUP": ["sections_dept", "sections_id"]}"#,
+    ] {
+        let json = format!(
+            r#"{{
+    "WHERE": {{}},
+    "OPTIONS": {{
+       "COLUMNS": ["sections_dept", "sections_id"]
+    }},
+    "TRANSFORMATIONS": {}
+}}"#,
+            transformations
+        );
+        let query: Query = serde_json::from_str(&json).unwrap();
+        let result = execute_query(&query, &dataset).unwrap();
+        assert_eq!(result.len(), 3);
+        assert!(result.iter().all(|row| row.len() == 2));
+    }
+}
+
+fn percentile_query(p: f32) -> Query {
+    let json = format!(
+        r#"{{
+    "WHERE": {{}},
+    "OPTIONS": {{
+       "COLUMNS": ["sections_dept", "grade"]
+    }},
+    "TRANSFORMATIONS": {{
+       "GROUP": ["sections_dept"],
+       "APPLY": [{{"grade": {{"PERCENTILE": {{"column": "sections_avg", "p": {}}}}}}}]
+    }}
+}}"#,
+        p
+    );
+    serde_json::from_str(&json).unwrap()
+}
+
+#[test]
+fn test_percentile() {
+    let dataset = vec![
+        section("1", "cpsc", "110", 90.0),
+        section("2", "cpsc", "210", 70.0),
+        section("3", "cpsc", "310", 100.0),
+        section("4", "cpsc", "410", 80.0),
+    ];
+    for (p, expected) in [(0.0, 70.0), (50.0, 85.0), (100.0, 100.0)] {
+        let result = execute_query(&percentile_query(p), &dataset).unwrap();
+        assert_eq!(result[0]["grade"], Value::Num(OrderedFloat::from(expected)));
+    }
+}
+
+#[test]
+fn test_percentile_out_of_range() {
+    let dataset = vec![section("1", "cpsc", "110", 90.0)];
+    let error = execute_query(&percentile_query(101.0), &dataset).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Percentile must be between 0 and 100, got 101"
+    );
+}
+
+#[test]
+fn test_empty_and() {
+    let json = r#"{
+    "WHERE":{
+       "AND":[]
+    },
+    "OPTIONS":{
+       "COLUMNS":[
+          "sections_dept"
+       ]
+    }
+} "#;
+    let query: Query = serde_json::from_str(json).unwrap();
+    let error = validate_query(&query).unwrap_err();
+    assert_eq!(error.to_string(), "AND requires at least one filter");
+}
+
+#[test]
+fn test_empty_or() {
+    let json = r#"{
+    "WHERE":{
+       "NOT":{
+          "OR":[]
+       }
+    },
+    "OPTIONS":{
+       "COLUMNS":[
+          "sections_dept"
+       ]
+    }
+} "#;
+    let query: Query = serde_json::from_str(json).unwrap();
+    let dataset = vec![section("1", "cpsc", "110", 80.0)];
+    let error = execute_query(&query, &dataset).unwrap_err();
+    assert_eq!(error.to_string(), "OR requires at least one filter");
+}
+
+#[test]
+fn test_field_suggestion() {
+    let json = r#"{
+    "WHERE":{
+       "GT":{
+          "sections_averag":90
+       }
+    },
+    "OPTIONS":{
+       "COLUMNS":[
+          "sections_dept"
+       ]
+    }
+} "#;
+    let query: Query = serde_json::from_str(json).unwrap();
+    let dataset = vec![section("1", "cpsc", "110", 80.0)];
+    let error = execute_query(&query, &dataset).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Field sections_averag does not exist; did you mean sections_avg?"
+    );
+}
+
+#[test]
+fn test_first_last() {
+    let json = r#"{
+    "WHERE":{},
+    "OPTIONS":{
+       "COLUMNS":[
+          "sections_dept",
+          "earliest",
+          "latest",
+          "firstListed"
+       ]
+    },
+    "TRANSFORMATIONS":{
+       "GROUP":[
+          "sections_dept"
+       ],
+       "APPLY":[
+          {
+             "earliest":{
+                "FIRST":{
+                   "column":"sections_title",
+                   "by":"sections_year"
+                }
+             }
+          },
+          {
+             "latest":{
+                "LAST":{
+                   "column":"sections_avg",
+                   "by":"sections_year"
+                }
+             }
+          },
+          {
+             "firstListed":{
+                "FIRST":"sections_id"
+             }
+          }
+       ]
+    }
+} "#;
+    let query: Query = serde_json::from_str(json).unwrap();
+    let mut dataset = vec![
+        section("1", "cpsc", "210", 70.0),
+        section("2", "cpsc", "110", 80.0),
+        section("3", "cpsc", "310", 90.0),
+    ];
+    dataset[0].year = OrderedFloat::from(2016f32);
+    dataset[1].year = OrderedFloat::from(2010f32);
+    dataset[2].year = OrderedFloat::from(2012f32);
+    let result = execute_query(&query, &dataset).unwrap();
+    assert_eq!(result[0]["earliest"], Value::Str("cpsc 110".to_string()));
+    assert_eq!(result[0]["latest"], Value::Num(OrderedFloat::from(70f32)));
+    assert_eq!(result[0]["firstListed"], Value::Str("210".to_string()));
+}
+
+fn eq_query(tol: &str) -> Query {
+    let json = format!(
+        r#"{{
+    "WHERE": {{
+       "EQ": {{"sections_avg": 85}}{}
+    }},
+    "OPTIONS": {{
+       "COLUMNS": ["sections_uuid"]
+    }}
+}}"#,
+        tol
+    );
+    serde_json::from_str(&json).unwrap()
+}
+
+#[test]
+fn test_eq_tolerance() {
+    // Within the default EPSILON of 85 but not exactly equal
+    let dataset = vec![
+        section("1", "cpsc", "110", 85.0),
+        section("2", "cpsc", "210", 85.00005),
+        section("3", "cpsc", "310", 85.5),
+    ];
+    assert_eq!(execute_query(&eq_query(""), &dataset).unwrap().len(), 2);
+    assert_eq!(
+        execute_query(&eq_query(r#", "tol": 0"#), &dataset)
+            .unwrap()
+            .len(),
+        1
+    );
+    assert_eq!(
+        execute_query(&eq_query(r#", "tol": 1"#), &dataset)
+            .unwrap()
+            .len(),
+        3
+    );
+}
+
+#[test]
+fn test_negative_eq_tolerance() {
+    let error = validate_query(&eq_query(r#", "tol": -1"#)).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "EQ tolerance must be non-negative, got -1"
+    );
+}
+
+#[test]
+fn test_missing_where() {
+    let dataset = vec![
+        section("1", "cpsc", "110", 80.0),
+        section("2", "math", "100", 90.0),
+    ];
+    let query: Query =
+        serde_json::from_str(r#"{"OPTIONS": {"COLUMNS": ["sections_uuid"]}}"#).unwrap();
+    assert!(query.r#where.is_none());
+    assert!(matches!(query.filter(), Filter::EMPTY {}));
+    assert_eq!(execute_query(&query, &dataset).unwrap().len(), 2);
+}
+
+#[test]
+fn test_f64_accumulation() {
+    let dataset: Vec<Section> = (0..100_000)
+        .map(|i| section(&i.to_string(), "cpsc", "110", 1.1))
+        .collect();
+    let sum = |wide: bool| {
+        let json = format!(
+            r#"{{"WHERE": {{}}, "OPTIONS": {{"COLUMNS": ["total"], "F64": {}}},
+            "TRANSFORMATIONS": {{"GROUP": ["sections_dept"], "APPLY": [{{"total": {{"SUM": "sections_avg"}}}}]}}}}"#,
+            wide
+        );
+        let query: Query = serde_json::from_str(&json).unwrap();
+        match &execute_query(&query, &dataset).unwrap()[0]["total"] {
+            Value::Num(n) => n.0,
+            _ => panic!("not a number"),
+        }
+    };
+    assert_eq!(sum(true), 110_000.0);
+    assert!((sum(false) - 110_000.0).abs() > 1.0);
+}
+
+#[test]
+fn test_search() {
+    let dataset = vec![
+        section("1", "cpsc", "310", 85.5),
+        section("2", "math", "100", 90.0),
+    ];
+    let search = |term: &str| {
+        let json = format!(
+            r#"{{"WHERE": {{"SEARCH": "{}"}}, "OPTIONS": {{"COLUMNS": ["sections_uuid"]}}}}"#,
+            term
+        );
+        let query: Query = serde_json::from_str(&json).unwrap();
+        execute_query(&query, &dataset).unwrap().len()
+    };
+    // Titles are "<dept> <id>"
+    assert_eq!(search("CPSC 3"), 1);
+    assert_eq!(search("h 1"), 1);
+    assert_eq!(search("85.5"), 0);
+}
+
+#[test]
+fn test_order_key_in_columns() {
+    let dataset = vec![
+        section("1", "cpsc", "310", 90.0),
+        section("2", "math", "100", 80.0),
+    ];
+    let order = |order: &str| {
+        let json = format!(
+            r#"{{"WHERE": {{}}, "OPTIONS": {{"COLUMNS": ["sections_uuid", "sections_avg"], "ORDER": {}}}}}"#,
+            order
+        );
+        let query: Query = serde_json::from_str(&json).unwrap();
+        execute_query(&query, &dataset)
+    };
+
+    let result = order(r#""sections_avg""#).unwrap();
+    assert_eq!(result[0]["sections_uuid"], Value::Str("2".to_string()));
+
+    let error = order(r#""sections_dept""#).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Cannot order by sections_dept: not in COLUMNS"
+    );
+    let error = order(r#"{"dir": "DOWN", "keys": ["sections_avg", "sections_id"]}"#).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Cannot order by sections_id: not in COLUMNS"
+    );
+}
+
+#[test]
+fn test_single_key_direction() {
+    let dataset = vec![
+        section("1", "cpsc", "310", 90.0),
+        section("2", "math", "100", 80.0),
+        section("3", "biol", "200", 85.0),
+    ];
+    let uuids = |order: &str| -> anyhow::Result<Vec<Value>> {
+        let json = format!(
+            r#"{{"WHERE": {{}}, "OPTIONS": {{"COLUMNS": ["sections_uuid", "sections_avg"], "ORDER": {}}}}}"#,
+            order
+        );
+        let query: Query = serde_json::from_str(&json)?;
+        Ok(execute_query(&query, &dataset)?
+            .into_iter()
+            .map(|row| row["sections_uuid"].clone())
+            .collect())
+    };
+    let ids =
+        |ids: &[&str]| -> Vec<Value> { ids.iter().map(|id| Value::Str(id.to_string())).collect() };
+
+    assert_eq!(
+        uuids(r#"{"key": "sections_avg", "dir": "DOWN"}"#).unwrap(),
+        ids(&["1", "3", "2"])
+    );
+    assert_eq!(
+        uuids(r#"{"key": "sections_avg", "dir": "UP"}"#).unwrap(),
+        ids(&["2", "3", "1"])
+    );
+    // The bare string form stays ascending
+    assert_eq!(uuids(r#""sections_avg""#).unwrap(), ids(&["2", "3", "1"]));
+    assert_eq!(
+        uuids(r#"{"key": "sections_avg", "dir": "SIDEWAYS"}"#)
+            .unwrap_err()
+            .to_string(),
+        "Invalid ordering SIDEWAYS, expected UP or DOWN"
+    );
+}
+
+#[test]
+fn test_short_circuit() {
+    let dataset = vec![section("1", "cpsc", "310", 90.0)];
+    let run = |filter: &str| {
+        let json = format!(
+            r#"{{"WHERE": {}, "OPTIONS": {{"COLUMNS": ["sections_uuid"]}}}}"#,
+            filter
+        );
+        let query: Query = serde_json::from_str(&json).unwrap();
+        execute_query(&query, &dataset)
+    };
+    // sections_dept isn't numeric, so reaching the GT is a type error
+    let bad = r#"{"GT": {"sections_dept": 1}}"#;
+    let matches = r#"{"IS": {"sections_dept": "cpsc"}}"#;
+    let misses = r#"{"IS": {"sections_dept": "math"}}"#;
+
+    assert_eq!(
+        run(&format!(r#"{{"OR": [{}, {}]}}"#, matches, bad))
+            .unwrap()
+            .len(),
+        1
+    );
+    assert_eq!(
+        run(&format!(r#"{{"AND": [{}, {}]}}"#, misses, bad))
+            .unwrap()
+            .len(),
+        0
+    );
+    assert!(run(&format!(r#"{{"OR": [{}, {}]}}"#, misses, bad)).is_err());
+    assert!(run(&format!(r#"{{"AND": [{}, {}]}}"#, matches, bad)).is_err());
+}
+
+#[test]
+fn test_is_modes() {
+    let dataset = vec![
+        section("1", "cpsc", "310", 90.0),
+        section("2", "cpsc", "110", 80.0),
+        section("3", "c*", "100", 70.0),
+    ];
+    let uuids = |pattern: &str, mode: &str| -> Vec<Value> {
+        let json = format!(
+            r#"{{"WHERE": {{"IS": {{"sections_dept": "{}"}}{}}}, "OPTIONS": {{"COLUMNS": ["sections_uuid"]}}}}"#,
+            pattern, mode
+        );
+        let query: Query = serde_json::from_str(&json).unwrap();
+        execute_query(&query, &dataset)
+            .unwrap()
+            .into_iter()
+            .map(|row| row["sections_uuid"].clone())
+            .collect()
+    };
+    let all = vec![
+        Value::Str("1".into()),
+        Value::Str("2".into()),
+        Value::Str("3".into()),
+    ];
+
+    assert_eq!(uuids("c*", ""), all);
+    assert_eq!(uuids("c*", r#", "mode": "wildcard""#), all);
+    // As a regex, "c*" is any run of c's, which every string contains unanchored
+    assert_eq!(uuids("c*", r#", "mode": "regex""#), all);
+    assert_eq!(
+        uuids("^c.$", r#", "mode": "regex""#),
+        vec![Value::Str("3".into())]
+    );
+    assert_eq!(
+        uuids("c*", r#", "mode": "exact""#),
+        vec![Value::Str("3".into())]
+    );
+    assert_eq!(uuids("p", r#", "mode": "regex""#), all[..2].to_vec());
+    assert_eq!(uuids("p", ""), Vec::<Value>::new());
+}
+
+#[test]
+fn test_is_literal_metacharacters() {
+    let titled = |uuid: &str, title: &str| Section {
+        title: title.to_string(),
+        ..section(uuid, "cpsc", "100", 80.0)
+    };
+    let dataset = vec![
+        titled("1", "C++"),
+        titled("2", "CCC"),
+        titled("3", "Math 100 (Honours)"),
+        titled("4", "Math 100 Honours"),
+        titled("5", "intro.prog"),
+        titled("6", "introXprog"),
+    ];
+    let uuids = |pattern: &str, mode: &str| -> Vec<Value> {
+        let json = format!(
+            r#"{{"WHERE": {{"IS": {{"sections_title": "{}"}}{}}}, "OPTIONS": {{"COLUMNS": ["sections_uuid"]}}}}"#,
+            pattern, mode
+        );
+        let query: Query = serde_json::from_str(&json).unwrap();
+        execute_query(&query, &dataset)
+            .unwrap()
+            .into_iter()
+            .map(|row| row["sections_uuid"].clone())
+            .collect()
+    };
+    let ids =
+        |ids: &[&str]| -> Vec<Value> { ids.iter().map(|id| Value::Str(id.to_string())).collect() };
+
+    for mode in ["", r#", "mode": "wildcard""#, r#", "mode": "exact""#] {
+        assert_eq!(uuids("C++", mode), ids(&["1"]));
+        assert_eq!(uuids("Math 100 (Honours)", mode), ids(&["3"]));
+        assert_eq!(uuids("intro.prog", mode), ids(&["5"]));
+    }
+    // Only `*` is special in wildcard mode
+    assert_eq!(uuids("C+*", ""), ids(&["1"]));
+    assert_eq!(uuids("*(Honours)", ""), ids(&["3"]));
+    assert_eq!(uuids("intro.*", ""), ids(&["5"]));
+    assert_eq!(uuids("*.*", ""), ids(&["5"]));
+    // A regex still reads them as metacharacters
+    assert_eq!(
+        uuids("^intro.prog$", r#", "mode": "regex""#),
+        ids(&["5", "6"])
+    );
+}
+
+#[test]
+fn test_product() {
+    let product = |dataset: &Vec<Section>| {
+        let json = r#"{"WHERE": {}, "OPTIONS": {"COLUMNS": ["sections_dept", "product"]},
+            "TRANSFORMATIONS": {"GROUP": ["sections_dept"], "APPLY": [{"product": {"PRODUCT": "sections_avg"}}]}}"#;
+        let query: Query = serde_json::from_str(json).unwrap();
+        execute_query(&query, dataset).unwrap()[0]["product"].clone()
+    };
+
+    let small = vec![
+        section("1", "cpsc", "110", 2.0),
+        section("2", "cpsc", "210", 3.5),
+        section("3", "cpsc", "310", 0.5),
+    ];
+    assert_eq!(product(&small), Value::Num(OrderedFloat(3.5)));
+
+    let huge = vec![
+        section("1", "cpsc", "110", 1e20),
+        section("2", "cpsc", "210", 1e20),
+    ];
+    assert_eq!(product(&huge), Value::Num(OrderedFloat(f32::INFINITY)));
+}
+
+#[test]
+fn test_concat() {
+    let mut dataset = vec![
+        section("1", "cpsc", "110", 80.0),
+        section("2", "cpsc", "210", 80.0),
+        section("3", "cpsc", "310", 80.0),
+        section("4", "cpsc", "313", 80.0),
+        section("5", "math", "100", 80.0),
+    ];
+    for (section, instructor) in dataset
+        .iter_mut()
+        .zip(["wolfman", "allen", "wolfman", "", "lee"])
+    {
+        section.instructor = instructor.to_string();
+    }
+    let concat = |argument: &str| {
+        let json = format!(
+            r#"{{"WHERE": {{}}, "OPTIONS": {{"COLUMNS": ["sections_dept", "instructors"], "ORDER": "sections_dept"}},
+            "TRANSFORMATIONS": {{"GROUP": ["sections_dept"], "APPLY": [{{"instructors": {{"CONCAT": {}}}}}]}}}}"#,
+            argument
+        );
+        let query: Query = serde_json::from_str(&json).unwrap();
+        execute_query(&query, &dataset).map(|rows| rows[0]["instructors"].clone())
+    };
+
+    assert_eq!(
+        concat(r#""sections_instructor""#).unwrap(),
+        Value::Str("allen, wolfman, wolfman".to_string())
+    );
+    assert_eq!(
+        concat(r#"{"column": "sections_instructor", "distinct": true, "separator": "; "}"#)
+            .unwrap(),
+        Value::Str("allen; wolfman".to_string())
+    );
+    let error = concat(r#""sections_avg""#).unwrap_err();
+    assert_eq!(
+        error.downcast_ref::<EngineError>(),
+        Some(&EngineError::TypeError {
+            op: "concat",
+            column: "sections_avg".to_string(),
+        })
+    );
+}
+
+fn room(name: &str, lat: f32, lon: f32) -> Room {
+    Room {
+        fullname: "".to_string(),
+        shortname: name.to_string(),
+        number: "100".to_string(),
+        name: name.to_string(),
+        address: None,
+        seats: OrderedFloat::from(50f32),
+        r#type: "".to_string(),
+        furniture: "".to_string(),
+        href: "".to_string(),
+        lat: OrderedFloat::from(lat),
+        lon: OrderedFloat::from(lon),
+    }
+}
+
+#[test]
+fn test_near() {
+    // A thousandth of a degree of latitude is about 111 metres
+    let rooms = vec![
+        room("SAME", 49.26, -123.25),
+        room("INSIDE", 49.2644, -123.25),
+        room("OUTSIDE", 49.2646, -123.25),
+        room("FAR", 49.28, -123.25),
+    ];
+    let near = |radius: f32| -> Vec<Value> {
+        let json = format!(
+            r#"{{"WHERE": {{"NEAR": {{"lat": 49.26, "lon": -123.25, "radius": {}}}}}, "OPTIONS": {{"COLUMNS": ["rooms_name"]}}}}"#,
+            radius
+        );
+        let query: Query = serde_json::from_str(&json).unwrap();
+        execute_query(&query, &rooms)
+            .unwrap()
+            .into_iter()
+            .map(|row| row["rooms_name"].clone())
+            .collect()
+    };
+    assert_eq!(
+        near(500.0),
+        vec![Value::Str("SAME".into()), Value::Str("INSIDE".into())]
+    );
+    assert_eq!(near(0.0), vec![Value::Str("SAME".into())]);
+    assert_eq!(near(3000.0).len(), 4);
+
+    let query: Query = serde_json::from_str(
+        r#"{"WHERE": {"NEAR": {"lat": 49.26, "lon": -123.25, "radius": 500}}, "OPTIONS": {"COLUMNS": ["sections_uuid"]}}"#,
+    )
+    .unwrap();
+    let error = execute_query(&query, &[section("1", "cpsc", "310", 90.0)]).unwrap_err();
+    assert_eq!(error.to_string(), "NEAR requires lat and lon columns");
+}
+
+#[test]
+fn test_group_order_is_stable() {
+    let dataset: Vec<Section> = ["phys", "cpsc", "math", "biol", "chem", "cpsc", "math"]
+        .iter()
+        .enumerate()
+        .map(|(i, dept)| section(&i.to_string(), dept, "100", 80.0))
+        .collect();
+    let json = r#"{"WHERE": {}, "OPTIONS": {"COLUMNS": ["sections_dept", "count"]},
+        "TRANSFORMATIONS": {"GROUP": ["sections_dept"], "APPLY": [{"count": {"COUNT": "sections_uuid"}}]}}"#;
+    let query: Query = serde_json::from_str(json).unwrap();
+
+    let first = execute_query(&query, &dataset).unwrap();
+    for _ in 0..5 {
+        assert_eq!(execute_query(&query, &dataset).unwrap(), first);
+    }
+    let depts: Vec<&Value> = first.iter().map(|row| &row["sections_dept"]).collect();
+    let expected =
+        ["biol", "chem", "cpsc", "math", "phys"].map(|dept| Value::Str(dept.to_string()));
+    assert_eq!(depts, expected.iter().collect::<Vec<_>>());
+}
+
+#[test]
+fn test_limit_offset() {
+    let dataset: Vec<Section> = (0..5)
+        .map(|i| section(&i.to_string(), "cpsc", "110", 80.0 + i as f32))
+        .collect();
+    let uuids = |options: &str| -> Vec<Value> {
+        let json = format!(
+            r#"{{"OPTIONS": {{"COLUMNS": ["sections_uuid", "sections_avg"], "ORDER": "sections_avg"{}}}}}"#,
+            options
+        );
+        let query: Query = serde_json::from_str(&json).unwrap();
+        execute_query(&query, &dataset)
+            .unwrap()
+            .into_iter()
+            .map(|row| row["sections_uuid"].clone())
+            .collect()
+    };
+    let strs = |ids: &[&str]| {
+        ids.iter()
+            .map(|id| Value::Str(id.to_string()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(uuids(r#", "LIMIT": 2"#), strs(&["0", "1"]));
+    assert_eq!(uuids(r#", "LIMIT": 2, "OFFSET": 2"#), strs(&["2", "3"]));
+    assert_eq!(uuids(r#", "OFFSET": 4"#), strs(&["4"]));
+    assert_eq!(uuids(r#", "OFFSET": 10"#), strs(&[]));
+}
+
+#[test]
+fn test_case_insensitive_field() {
+    let cpsc = section("1", "cpsc", "310", 80.0);
+    assert_eq!(
+        cpsc.get("SECTIONS_DEPT"),
+        Ok(Value::Str("cpsc".to_string()))
+    );
+    assert_eq!(cpsc.get("Sections_Avg"), cpsc.get("sections_avg"));
+    assert!(cpsc.get("SECTIONS_NOPE").is_err());
+
+    let dataset = vec![cpsc, section("2", "math", "100", 70.0)];
+    let query: Query = serde_json::from_str(
+        r#"{"WHERE": {"IS": {"SECTIONS_DEPT": "cpsc"}}, "OPTIONS": {"COLUMNS": ["sections_uuid"]}}"#,
+    )
+    .unwrap();
+    let result = execute_query(&query, &dataset).unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0]["sections_uuid"], Value::Str("1".to_string()));
+
+    // Projection, ordering and grouping resolve mixed case too, under the dataset's spelling
+    let query = parse_query(
+        r#"{"OPTIONS": {"COLUMNS": ["SECTIONS_UUID", "Sections_Avg"], "ORDER": "Sections_Avg", "FORMAT": "table"}}"#,
+    )
+    .unwrap();
+    let result = execute_query(&query, &dataset).unwrap();
+    assert_eq!(result[0]["sections_uuid"], Value::Str("2".to_string()));
+    assert_eq!(result[1]["sections_avg"], Value::Num(OrderedFloat(80.0)));
+    let (columns, rows) = to_table(&query.options, result);
+    assert_eq!(columns, ["sections_uuid", "sections_avg"]);
+    assert_eq!(rows[0][0], Value::Str("2".to_string()));
+
+    let query = parse_query(
+        r#"{"OPTIONS": {"COLUMNS": ["SECTIONS_DEPT", "maxAvg"], "ORDER": "SECTIONS_DEPT"}, "TRANSFORMATIONS": {"GROUP": ["SECTIONS_DEPT"], "APPLY": [{"maxAvg": {"MAX": "SECTIONS_AVG"}}]}}"#,
+    )
+    .unwrap();
+    let result = execute_query(&query, &dataset).unwrap();
+    assert_eq!(result[0]["sections_dept"], Value::Str("cpsc".to_string()));
+    assert_eq!(result[0]["maxAvg"], Value::Num(OrderedFloat(80.0)));
+
+    // Names that resolve to the same column are duplicates
+    let query = parse_query(
+        r#"{"OPTIONS": {"COLUMNS": ["sections_uuid", "SECTIONS_UUID"], "FORMAT": "table"}}"#,
+    )
+    .unwrap();
+    assert!(execute_query(&query, &dataset).is_err());
+    let query = parse_query(
+        r#"{"WHERE": {"GT": {"SECTIONS_AVG": 75}}, "OPTIONS": {"COLUMNS": ["sections_uuid"]}}"#,
+    )
+    .unwrap();
+    let denied = BTreeSet::from(["sections_avg".to_string()]);
+    assert!(check_denied(&query, &denied).is_err());
+}
+
+#[test]
+fn test_in_and_nin() {
+    let dataset = vec![
+        section("1", "cpsc", "110", 80.0),
+        section("2", "math", "100", 70.0),
+        section("3", "phys", "101", 75.0),
+    ];
+    let uuids = |filter: &str| -> anyhow::Result<Vec<Value>> {
+        let json = format!(
+            r#"{{"WHERE": {}, "OPTIONS": {{"COLUMNS": ["sections_uuid"], "ORDER": "sections_uuid"}}}}"#,
+            filter
+        );
+        let query: Query = serde_json::from_str(&json).unwrap();
+        Ok(execute_query(&query, &dataset)?
+            .into_iter()
+            .map(|row| row["sections_uuid"].clone())
+            .collect())
+    };
+    let strs = |ids: &[&str]| {
+        ids.iter()
+            .map(|id| Value::Str(id.to_string()))
+            .collect::<Vec<_>>()
+    };
+
+    let excluded = r#"{"NIN": {"sections_dept": ["cpsc", "math"]}}"#;
+    assert_eq!(uuids(excluded).unwrap(), strs(&["3"]));
+    assert_eq!(
+        uuids(r#"{"NIN": {"sections_dept": []}}"#).unwrap(),
+        strs(&["1", "2", "3"])
+    );
+    let included = r#"{"IN": {"sections_dept": ["cpsc", "math"]}}"#;
+    assert_eq!(uuids(included).unwrap(), strs(&["1", "2"]));
+
+    let err = uuids(r#"{"NIN": {"sections_avg": ["80"]}}"#).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<EngineError>(),
+        Some(EngineError::TypeError { op: "nin", .. })
+    ));
+}
+
+#[test]
+fn test_boxed_dataset() {
+    let dataset: Vec<Box<dyn Dataset>> = vec![
+        Box::new(section("1", "cpsc", "310", 85.0)),
+        Box::new(section("2", "math", "100", 70.0)),
+    ];
+    let query: Query = serde_json::from_str(
+        r#"{"WHERE": {"GT": {"sections_avg": 80}}, "OPTIONS": {"COLUMNS": ["sections_dept"]}}"#,
+    )
+    .unwrap();
+    let result = execute_query(&query, &dataset).unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0]["sections_dept"], Value::Str("cpsc".to_string()));
+}
+
+#[test]
+fn test_string_match_filters() {
+    let dataset = vec![
+        section("1", "cpsc", "310", 80.0),
+        section("2", "math", "310", 70.0),
+        section("3", "cpsc", "110", 75.0),
+    ];
+    let uuids = |filter: &str| -> anyhow::Result<Vec<Value>> {
+        let json = format!(
+            r#"{{"WHERE": {}, "OPTIONS": {{"COLUMNS": ["sections_uuid"], "ORDER": "sections_uuid"}}}}"#,
+            filter
+        );
+        let query: Query = serde_json::from_str(&json).unwrap();
+        Ok(execute_query(&query, &dataset)?
+            .into_iter()
+            .map(|row| row["sections_uuid"].clone())
+            .collect())
+    };
+    let strs = |ids: &[&str]| {
+        ids.iter()
+            .map(|id| Value::Str(id.to_string()))
+            .collect::<Vec<_>>()
+    };
+
+    // Titles are "<dept> <id>"
+    let starts = r#"{"STARTSWITH": {"sections_title": "cpsc"}}"#;
+    assert_eq!(uuids(starts).unwrap(), strs(&["1", "3"]));
+    let ends = r#"{"ENDSWITH": {"sections_title": "310"}}"#;
+    assert_eq!(uuids(ends).unwrap(), strs(&["1", "2"]));
+    let contains = r#"{"CONTAINS": {"sections_title": "h 3"}}"#;
+    assert_eq!(uuids(contains).unwrap(), strs(&["2"]));
+    // No wildcard or regex meaning, so "*" is matched literally
+    assert!(
+        uuids(r#"{"CONTAINS": {"sections_title": "*"}}"#)
+            .unwrap()
+            .is_empty()
+    );
+
+    for op in ["startswith", "endswith", "contains"] {
+        let filter = format!(r#"{{"{}": {{"sections_avg": "8"}}}}"#, op.to_uppercase());
+        let err = uuids(&filter).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<EngineError>(),
+            Some(EngineError::TypeError { op: actual, .. }) if *actual == op
+        ));
+    }
+}
+
+#[test]
+fn test_filter_depth() {
+    let nested = |depth: usize| {
+        let mut query: Query =
+            serde_json::from_str(r#"{"OPTIONS": {"COLUMNS": ["sections_uuid"]}}"#).unwrap();
+        let mut filter = Filter::EMPTY {};
+        for _ in 1..depth {
+            filter = Filter::NOT {
+                not: NotArg::ONE(Box::new(filter)),
+            };
+        }
+        query.r#where = Some(filter);
+        query
+    };
+    let dataset = vec![section("1", "cpsc", "310", 80.0)];
+
+    let err = execute_query(&nested(1000), &dataset).unwrap_err();
+    assert_eq!(err.to_string(), "Filter nesting too deep");
+    assert!(validate_query(&nested(MAX_FILTER_DEPTH + 1)).is_err());
+    assert!(validate_query(&nested(MAX_FILTER_DEPTH)).is_ok());
+}
+
+#[test]
+fn test_compare_columns() {
+    let mut failing = section("1", "cpsc", "310", 40.0);
+    failing.pass = OrderedFloat::from(3f32);
+    failing.fail = OrderedFloat::from(7f32);
+    let mut even = section("2", "cpsc", "310", 50.0);
+    even.fail = even.pass;
+    let dataset = vec![failing, even, section("3", "cpsc", "310", 80.0)];
+
+    let uuids = |op: &str, right: &str| -> anyhow::Result<Vec<Value>> {
+        let json = format!(
+            r#"{{"WHERE": {{"CMP": {{"left": "sections_pass", "right": "{}", "op": "{}"}}}}, "OPTIONS": {{"COLUMNS": ["sections_uuid"], "ORDER": "sections_uuid"}}}}"#,
+            right, op
+        );
+        let query: Query = serde_json::from_str(&json).unwrap();
+        Ok(execute_query(&query, &dataset)?
+            .into_iter()
+            .map(|row| row["sections_uuid"].clone())
+            .collect())
+    };
+    let strs = |ids: &[&str]| {
+        ids.iter()
+            .map(|id| Value::Str(id.to_string()))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(uuids("LT", "sections_fail").unwrap(), strs(&["1"]));
+    assert_eq!(uuids("EQ", "sections_fail").unwrap(), strs(&["2"]));
+    assert_eq!(uuids("GT", "sections_fail").unwrap(), strs(&["3"]));
+
+    let err = uuids("LT", "sections_dept").unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<EngineError>(),
+        Some(EngineError::TypeError { op: "cmp", .. })
+    ));
+    let err = uuids("LT", "sections_nope").unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<EngineError>(),
+        Some(EngineError::FieldNotFound { .. })
+    ));
+}
+
+#[test]
+fn test_unknown_key_message() {
+    let error = |json: &str| parse_query(json).unwrap_err().to_string();
+    assert_eq!(
+        error(r#"{"OPTIONS": {"COLUMNS": ["sections_avg"]}, "HAVING": "blank"}"#),
+        "Unknown key HAVING, allowed keys are WHERE, OPTIONS, TRANSFORMATIONS"
+    );
+    assert_eq!(
+        error(r#"{"OPTIONS": {"COLUMNS": ["sections_avg"], "ODRER": "sections_avg"}}"#),
+        "Unknown key ODRER, allowed keys are COLUMNS, ORDER, F64, LIMIT, OFFSET, CURSOR, FORMAT, KEY_CASE, STRIP_PREFIX, DISTINCT, DISTINCT_TOLERANCE, NULLS_LAST, PERCENT_RANK"
+    );
+    assert_eq!(
+        error(
+            r#"{"OPTIONS": {"COLUMNS": ["sections_dept"]}, "TRANSFORMATION": {"GROUP": ["sections_dept"]}}"#
+        ),
+        "Unknown key TRANSFORMATION, allowed keys are WHERE, OPTIONS, TRANSFORMATIONS"
+    );
+    assert_eq!(
+        error(
+            r#"{"OPTIONS": {"COLUMNS": ["sections_dept"]}, "TRANSFORMATIONS": {"GROUP": ["sections_dept"], "APLY": []}}"#
+        ),
+        "Unknown key APLY, allowed keys are GROUP, APPLY, PRECISION"
+    );
+}
+
+#[test]
+fn test_aggregate_precision() {
+    let dataset = vec![
+        section("1", "cpsc", "310", 80.0),
+        section("2", "cpsc", "310", 81.0),
+        section("3", "cpsc", "310", 82.5),
+    ];
+    // The average is 81.1666...
+    let average = |precision: &str| -> anyhow::Result<Value> {
+        let json = format!(
+            r#"{{"OPTIONS": {{"COLUMNS": ["avg"]}}, "TRANSFORMATIONS": {{"GROUP": ["sections_dept"], "APPLY": [{{"avg": {{"AVG": "sections_avg"}}}}]{}}}}}"#,
+            precision
+        );
+        let query: Query = serde_json::from_str(&json).unwrap();
+        Ok(execute_query(&query, &dataset)?[0]["avg"].clone())
+    };
+    let num = |n: f32| Value::Num(OrderedFloat::from(n));
+
+    assert_eq!(average("").unwrap(), num(81.17));
+    assert_eq!(average(r#", "PRECISION": 0"#).unwrap(), num(81.0));
+    assert_eq!(average(r#", "PRECISION": 4"#).unwrap(), num(81.1667));
+    assert_eq!(
+        average(r#", "PRECISION": 12"#).unwrap_err().to_string(),
+        "PRECISION must be at most 7, got 12"
+    );
+}
+
+#[test]
+fn test_numeric_in() {
+    let dataset = vec![
+        section("1", "cpsc", "310", 80.0),
+        section("2", "math", "100", 70.0),
+        section("3", "phys", "101", 75.0),
+    ];
+    let uuids = |filter: &str| -> anyhow::Result<Vec<Value>> {
+        let json = format!(
+            r#"{{"WHERE": {}, "OPTIONS": {{"COLUMNS": ["sections_uuid"], "ORDER": "sections_uuid"}}}}"#,
+            filter
+        );
+        let query: Query = serde_json::from_str(&json).unwrap();
+        Ok(execute_query(&query, &dataset)?
+            .into_iter()
+            .map(|row| row["sections_uuid"].clone())
+            .collect())
+    };
+    let strs = |ids: &[&str]| {
+        ids.iter()
+            .map(|id| Value::Str(id.to_string()))
+            .collect::<Vec<_>>()
+    };
+
+    // 80.00005 is within EPSILON of 80, 80.0002 is not
+    let near = r#"{"INNUM": {"sections_avg": [70, 80.00005]}}"#;
+    assert_eq!(uuids(near).unwrap(), strs(&["1", "2"]));
+    let outside = r#"{"INNUM": {"sections_avg": [80.0002]}}"#;
+    assert!(uuids(outside).unwrap().is_empty());
+
+    let err = uuids(r#"{"INNUM": {"sections_dept": [1]}}"#).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<EngineError>(),
+        Some(EngineError::TypeError { op: "innum", .. })
+    ));
+}
+
+#[test]
+fn test_all_columns() {
+    let run = |json: &str, dataset: &[Box<dyn Dataset>]| {
+        execute_query(&parse_query(json).unwrap(), dataset)
+    };
+    let keys =
+        |row: &std::collections::BTreeMap<String, Value>| row.keys().cloned().collect::<Vec<_>>();
+
+    let sections: Vec<Box<dyn Dataset>> = vec![Box::new(section("1", "cpsc", "310", 80.0))];
+    let result = run(
+        r#"{"OPTIONS": {"COLUMNS": ["*"], "ORDER": "sections_avg"}}"#,
+        &sections,
+    )
+    .unwrap();
+    let mut expected: Vec<String> = sections[0]
+        .columns()
+        .iter()
+        .map(|c| c.to_string())
+        .collect();
+    expected.sort();
+    assert_eq!(keys(&result[0]), expected);
+
+    let rooms: Vec<Box<dyn Dataset>> = vec![Box::new(room("DMP_110", 49.26, -123.25))];
+    let result = run(r#"{"OPTIONS": {"COLUMNS": ["*"]}}"#, &rooms).unwrap();
+    assert_eq!(result[0].len(), rooms[0].columns().len());
+    assert_eq!(result[0]["rooms_name"], Value::Str("DMP_110".to_string()));
+
+    // With TRANSFORMATIONS only the group and apply keys exist
+    let grouped = r#"{"OPTIONS": {"COLUMNS": ["*"]}, "TRANSFORMATIONS": {"GROUP": ["sections_dept"], "APPLY": [{"best": {"MAX": "sections_avg"}}]}}"#;
+    let result = run(grouped, &sections).unwrap();
+    assert_eq!(keys(&result[0]), vec!["best", "sections_dept"]);
+
+    let mixed = r#"{"OPTIONS": {"COLUMNS": ["*", "sections_avg"]}}"#;
+    assert_eq!(
+        run(mixed, &sections).unwrap_err().to_string(),
+        "* cannot be combined with other COLUMNS"
+    );
+}
+
+#[test]
+fn test_not_forms() {
+    let dataset = vec![
+        section("1", "cpsc", "310", 80.0),
+        section("2", "cpsc", "110", 70.0),
+        section("3", "math", "310", 75.0),
+    ];
+    let uuids = |filter: &str| -> anyhow::Result<Vec<Value>> {
+        let json = format!(
+            r#"{{"WHERE": {}, "OPTIONS": {{"COLUMNS": ["sections_uuid"], "ORDER": "sections_uuid"}}}}"#,
+            filter
+        );
+        Ok(execute_query(&parse_query(&json)?, &dataset)?
+            .into_iter()
+            .map(|row| row["sections_uuid"].clone())
+            .collect())
+    };
+    let strs = |ids: &[&str]| {
+        ids.iter()
+            .map(|id| Value::Str(id.to_string()))
+            .collect::<Vec<_>>()
+    };
+
+    let single = r#"{"NOT": {"IS": {"sections_dept": "cpsc"}}}"#;
+    assert_eq!(uuids(single).unwrap(), strs(&["3"]));
+    // Only rows matching both are excluded
+    let many = r#"{"NOT": [{"IS": {"sections_dept": "cpsc"}}, {"IS": {"sections_id": "310"}}]}"#;
+    assert_eq!(uuids(many).unwrap(), strs(&["2", "3"]));
+    assert_eq!(
+        uuids(r#"{"NOT": []}"#).unwrap_err().to_string(),
+        "NOT requires at least one filter"
+    );
+}
+
+#[test]
+fn test_count_nonempty() {
+    let furnished = |name: &str, furniture: &str| Room {
+        furniture: furniture.to_string(),
+        ..room(name, 49.26, -123.25)
+    };
+    let dataset = vec![
+        furnished("DMP", "Tables"),
+        furnished("DMP", ""),
+        furnished("DMP", "Chairs"),
+        furnished("ANGU", ""),
+    ];
+    let query = parse_query(
+        r#"{"OPTIONS": {"COLUMNS": ["rooms_shortname", "rooms", "furnished"], "ORDER": "rooms_shortname"},
+            "TRANSFORMATIONS": {"GROUP": ["rooms_shortname"], "APPLY": [
+                {"rooms": {"COUNT": "rooms_furniture"}},
+                {"furnished": {"COUNTNONEMPTY": "rooms_furniture"}}]}}"#,
+    )
+    .unwrap();
+    let result = execute_query(&query, &dataset).unwrap();
+    let counts: Vec<_> = result
+        .iter()
+        .map(|row| (row["rooms"].clone(), row["furnished"].clone()))
+        .collect();
+    let num = |n: f32| Value::Num(OrderedFloat::from(n));
+    assert_eq!(counts, vec![(num(1.0), num(0.0)), (num(3.0), num(2.0))]);
+}
+
+#[test]
+fn test_table_format() {
+    let dataset = vec![
+        section("1", "cpsc", "310", 80.0),
+        section("2", "math", "100", 70.0),
+    ];
+    let query = parse_query(
+        r#"{"OPTIONS": {"COLUMNS": ["sections_uuid", "sections_avg", "sections_dept"], "ORDER": "sections_uuid", "FORMAT": "table"}}"#,
+    )
+    .unwrap();
+    let objects = execute_query(&query, &dataset).unwrap();
+    let (columns, rows) = to_table(&query.options, objects.clone());
+
+    // Header keeps the COLUMNS order rather than the sorted object keys
+    assert_eq!(
+        columns,
+        vec!["sections_uuid", "sections_avg", "sections_dept"]
+    );
+    assert_eq!(rows.len(), objects.len());
+    for (row, object) in rows.iter().zip(objects.iter()) {
+        let expected: Vec<_> = columns
+            .iter()
+            .map(|column| object[column].clone())
+            .collect();
+        assert_eq!(*row, expected);
+    }
+    assert_eq!(rows[1][2], Value::Str("math".to_string()));
+
+    // A repeated column has nothing left to fill its second table cell
+    let repeated = parse_query(
+        r#"{"OPTIONS": {"COLUMNS": ["sections_uuid", "sections_uuid"], "FORMAT": "table"}}"#,
+    )
+    .unwrap();
+    let error = execute_query(&repeated, &dataset).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Duplicate column 'sections_uuid' in COLUMNS"
+    );
+}
+
+#[test]
+fn test_unbounded_query() {
+    let unbounded = parse_query(r#"{"OPTIONS": {"COLUMNS": ["sections_avg"]}}"#).unwrap();
+    assert_eq!(
+        check_bounded(&unbounded).unwrap_err().to_string(),
+        "Unbounded query requires a LIMIT"
+    );
+    let empty_where =
+        parse_query(r#"{"WHERE": {}, "OPTIONS": {"COLUMNS": ["sections_avg"]}}"#).unwrap();
+    assert!(check_bounded(&empty_where).is_err());
+
+    let limited =
+        parse_query(r#"{"WHERE": {}, "OPTIONS": {"COLUMNS": ["sections_avg"], "LIMIT": 10}}"#)
+            .unwrap();
+    assert!(check_bounded(&limited).is_ok());
+    let filtered = parse_query(
+        r#"{"WHERE": {"GT": {"sections_avg": 90}}, "OPTIONS": {"COLUMNS": ["sections_avg"]}}"#,
+    )
+    .unwrap();
+    assert!(check_bounded(&filtered).is_ok());
+}
+
+#[test]
+fn test_count_where() {
+    let dataset = vec![
+        section("1", "cpsc", "310", 95.0),
+        section("2", "cpsc", "110", 85.0),
+        section("3", "cpsc", "210", 91.0),
+        section("4", "math", "100", 92.0),
+    ];
+    let query = parse_query(
+        r#"{"OPTIONS": {"COLUMNS": ["sections_dept", "honours"], "ORDER": "sections_dept"},
+            "TRANSFORMATIONS": {"GROUP": ["sections_dept"], "APPLY": [
+                {"honours": {"COUNTWHERE": {"filter": {"GT": {"sections_avg": 90}}}}}]}}"#,
+    )
+    .unwrap();
+    validate_query(&query).unwrap();
+    let result = execute_query(&query, &dataset).unwrap();
+    let counts: Vec<_> = result.iter().map(|row| row["honours"].clone()).collect();
+    let num = |n: f32| Value::Num(OrderedFloat::from(n));
+    assert_eq!(counts, vec![num(2.0), num(1.0)]);
+
+    let missing = parse_query(
+        r#"{"OPTIONS": {"COLUMNS": ["sections_dept", "n"]},
+            "TRANSFORMATIONS": {"GROUP": ["sections_dept"], "APPLY": [
+                {"n": {"COUNTWHERE": {"filter": {"GT": {"sections_nope": 90}}}}}]}}"#,
+    )
+    .unwrap();
+    assert!(execute_query(&missing, &dataset).is_err());
+}
+
+#[test]
+fn test_columns() {
+    assert_eq!(
+        section("1", "cpsc", "310", 80.0).columns(),
+        vec![
+            "sections_uuid",
+            "sections_id",
+            "sections_title",
+            "sections_instructor",
+            "sections_dept",
+            "sections_year",
+            "sections_avg",
+            "sections_pass",
+            "sections_fail",
+            "sections_audit",
+        ]
+    );
+    let room = room("DMP_110", 49.26, -123.25);
+    let columns = room.columns();
+    assert_eq!(columns.len(), 11);
+    assert!(columns.iter().all(|column| room.get(column).is_ok()));
+    assert!(columns.contains(&"rooms_type".to_string()));
+    assert_eq!(room.prefix(), "rooms_");
+}
+
+#[test]
+fn test_furniture_has() {
+    let furnished = |name: &str, furniture: &str| Room {
+        furniture: furniture.to_string(),
+        ..room(name, 49.26, -123.25)
+    };
+    let dataset = vec![
+        furnished("DMP_110", "Classroom-Movable Tables & Chairs"),
+        furnished("DMP_201", "Classroom-Fixed Tables/Movable Chairs"),
+        furnished("ANGU_098", "Classroom-Moveable Tablets"),
+        furnished("ANGU_037", "Classroom-Fixed Tablets"),
+    ];
+    let names = |keywords: &str| -> Vec<Value> {
+        let query = parse_query(&format!(
+            r#"{{"WHERE": {{"FURNITUREHAS": {{"rooms_furniture": {}}}}}, "OPTIONS": {{"COLUMNS": ["rooms_name"], "ORDER": "rooms_name"}}}}"#,
+            keywords
+        ))
+        .unwrap();
+        execute_query(&query, &dataset)
+            .unwrap()
+            .into_iter()
+            .map(|row| row["rooms_name"].clone())
+            .collect()
+    };
+    let str = |s: &str| Value::Str(s.to_string());
+
+    assert_eq!(names(r#"["movable tables"]"#), vec![str("DMP_110")]);
+    assert_eq!(names(r#"["CHAIRS"]"#), vec![str("DMP_110"), str("DMP_201")]);
+    // Any keyword may match, and words only match whole
+    assert_eq!(
+        names(r#"["tablets", "Movable Chairs"]"#),
+        vec![str("ANGU_037"), str("ANGU_098"), str("DMP_201")]
+    );
+    assert!(names(r#"["table"]"#).is_empty());
+}
+
+#[test]
+fn test_average_filters() {
+    let dataset: Vec<_> = [60.0, 70.0, 80.0, 90.0, 100.0]
+        .iter()
+        .enumerate()
+        .map(|(i, avg)| section(&i.to_string(), "cpsc", "310", *avg))
+        .collect();
+    let avgs = |filter: &str| -> Vec<Value> {
+        let query = parse_query(&format!(
+            r#"{{"WHERE": {}, "OPTIONS": {{"COLUMNS": ["sections_avg"], "ORDER": "sections_avg"}}}}"#,
+            filter
+        ))
+        .unwrap();
+        execute_query(&query, &dataset)
+            .unwrap()
+            .into_iter()
+            .map(|row| row["sections_avg"].clone())
+            .collect()
+    };
+    let num = |n: f32| Value::Num(OrderedFloat::from(n));
+
+    // The mean is 80, which is neither above nor below itself
+    assert_eq!(
+        avgs(r#"{"ABOVEAVG": "sections_avg"}"#),
+        vec![num(90.0), num(100.0)]
+    );
+    assert_eq!(
+        avgs(r#"{"BELOWAVG": "sections_avg"}"#),
+        vec![num(60.0), num(70.0)]
+    );
+    // The mean is over the whole dataset, not just the rows other filters keep
+    assert_eq!(
+        avgs(r#"{"AND": [{"GT": {"sections_avg": 85}}, {"ABOVEAVG": "sections_avg"}]}"#),
+        vec![num(90.0), num(100.0)]
+    );
+
+    let query = parse_query(
+        r#"{"WHERE": {"ABOVEAVG": "sections_dept"}, "OPTIONS": {"COLUMNS": ["sections_avg"]}}"#,
+    )
+    .unwrap();
+    assert!(execute_query(&query, &dataset).is_err());
+}
+
+#[test]
+fn test_key_case() {
+    assert_eq!(KeyCase::CAMEL.apply("sections_avg"), "sectionsAvg");
+    assert_eq!(KeyCase::CAMEL.apply("maxSeats"), "maxSeats");
+    assert_eq!(KeyCase::SNAKE.apply("maxSeats"), "max_seats");
+    assert_eq!(KeyCase::SNAKE.apply("rooms_lat"), "rooms_lat");
+
+    // Keys that only differ in case style would merge into one
+    let query = parse_query(
+        r#"{"OPTIONS": {"COLUMNS": ["rooms_shortname", "max_seats", "maxSeats"], "KEY_CASE": "camel"}, "TRANSFORMATIONS": {"GROUP": ["rooms_shortname"], "APPLY": [{"max_seats": {"MAX": "rooms_seats"}}, {"maxSeats": {"MAX": "rooms_seats"}}]}}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        validate_query(&query).unwrap_err().to_string(),
+        "Columns max_seats and maxSeats would both be returned as maxSeats"
+    );
+}
+
+#[test]
+fn test_output_key() {
+    let query = parse_query(
+        r#"{"OPTIONS": {"COLUMNS": ["sections_avg"], "STRIP_PREFIX": true, "KEY_CASE": "camel"}}"#,
+    )
+    .unwrap();
+    assert_eq!(query.options.output_key("sections_", "sections_avg"), "avg");
+    assert_eq!(query.options.output_key("sections_", "max_avg"), "maxAvg");
+    assert_eq!(query.options.output_key("", "sections_avg"), "sectionsAvg");
+
+    // Stripping the prefix can't merge a group key into an apply key
+    let collides = parse_query(
+        r#"{"OPTIONS": {"COLUMNS": ["sections_dept", "dept"], "STRIP_PREFIX": true}, "TRANSFORMATIONS": {"GROUP": ["sections_dept"], "APPLY": [{"dept": {"COUNT": "sections_uuid"}}]}}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        validate_query(&collides).unwrap_err().to_string(),
+        "Columns sections_dept and dept would both be returned as dept"
+    );
+    let all = parse_query(
+        r#"{"OPTIONS": {"COLUMNS": ["*"], "STRIP_PREFIX": true}, "TRANSFORMATIONS": {"GROUP": ["sections_dept"], "APPLY": [{"dept": {"COUNT": "sections_uuid"}}]}}"#,
+    )
+    .unwrap();
+    assert!(validate_query(&all).is_err());
+}
+
+#[test]
+fn test_cursor_pagination() {
+    let avgs = [80.0, 82.0, 80.0, 81.0, 80.0, 82.0, 83.0];
+    let dataset = Indexed::new(
+        avgs.iter()
+            .enumerate()
+            .map(|(i, avg)| section(&i.to_string(), "cpsc", "110", *avg))
+            .collect::<Vec<_>>(),
+    );
+    let run = |order: &str, cursor: Option<&str>| {
+        let cursor = cursor.map_or(String::new(), |cursor| {
+            format!(r#", "CURSOR": "{}""#, cursor)
+        });
+        let query = parse_query(&format!(
+            r#"{{"OPTIONS": {{"COLUMNS": ["sections_uuid", "sections_avg"], "ORDER": {}, "LIMIT": 2{}}}}}"#,
+            order, cursor
+        ))
+        .unwrap();
+        execute_paged_query(&query, &dataset).unwrap()
+    };
+    // Pages through with successive cursors, which must end up matching one unpaged pass
+    let page_through = |order: &str| {
+        let mut uuids = vec![];
+        let mut cursor = None;
+        loop {
+            let (rows, page) = run(order, cursor.as_deref());
+            uuids.extend(rows.into_iter().map(|row| row["sections_uuid"].clone()));
+            match page.unwrap().next_cursor {
+                Some(next) => cursor = Some(next),
+                None => return uuids,
+            }
+        }
+    };
+    let unpaged = |order: &str| -> Vec<Value> {
+        let query = parse_query(&format!(
+            r#"{{"OPTIONS": {{"COLUMNS": ["sections_uuid", "sections_avg"], "ORDER": {}}}}}"#,
+            order
+        ))
+        .unwrap();
+        execute_query(&query, dataset.rows.as_slice())
+            .unwrap()
+            .into_iter()
+            .map(|row| row["sections_uuid"].clone())
+            .collect()
+    };
+
+    // Ties on 80 and 82 straddle page boundaries
+    for order in [
+        r#""sections_avg""#,
+        r#"{"dir": "DOWN", "keys": ["sections_avg"]}"#,
+    ] {
+        let paged = page_through(order);
+        assert_eq!(paged, unpaged(order));
+        assert_eq!(paged.len(), avgs.len());
+    }
+
+    let query = parse_query(
+        r#"{"OPTIONS": {"COLUMNS": ["sections_avg"], "ORDER": "sections_avg", "CURSOR": "nope!"}}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        execute_paged_query(&query, &dataset)
+            .unwrap_err()
+            .to_string(),
+        "Invalid cursor nope!"
+    );
+    let query =
+        parse_query(r#"{"OPTIONS": {"COLUMNS": ["sections_avg"], "CURSOR": "e30"}}"#).unwrap();
+    assert_eq!(
+        validate_query(&query).unwrap_err().to_string(),
+        "CURSOR requires ORDER"
+    );
+}
+
+#[test]
+fn test_numeric_comparison_message() {
+    let message = |filter: &str| {
+        parse_query(&format!(
+            r#"{{"WHERE": {}, "OPTIONS": {{"COLUMNS": ["courses_avg"]}}}}"#,
+            filter
+        ))
+        .unwrap_err()
+        .to_string()
+    };
+    assert_eq!(
+        message(r#"{"GT": {"courses_avg": "adhe"}}"#),
+        "GT requires a numeric value for 'courses_avg', got string"
+    );
+    assert_eq!(
+        message(
+            r#"{"AND": [{"IS": {"courses_dept": "cpsc"}}, {"NOT": {"EQ": {"courses_avg": true}}}]}"#
+        ),
+        "EQ requires a numeric value for 'courses_avg', got boolean"
+    );
+}
+
+#[test]
+fn test_distinct_tolerance() {
+    let dataset = vec![
+        section("1", "cpsc", "310", 90.0),
+        section("2", "cpsc", "310", 90.00001),
+        section("3", "cpsc", "310", 90.004),
+        section("4", "math", "310", 90.0),
+        section("5", "cpsc", "310", 90.0),
+    ];
+    let count = |options: &str| {
+        let query = parse_query(&format!(
+            r#"{{"OPTIONS": {{"COLUMNS": ["sections_dept", "sections_avg"]{}}}}}"#,
+            options
+        ))
+        .unwrap();
+        validate_query(&query).unwrap();
+        execute_query(&query, &dataset).unwrap().len()
+    };
+    assert_eq!(count(""), 5);
+    assert_eq!(count(r#", "DISTINCT": true"#), 4);
+    // Strings still compare exactly, so math stays apart from cpsc
+    assert_eq!(
+        count(r#", "DISTINCT": true, "DISTINCT_TOLERANCE": 0.01"#),
+        2
+    );
+
+    let query =
+        parse_query(r#"{"OPTIONS": {"COLUMNS": ["sections_avg"], "DISTINCT_TOLERANCE": 0.01}}"#)
+            .unwrap();
+    assert_eq!(
+        validate_query(&query).unwrap_err().to_string(),
+        "DISTINCT_TOLERANCE requires DISTINCT"
+    );
+}
+
+#[test]
+fn test_regex_flags() {
+    let dataset = vec![
+        section("1", "CPSC", "310", 90.0),
+        Section {
+            title: "intro\nsoftware".to_string(),
+            ..section("2", "cpsc", "110", 80.0)
+        },
+    ];
+    let uuids = |column: &str, pattern: &str, flags: &str| -> anyhow::Result<Vec<Value>> {
+        let query = parse_query(&format!(
+            r#"{{"WHERE": {{"IS": {{"{}": "{}"}}, "mode": "regex"{}}}, "OPTIONS": {{"COLUMNS": ["sections_uuid"]}}}}"#,
+            column, pattern, flags
+        ))
+        .unwrap();
+        Ok(execute_query(&query, &dataset)?
+            .into_iter()
+            .map(|row| row["sections_uuid"].clone())
+            .collect())
+    };
+    let ids =
+        |ids: &[&str]| -> Vec<Value> { ids.iter().map(|id| Value::Str(id.to_string())).collect() };
+
+    assert_eq!(uuids("sections_dept", "^cpsc$", "").unwrap(), ids(&["2"]));
+    assert_eq!(
+        uuids("sections_dept", "^cpsc$", r#", "flags": "i""#).unwrap(),
+        ids(&["1", "2"])
+    );
+    assert!(
+        uuids("sections_title", "intro.software", "")
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(
+        uuids("sections_title", "intro.software", r#", "flags": "s""#).unwrap(),
+        ids(&["2"])
+    );
+    assert_eq!(
+        uuids("sections_title", "^software$", r#", "flags": "m""#).unwrap(),
+        ids(&["2"])
+    );
+
+    assert_eq!(
+        uuids("sections_dept", "cpsc", r#", "flags": "iq""#)
+            .unwrap_err()
+            .to_string(),
+        "Unknown regex flag q, expected any of imsx"
+    );
+}
+
+#[test]
+fn test_regex_size_limit() {
+    let dataset = vec![section("1", "cpsc", "310", 90.0)];
+    // Nested counted repetitions expand to far more states than the size limit allows
+    let query = parse_query(
+        r#"{"WHERE": {"IS": {"sections_dept": "((a{100}){100}){100}"}, "mode": "regex"},
+            "OPTIONS": {"COLUMNS": ["sections_uuid"]}}"#,
+    )
+    .unwrap();
+
+    let err = execute_query(&query, &dataset).unwrap_err().to_string();
+    assert!(err.contains("exceeds size limit"), "{}", err);
+}
+
+#[test]
+fn test_nulls_last() {
+    let row = |name: &str, avg: Option<f32>| {
+        let mut row = BTreeMap::from([("name".to_string(), Value::Str(name.to_string()))]);
+        if let Some(avg) = avg {
+            row.insert("avg".to_string(), Value::Num(OrderedFloat(avg)));
+        }
+        row
+    };
+    let dataset = vec![
+        row("a", Some(80.0)),
+        row("b", None),
+        row("c", Some(90.0)),
+        row("d", None),
+        row("e", Some(70.0)),
+    ];
+    let names = |options: &str| -> anyhow::Result<Vec<Value>> {
+        let query = parse_query(&format!(
+            r#"{{"WHERE": {{}}, "OPTIONS": {{"COLUMNS": ["*"]{}}}}}"#,
+            options
+        ))?;
+        Ok(execute_query(&query, &dataset)?
+            .into_iter()
+            .map(|row| row["name"].clone())
+            .collect())
+    };
+    let ids =
+        |ids: &[&str]| -> Vec<Value> { ids.iter().map(|id| Value::Str(id.to_string())).collect() };
+
+    assert_eq!(
+        names(r#", "ORDER": "avg""#).unwrap_err().to_string(),
+        "Order column 'avg' not found in results"
+    );
+    assert_eq!(
+        names(r#", "ORDER": "avg", "NULLS_LAST": true"#).unwrap(),
+        ids(&["e", "a", "c", "b", "d"])
+    );
+    // Missing rows stay last when the direction flips
+    assert_eq!(
+        names(r#", "ORDER": {"key": "avg", "dir": "DOWN"}, "NULLS_LAST": true"#).unwrap(),
+        ids(&["c", "a", "e", "b", "d"])
+    );
+    assert_eq!(
+        names(r#", "ORDER": "avg", "NULLS_LAST": false"#).unwrap(),
+        ids(&["b", "d", "e", "a", "c"])
+    );
+
+    // A cursor can end on a row missing the key
+    let dataset = Indexed::new(dataset.clone());
+    let query = parse_query(
+        r#"{"WHERE": {}, "OPTIONS": {"COLUMNS": ["*"], "ORDER": "avg", "NULLS_LAST": true, "LIMIT": 4}}"#,
+    )
+    .unwrap();
+    let (_, page) = execute_paged_query(&query, &dataset).unwrap();
+    let cursor = page.unwrap().next_cursor.unwrap();
+    let query = parse_query(&format!(
+        r#"{{"WHERE": {{}}, "OPTIONS": {{"COLUMNS": ["*"], "ORDER": "avg", "NULLS_LAST": true, "LIMIT": 4, "CURSOR": "{}"}}}}"#,
+        cursor
+    ))
+    .unwrap();
+    let (rows, _) = execute_paged_query(&query, &dataset).unwrap();
+    assert_eq!(
+        rows.into_iter()
+            .map(|row| row["name"].clone())
+            .collect::<Vec<_>>(),
+        ids(&["d"])
+    );
+}
+
+#[test]
+fn test_nulls_last_rooms() {
+    let address = |name: &str, address: Option<&str>| Room {
+        address: address.map(str::to_string),
+        ..room(name, 0.0, 0.0)
+    };
+    let rooms = Indexed::new(vec![
+        address("A", Some("3 Main Mall")),
+        address("B", None),
+        address("C", Some("1 Main Mall")),
+        address("D", None),
+        address("E", Some("2 Main Mall")),
+    ]);
+    let page = |options: &str| {
+        let query = parse_query(&format!(
+            r#"{{"WHERE": {{}}, "OPTIONS": {{"COLUMNS": ["rooms_name", "rooms_address"], "ORDER": {{"key": "rooms_address", "dir": "DOWN"}}, "LIMIT": 2{}}}}}"#,
+            options
+        ))
+        .unwrap();
+        let (rows, page) = execute_paged_query(&query, &rooms).unwrap();
+        let names: Vec<_> = rows
+            .into_iter()
+            .map(|row| row["rooms_name"].clone())
+            .collect();
+        (names, page.unwrap().next_cursor)
+    };
+    let names = |names: &[&str]| -> Vec<Value> {
+        names
+            .iter()
+            .map(|name| Value::Str(name.to_string()))
+            .collect()
+    };
+
+    // Rooms without an address stay last when the direction flips, and pages walk past them
+    let (first, cursor) = page(r#", "NULLS_LAST": true"#);
+    assert_eq!(first, names(&["A", "E"]));
+    let (second, cursor) = page(&format!(
+        r#", "NULLS_LAST": true, "CURSOR": "{}""#,
+        cursor.unwrap()
+    ));
+    assert_eq!(second, names(&["C", "B"]));
+    let (third, _) = page(&format!(
+        r#", "NULLS_LAST": true, "CURSOR": "{}""#,
+        cursor.unwrap()
+    ));
+    assert_eq!(third, names(&["D"]));
+
+    let (first, _) = page(r#", "NULLS_LAST": false"#);
+    assert_eq!(first, names(&["B", "D"]));
+}
+
+#[test]
+fn test_is_int() {
+    let dataset = vec![
+        Section {
+            year: OrderedFloat(2015.0),
+            ..section("1", "cpsc", "310", 90.0)
+        },
+        Section {
+            year: OrderedFloat(2015.5),
+            ..section("2", "cpsc", "310", 90.0)
+        },
+    ];
+    let run = |filter: &str| {
+        let query = parse_query(&format!(
+            r#"{{"WHERE": {}, "OPTIONS": {{"COLUMNS": ["sections_uuid"]}}}}"#,
+            filter
+        ))
+        .unwrap();
+        execute_query(&query, &dataset)
+    };
+
+    assert_eq!(
+        run(r#"{"ISINT": "sections_year"}"#).unwrap()[..],
+        [BTreeMap::from([(
+            "sections_uuid".to_string(),
+            Value::Str("1".into())
+        )])]
+    );
+    assert_eq!(
+        run(r#"{"NOT": {"ISINT": "sections_year"}}"#).unwrap()[0]["sections_uuid"],
+        Value::Str("2".into())
+    );
+    let error = run(r#"{"ISINT": "sections_dept"}"#).unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<EngineError>(),
+        Some(EngineError::TypeError { op: "isint", .. })
+    ));
+}
+
+#[test]
+fn test_denied_columns() {
+    let denied = BTreeSet::from(["sections_instructor".to_string()]);
+    let check = |query: &str| {
+        check_denied(&parse_query(query).unwrap(), &denied).map_err(|e| e.to_string())
+    };
+    let unavailable = Err("Column sections_instructor is not available".to_string());
+
+    assert_eq!(
+        check(
+            r#"{"WHERE": {"GT": {"sections_avg": 90}}, "OPTIONS": {"COLUMNS": ["sections_dept", "sections_avg"], "ORDER": "sections_avg"}}"#
+        ),
+        Ok(())
+    );
+    assert_eq!(
+        check(r#"{"WHERE": {}, "OPTIONS": {"COLUMNS": ["sections_instructor"]}}"#),
+        unavailable
+    );
+    assert_eq!(
+        check(
+            r#"{"WHERE": {"NOT": {"OR": [{"IS": {"sections_dept": "cpsc"}}, {"IS": {"sections_instructor": "*smith*"}}]}}, "OPTIONS": {"COLUMNS": ["sections_dept"]}}"#
+        ),
+        unavailable
+    );
+    assert_eq!(
+        check(
+            r#"{"WHERE": {}, "OPTIONS": {"COLUMNS": ["sections_dept", "count"]},
+            "TRANSFORMATIONS": {"GROUP": ["sections_dept"], "APPLY": [{"count": {"COUNT": "sections_instructor"}}]}}"#
+        ),
+        unavailable
+    );
+    // These read columns they don't name
+    assert_eq!(
+        check(r#"{"WHERE": {"SEARCH": "smith"}, "OPTIONS": {"COLUMNS": ["sections_dept"]}}"#),
+        unavailable
+    );
+    assert_eq!(
+        check(r#"{"WHERE": {}, "OPTIONS": {"COLUMNS": ["*"]}}"#),
+        unavailable
+    );
+    assert_eq!(
+        check_denied(
+            &parse_query(r#"{"WHERE": {}, "OPTIONS": {"COLUMNS": ["*"]}}"#).unwrap(),
+            &BTreeSet::new()
+        )
+        .map_err(|e| e.to_string()),
+        Ok(())
+    );
+}
+
+#[test]
+fn test_eqv() {
+    let dataset = vec![
+        section("1", "cpsc", "310", 90.0),
+        section("2", "math", "100", 80.0),
+    ];
+    let uuids = |filter: &str| -> anyhow::Result<Vec<Value>> {
+        let query = parse_query(&format!(
+            r#"{{"WHERE": {}, "OPTIONS": {{"COLUMNS": ["sections_uuid"]}}}}"#,
+            filter
+        ))?;
+        Ok(execute_query(&query, &dataset)?
+            .into_iter()
+            .map(|row| row["sections_uuid"].clone())
+            .collect())
+    };
+
+    assert_eq!(
+        uuids(r#"{"EQV": {"sections_dept": "cpsc"}}"#).unwrap(),
+        [Value::Str("1".into())]
+    );
+    assert_eq!(
+        uuids(r#"{"EQV": {"sections_avg": 80}}"#).unwrap(),
+        [Value::Str("2".into())]
+    );
+    // Within EPSILON, like EQ
+    assert_eq!(
+        uuids(r#"{"EQV": {"sections_avg": 80.00001}}"#).unwrap(),
+        [Value::Str("2".into())]
+    );
+    assert!(
+        uuids(r#"{"EQV": {"sections_dept": "CPSC"}}"#)
+            .unwrap()
+            .is_empty()
+    );
+
+    let error = uuids(r#"{"EQV": {"sections_avg": "80"}}"#).unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<EngineError>(),
+        Some(EngineError::TypeError { op: "eqv", .. })
+    ));
+}
+
+#[test]
+fn test_order_ties_are_deterministic() {
+    let mut dataset = vec![
+        section("3", "math", "100", 80.0),
+        section("1", "cpsc", "310", 90.0),
+        section("2", "biol", "200", 80.0),
+        section("4", "cpsc", "110", 80.0),
+    ];
+    let query = parse_query(
+        r#"{"WHERE": {}, "OPTIONS": {"COLUMNS": ["sections_uuid", "sections_avg"], "ORDER": "sections_avg"}}"#,
+    )
+    .unwrap();
+    let uuids = |dataset: &Vec<Section>| -> Vec<Value> {
+        execute_query(&query, dataset)
+            .unwrap()
+            .into_iter()
+            .map(|row| row["sections_uuid"].clone())
+            .collect()
+    };
+
+    let first = uuids(&dataset);
+    dataset.reverse();
+    assert_eq!(uuids(&dataset), first);
+    dataset.swap(0, 2);
+    assert_eq!(uuids(&dataset), first);
+    // Tied rows fall back to comparing the rest of the row
+    assert_eq!(
+        first,
+        ["2", "3", "4", "1"]
+            .map(|uuid| Value::Str(uuid.into()))
+            .to_vec()
+    );
+}
+
+#[test]
+fn test_range() {
+    let dataset = vec![
+        section("1", "cpsc", "310", 70.0),
+        section("2", "cpsc", "310", 80.0),
+        section("3", "math", "100", 90.0),
+    ];
+    let uuids = |range: &str| -> anyhow::Result<Vec<Value>> {
+        let query = parse_query(&format!(
+            r#"{{"WHERE": {{"RANGE": {{"sections_avg": {}}}}}, "OPTIONS": {{"COLUMNS": ["sections_uuid"], "ORDER": "sections_uuid"}}}}"#,
+            range
+        ))?;
+        Ok(execute_query(&query, &dataset)?
+            .into_iter()
+            .map(|row| row["sections_uuid"].clone())
+            .collect())
+    };
+    let ids =
+        |ids: &[&str]| -> Vec<Value> { ids.iter().map(|id| Value::Str(id.to_string())).collect() };
+
+    // Both bounds are inclusive
+    assert_eq!(uuids(r#"{"min": 80}"#).unwrap(), ids(&["2", "3"]));
+    assert_eq!(uuids(r#"{"max": 80}"#).unwrap(), ids(&["1", "2"]));
+    assert_eq!(uuids(r#"{"min": 75, "max": 85}"#).unwrap(), ids(&["2"]));
+    assert!(uuids(r#"{"min": 95}"#).unwrap().is_empty());
+
+    let error = uuids("{}").unwrap_err();
+    assert!(error.to_string().contains("RANGE requires a min or a max"));
+    assert!(uuids(r#"{"min": 80, "step": 1}"#).is_err());
+    let error = uuids(r#"{"min": 90, "max": 80}"#).unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("RANGE min 90 is greater than its max 80")
+    );
+
+    let query = parse_query(
+        r#"{"WHERE": {"RANGE": {"sections_dept": {"min": 80}}}, "OPTIONS": {"COLUMNS": ["sections_uuid"]}}"#,
+    )
+    .unwrap();
+    let error = execute_query(&query, &dataset).unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<EngineError>(),
+        Some(EngineError::TypeError { op: "range", .. })
+    ));
+}
+
+#[test]
+fn test_query_builder() {
+    let dataset = vec![
+        section("1", "cpsc", "310", 70.0),
+        section("2", "cpsc", "310", 90.0),
+        section("3", "math", "100", 85.0),
+    ];
+    let built = QueryBuilder::new()
+        .filter(Filter::GT {
+            gt: KVPair::new("sections_avg", OrderedFloat(80.0)),
+        })
+        .column("sections_uuid")
+        .column("sections_avg")
+        .order("sections_avg")
+        .build();
+    let parsed = parse_query(
+        r#"{"WHERE": {"GT": {"sections_avg": 80}}, "OPTIONS": {"COLUMNS": ["sections_uuid", "sections_avg"], "ORDER": "sections_avg"}}"#,
+    )
+    .unwrap();
+    assert_eq!(format!("{:?}", built), format!("{:?}", parsed));
+
+    let result = execute_query(&built, &dataset).unwrap();
+    assert_eq!(result, execute_query(&parsed, &dataset).unwrap());
+    let uuids: Vec<_> = result
+        .iter()
+        .map(|row| row["sections_uuid"].clone())
+        .collect();
+    assert_eq!(uuids, [Value::Str("3".into()), Value::Str("2".into())]);
+
+    let paged = QueryBuilder::new()
+        .column("sections_uuid")
+        .order_by(Order::KEYED {
+            key: "sections_uuid".into(),
+            dir: "DOWN".into(),
+        })
+        .limit(1)
+        .offset(1)
+        .build();
+    let result = execute_query(&paged, &dataset).unwrap();
+    assert_eq!(result[0]["sections_uuid"], Value::Str("2".into()));
+
+    let grouped = QueryBuilder::new()
+        .column("sections_dept")
+        .column("count")
+        .order("sections_dept")
+        .transformations(
+            serde_json::from_str(
+                r#"{"GROUP": ["sections_dept"], "APPLY": [{"count": {"COUNT": "sections_uuid"}}]}"#,
+            )
+            .unwrap(),
+        )
+        .build();
+    let result = execute_query(&grouped, &dataset).unwrap();
+    assert_eq!(result[0]["count"], Value::Num(OrderedFloat(2.0)));
+
+    // Built queries are validated when executed, like parsed ones
+    let error = execute_query(&QueryBuilder::new().build(), &dataset).unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("COLUMNS must be a non-empty array")
+    );
+}
+
+#[test]
+fn test_null_fields() {
+    let address = |name: &str, address: Option<&str>| Room {
+        address: address.map(str::to_string),
+        ..room(name, 0.0, 0.0)
+    };
+    let rooms = vec![
+        address("NONE", None),
+        address("EMPTY", Some("")),
+        address("MAIN", Some("1 Main Mall")),
+    ];
+    let names = |filter: &str| -> Vec<Value> {
+        let query = parse_query(&format!(
+            r#"{{"WHERE": {}, "OPTIONS": {{"COLUMNS": ["rooms_name"], "ORDER": "rooms_name"}}}}"#,
+            filter
+        ))
+        .unwrap();
+        execute_query(&query, &rooms)
+            .unwrap()
+            .into_iter()
+            .map(|row| row["rooms_name"].clone())
+            .collect()
+    };
+    let ids =
+        |ids: &[&str]| -> Vec<Value> { ids.iter().map(|id| Value::Str(id.to_string())).collect() };
+
+    // Null matches no filter, not even one for the empty string
+    assert_eq!(names(r#"{"IS": {"rooms_address": ""}}"#), ids(&["EMPTY"]));
+    assert_eq!(
+        names(r#"{"IS": {"rooms_address": "*"}}"#),
+        ids(&["EMPTY", "MAIN"])
+    );
+    assert_eq!(names(r#"{"EQV": {"rooms_address": null}}"#), ids(&[]));
+    assert_eq!(
+        names(r#"{"NOT": {"IS": {"rooms_address": "*"}}}"#),
+        ids(&["NONE"])
+    );
+    assert_eq!(
+        names(r#"{"HAS": "rooms_address"}"#),
+        ids(&["EMPTY", "MAIN"])
+    );
+    assert!(
+        parse_query(r#"{"WHERE": {"HAS": "rooms_nope"}, "OPTIONS": {"COLUMNS": ["rooms_name"]}}"#)
+            .and_then(|query| execute_query(&query, &rooms))
+            .is_err()
+    );
+
+    let query = parse_query(
+        r#"{"WHERE": {}, "OPTIONS": {"COLUMNS": ["rooms_name", "rooms_address"], "ORDER": "rooms_address", "NULLS_LAST": false, "LIMIT": 1}}"#,
+    )
+    .unwrap();
+    let indexed = Indexed::new(rooms.clone());
+    let (result, page) = execute_paged_query(&query, &indexed).unwrap();
+    // Null sorts first here, serializes as null, and a cursor after it resumes at the next row
+    assert_eq!(result[0]["rooms_address"], Value::Null);
+    assert_eq!(
+        serde_json::to_value(&result[0]).unwrap()["rooms_address"],
+        serde_json::Value::Null
+    );
+    let cursor = page.unwrap().next_cursor.unwrap();
+    let query = parse_query(&format!(
+        r#"{{"WHERE": {{}}, "OPTIONS": {{"COLUMNS": ["rooms_name", "rooms_address"], "ORDER": "rooms_address", "NULLS_LAST": false, "LIMIT": 1, "CURSOR": "{}"}}}}"#,
+        cursor
+    ))
+    .unwrap();
+    let (result, _) = execute_paged_query(&query, &indexed).unwrap();
+    assert_eq!(result[0]["rooms_name"], Value::Str("EMPTY".into()));
+
+    // A null ORDER value counts as missing, so it needs NULLS_LAST and goes last with true
+    let order = |nulls_last: &str| {
+        parse_query(&format!(
+            r#"{{"WHERE": {{}}, "OPTIONS": {{"COLUMNS": ["rooms_name", "rooms_address"], "ORDER": "rooms_address"{}}}}}"#,
+            nulls_last
+        ))
+        .and_then(|query| execute_query(&query, &rooms))
+        .map(|rows| {
+            rows.into_iter()
+                .map(|row| row["rooms_name"].clone())
+                .collect::<Vec<_>>()
+        })
+    };
+    assert_eq!(
+        order("").unwrap_err().to_string(),
+        "Order column 'rooms_address' is null in some rows, which requires NULLS_LAST"
+    );
+    assert_eq!(
+        order(r#", "NULLS_LAST": true"#).unwrap(),
+        ids(&["EMPTY", "MAIN", "NONE"])
+    );
+}
+
+#[test]
+fn test_null_applies() {
+    let address = |name: &str, address: Option<&str>| Room {
+        address: address.map(str::to_string),
+        ..room(name, 0.0, 0.0)
+    };
+    let rooms = vec![
+        address("NONE", None),
+        address("MAIN", Some("1 Main Mall")),
+        address("WEST", Some("2 West Mall")),
+    ];
+    let query = parse_query(
+        r#"{"WHERE": {}, "OPTIONS": {"COLUMNS": ["all", "first", "count"]}, "TRANSFORMATIONS": {"GROUP": ["rooms_number"], "APPLY": [{"all": {"CONCAT": "rooms_address"}}, {"first": {"FIRST": "rooms_address"}}, {"count": {"COUNT": "rooms_address"}}]}}"#,
+    )
+    .unwrap();
+    let result = execute_query(&query, &rooms).unwrap();
+    assert_eq!(
+        result[0],
+        BTreeMap::from([
+            (
+                "all".to_string(),
+                Value::Str("1 Main Mall, 2 West Mall".into())
+            ),
+            ("first".to_string(), Value::Str("1 Main Mall".into())),
+            ("count".to_string(), Value::Num(OrderedFloat(2.0))),
+        ])
+    );
+
+    // Numeric aggregates skip nulls too, and a group of only nulls gets null
+    let row = |group: &str, x: Value| {
+        BTreeMap::from([
+            ("rows_g".to_string(), Value::Str(group.into())),
+            ("rows_x".to_string(), x),
+        ])
+    };
+    let rows = vec![
+        row("a", Value::Num(OrderedFloat(1.0))),
+        row("a", Value::Null),
+        row("a", Value::Num(OrderedFloat(3.0))),
+        row("b", Value::Null),
+    ];
+    let query = parse_query(
+        r#"{"WHERE": {}, "OPTIONS": {"COLUMNS": ["rows_g", "avg", "sum", "max", "p50", "count", "last"], "ORDER": "rows_g"}, "TRANSFORMATIONS": {"GROUP": ["rows_g"], "APPLY": [{"avg": {"AVG": "rows_x"}}, {"sum": {"SUM": "rows_x"}}, {"max": {"MAX": "rows_x"}}, {"p50": {"PERCENTILE": {"column": "rows_x", "p": 50}}}, {"count": {"COUNT": "rows_x"}}, {"last": {"LAST": "rows_x"}}]}}"#,
+    )
+    .unwrap();
+    let result = execute_query(&query, &rows).unwrap();
+    let values = |row: &BTreeMap<String, Value>| -> Vec<Value> {
+        ["avg", "sum", "max", "p50", "count", "last"]
+            .iter()
+            .map(|key| row[*key].clone())
+            .collect()
+    };
+    let num = |n: f32| Value::Num(OrderedFloat(n));
+    assert_eq!(
+        values(&result[0]),
+        [num(2.0), num(4.0), num(3.0), num(2.0), num(2.0), num(3.0)]
+    );
+    assert_eq!(
+        values(&result[1]),
+        [
+            Value::Null,
+            Value::Null,
+            Value::Null,
+            Value::Null,
+            num(0.0),
+            Value::Null
+        ]
+    );
+}
+
+#[test]
+fn test_percent_rank() {
+    let dataset = vec![
+        section("1", "cpsc", "110", 60.0),
+        section("2", "cpsc", "210", 70.0),
+        section("3", "cpsc", "310", 80.0),
+        section("4", "cpsc", "410", 90.0),
+        section("5", "cpsc", "510", 100.0),
+        section("6", "math", "100", 50.0),
+    ];
+    let ranks = |rank: &str| -> anyhow::Result<Vec<(Value, Value)>> {
+        let query = parse_query(&format!(
+            r#"{{"WHERE": {{}}, "OPTIONS": {{"COLUMNS": ["sections_uuid", "avgRank"], "ORDER": "sections_uuid", "PERCENT_RANK": {}}}}}"#,
+            rank
+        ))?;
+        Ok(execute_query(&query, &dataset)?
+            .into_iter()
+            .map(|row| (row["sections_uuid"].clone(), row["avgRank"].clone()))
+            .collect())
+    };
+    let expected = |ranks: &[(&str, f32)]| -> Vec<(Value, Value)> {
+        ranks
+            .iter()
+            .map(|(uuid, rank)| {
+                (
+                    Value::Str(uuid.to_string()),
+                    Value::Num(OrderedFloat(*rank)),
+                )
+            })
+            .collect()
+    };
+
+    // The minimum, median and maximum of cpsc get 0, 50 and 100, and math's only row 0
+    assert_eq!(
+        ranks(r#"{"name": "avgRank", "column": "sections_avg", "partition": "sections_dept"}"#)
+            .unwrap(),
+        expected(&[
+            ("1", 0.0),
+            ("2", 25.0),
+            ("3", 50.0),
+            ("4", 75.0),
+            ("5", 100.0),
+            ("6", 0.0)
+        ])
+    );
+    // Without a partition every row is ranked together
+    assert_eq!(
+        ranks(r#"{"name": "avgRank", "column": "sections_avg"}"#).unwrap()[5],
+        expected(&[("6", 0.0)])[0]
+    );
+
+    let error = ranks(r#"{"name": "avgRank", "column": "sections_dept"}"#).unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<EngineError>(),
+        Some(EngineError::TypeError {
+            op: "percent_rank",
+            ..
+        })
+    ));
+    let error = ranks(r#"{"name": "sections_uuid", "column": "sections_avg"}"#).unwrap_err();
+    assert!(error.to_string().contains("is already a column"));
+}
+
+#[test]
+fn test_arg_extremes() {
+    let dataset = vec![
+        section("1", "cpsc", "110", 70.0),
+        section("2", "cpsc", "310", 95.0),
+        section("3", "cpsc", "210", 60.0),
+        section("4", "cpsc", "410", 95.0),
+        section("5", "math", "100", 80.0),
+    ];
+    let run = |apply: &str| -> anyhow::Result<Vec<BTreeMap<String, Value>>> {
+        let query = parse_query(&format!(
+            r#"{{"WHERE": {{}}, "OPTIONS": {{"COLUMNS": ["sections_dept", "best", "worst"]}},
+            "TRANSFORMATIONS": {{"GROUP": ["sections_dept"], "APPLY": {}}}}}"#,
+            apply
+        ))?;
+        execute_query(&query, &dataset)
+    };
+    let id = |id: &str| Value::Str(id.to_string());
+
+    let result = run(
+        r#"[{"best": {"ARGMAX": {"column": "sections_avg", "id": "sections_id"}}},
+            {"worst": {"ARGMIN": {"column": "sections_avg", "id": "sections_id"}}}]"#,
+    )
+    .unwrap();
+    // Of the two cpsc sections at 95, the first in the dataset wins
+    assert_eq!(
+        (&result[0]["best"], &result[0]["worst"]),
+        (&id("310"), &id("210"))
+    );
+    assert_eq!(
+        (&result[1]["best"], &result[1]["worst"]),
+        (&id("100"), &id("100"))
+    );
+
+    let error = run(
+        r#"[{"best": {"ARGMAX": {"column": "sections_title", "id": "sections_id"}}},
+            {"worst": {"ARGMIN": {"column": "sections_avg", "id": "sections_id"}}}]"#,
+    )
+    .unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<EngineError>(),
+        Some(EngineError::TypeError { op: "argmax", .. })
+    ));
+    let error = run(r#"[{"best": {"ARGMAX": "sections_avg"}},
+            {"worst": {"ARGMIN": {"column": "sections_avg", "id": "sections_id"}}}]"#)
+    .unwrap_err();
+    assert!(error.to_string().contains("ARGMAX and ARGMIN require"));
+}
@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::Instant;
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+// Past this many clients the full buckets are dropped, as a new bucket starts full anyway
+const MAX_BUCKETS: usize = 10_000;
+
+/// Per-IP token buckets refilling at `rate` requests per second
+pub struct RateLimiter {
+    rate: f64,
+    // A client may burst up to one second's worth of requests
+    capacity: f64,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(rate: f64) -> Self {
+        RateLimiter {
+            rate,
+            capacity: rate.max(1.0),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token from the client's bucket, or returns false if it is empty
+    pub fn check(&self, ip: IpAddr, now: Instant) -> bool {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_BUCKETS && !buckets.contains_key(&ip) {
+            buckets.retain(|_, bucket| {
+                let elapsed = now.saturating_duration_since(bucket.refilled).as_secs_f64();
+                bucket.tokens + elapsed * self.rate < self.capacity
+            });
+        }
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: self.capacity,
+            refilled: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.refilled).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.capacity);
+        bucket.refilled = now;
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+}
+
+#[cfg(test)]
+#[path = "rate_limit_test.rs"]
+mod rate_limit_test;
@@ -0,0 +1,42 @@
+use crate::rate_limit::{MAX_BUCKETS, RateLimiter};
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_token_bucket() {
+    let limiter = RateLimiter::new(2.0);
+    let (a, b) = (
+        IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+        IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+    );
+    let start = Instant::now();
+
+    assert!(limiter.check(a, start));
+    assert!(limiter.check(a, start));
+    assert!(!limiter.check(a, start));
+    // Each client has its own bucket
+    assert!(limiter.check(b, start));
+
+    // Half a second refills one token at 2 per second
+    let later = start + Duration::from_millis(500);
+    assert!(limiter.check(a, later));
+    assert!(!limiter.check(a, later));
+}
+
+#[test]
+fn test_idle_buckets_dropped() {
+    let limiter = RateLimiter::new(1.0);
+    let start = Instant::now();
+    let client = |i: usize| IpAddr::V4(Ipv4Addr::from(i as u32));
+    for i in 0..MAX_BUCKETS {
+        assert!(limiter.check(client(i), start));
+    }
+    assert_eq!(limiter.buckets.lock().unwrap().len(), MAX_BUCKETS);
+
+    // A second later every bucket has refilled except the one just emptied
+    let later = start + Duration::from_secs(1);
+    assert!(limiter.check(client(0), later));
+    assert!(limiter.check(client(MAX_BUCKETS), later));
+    assert_eq!(limiter.buckets.lock().unwrap().len(), 2);
+    assert!(!limiter.check(client(0), later));
+}
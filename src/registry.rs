@@ -0,0 +1,142 @@
+use crate::config::DatasetSpec;
+use crate::dataset::CoursesLoader;
+use crate::index::Indexed;
+use crate::rooms_dataset::RoomsLoader;
+use crate::types::{Dataset, DatasetLoader, Row};
+use anyhow::{Context, anyhow};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::info;
+
+pub struct RegisteredDataset {
+    pub kind: String,
+    pub rows: Indexed<Row>,
+}
+
+impl RegisteredDataset {
+    /// The field prefix of the dataset's rows, empty if it has none
+    pub fn prefix(&self) -> &'static str {
+        self.rows.rows.first().map_or("", |row| row.prefix())
+    }
+
+    /// The column names of the dataset's rows, none if it is empty
+    pub fn columns(&self) -> Vec<String> {
+        self.rows
+            .rows
+            .first()
+            .map(Dataset::columns)
+            .unwrap_or_default()
+    }
+}
+
+/// Loaded datasets by id
+#[derive(Default)]
+pub struct Registry {
+    // Shared so a query can keep its dataset while a reload swaps the registry
+    datasets: HashMap<String, Arc<RegisteredDataset>>,
+    // Registration order, so listings are stable
+    ids: Vec<String>,
+}
+
+/// Dataset loaders by kind, starting with the built-in courses and rooms
+pub struct Loaders {
+    loaders: HashMap<String, Box<dyn DatasetLoader>>,
+}
+
+impl Default for Loaders {
+    fn default() -> Self {
+        let mut loaders = Loaders {
+            loaders: HashMap::new(),
+        };
+        loaders.add(CoursesLoader);
+        loaders.add(RoomsLoader);
+        loaders
+    }
+}
+
+impl Loaders {
+    /// Replaces any loader already added for the same kind
+    pub fn add(&mut self, loader: impl DatasetLoader + 'static) {
+        self.loaders
+            .insert(loader.kind().to_string(), Box::new(loader));
+    }
+
+    pub fn load(&self, kind: &str, path: &str) -> anyhow::Result<Vec<Row>> {
+        self.loaders
+            .get(kind)
+            .ok_or_else(|| anyhow!("Unknown dataset kind {}", kind))?
+            .load(path)
+    }
+}
+
+impl Registry {
+    pub fn load(specs: &[DatasetSpec], loaders: &Loaders) -> anyhow::Result<Self> {
+        let mut registry = Registry::default();
+        for spec in specs {
+            let rows = loaders
+                .load(&spec.kind, &spec.path)
+                .with_context(|| format!("Failed to load dataset {}", spec.id))?;
+            registry.register(&spec.id, &spec.kind, rows);
+        }
+        Ok(registry)
+    }
+
+    /// Loads every dataset at once, each on a blocking thread, and registers them in spec order
+    pub async fn load_concurrently(
+        specs: &[DatasetSpec],
+        loaders: Arc<Loaders>,
+    ) -> anyhow::Result<Self> {
+        let tasks: Vec<_> = specs
+            .iter()
+            .cloned()
+            .map(|spec| {
+                let loaders = loaders.clone();
+                tokio::task::spawn_blocking(move || -> anyhow::Result<_> {
+                    let started = Instant::now();
+                    let rows = loaders
+                        .load(&spec.kind, &spec.path)
+                        .with_context(|| format!("Failed to load dataset {}", spec.id))?;
+                    info!(
+                        dataset = %spec.id,
+                        rows = rows.len(),
+                        elapsed = ?started.elapsed(),
+                        "Loaded dataset"
+                    );
+                    Ok((spec, rows))
+                })
+            })
+            .collect();
+
+        let mut registry = Registry::default();
+        for task in tasks {
+            let (spec, rows) = task.await??;
+            registry.register(&spec.id, &spec.kind, rows);
+        }
+        Ok(registry)
+    }
+
+    /// Replaces any dataset already registered under the id
+    pub fn register(&mut self, id: &str, kind: &str, rows: Vec<Row>) {
+        let dataset = Arc::new(RegisteredDataset {
+            kind: kind.to_string(),
+            rows: Indexed::new(rows),
+        });
+        if self.datasets.insert(id.to_string(), dataset).is_none() {
+            self.ids.push(id.to_string());
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<RegisteredDataset>> {
+        self.datasets.get(id).cloned()
+    }
+
+    /// Registered ids in the order they were registered
+    pub fn ids(&self) -> Vec<&str> {
+        self.ids.iter().map(String::as_str).collect()
+    }
+}
+
+#[cfg(test)]
+#[path = "registry_test.rs"]
+mod registry_test;
@@ -0,0 +1,111 @@
+use crate::config::DatasetSpec;
+use crate::dataset::Section;
+use crate::query::{Query, execute_indexed_query};
+use crate::registry::{Loaders, Registry};
+use crate::testing::section;
+use crate::types::{DatasetLoader, Row, Value};
+use ordered_float::OrderedFloat;
+use std::sync::Arc;
+
+fn row(uuid: &str, year: f32) -> Row {
+    Box::new(Section {
+        year: OrderedFloat::from(year),
+        ..section(uuid, "cpsc", "310", 80.0)
+    })
+}
+
+#[test]
+fn test_datasets_by_id() {
+    let mut registry = Registry::default();
+    registry.register("y2014", "courses", vec![row("1", 2014.0), row("2", 2014.0)]);
+    registry.register("y2015", "courses", vec![row("3", 2015.0)]);
+    assert_eq!(registry.ids(), vec!["y2014", "y2015"]);
+
+    let query: Query = serde_json::from_str(
+        r#"{"WHERE": {"IS": {"sections_dept": "cpsc"}}, "OPTIONS": {"COLUMNS": ["sections_uuid"]}}"#,
+    )
+    .unwrap();
+    let uuids = |id: &str| -> Vec<Value> {
+        let dataset = registry.get(id).unwrap();
+        assert_eq!(dataset.kind, "courses");
+        execute_indexed_query(&query, &dataset.rows)
+            .unwrap()
+            .into_iter()
+            .map(|row| row["sections_uuid"].clone())
+            .collect()
+    };
+    assert_eq!(
+        uuids("y2014"),
+        vec![Value::Str("1".into()), Value::Str("2".into())]
+    );
+    assert_eq!(uuids("y2015"), vec![Value::Str("3".into())]);
+    assert!(registry.get("y2016").is_none());
+}
+
+#[test]
+fn test_unknown_kind() {
+    let specs = [DatasetSpec::new("books", "books", "books.zip")];
+    let error = Registry::load(&specs, &Loaders::default()).err().unwrap();
+    assert_eq!(
+        format!("{:#}", error),
+        "Failed to load dataset books: Unknown dataset kind books"
+    );
+}
+
+// Stands in for a kind added outside this crate, one row per comma in the path
+struct DummyLoader;
+
+impl DatasetLoader for DummyLoader {
+    fn kind(&self) -> &str {
+        "dummy"
+    }
+
+    fn load(&self, path: &str) -> anyhow::Result<Vec<Row>> {
+        Ok(path.split(',').map(|uuid| row(uuid, 2020.0)).collect())
+    }
+}
+
+#[test]
+fn test_custom_loader() {
+    let mut loaders = Loaders::default();
+    loaders.add(DummyLoader);
+    let registry = Registry::load(&[DatasetSpec::new("fake", "dummy", "a,b,c")], &loaders).unwrap();
+
+    let dataset = registry.get("fake").unwrap();
+    assert_eq!(dataset.kind, "dummy");
+    assert_eq!(dataset.rows.rows.len(), 3);
+    assert_eq!(
+        dataset.rows.rows[2].get("sections_uuid"),
+        Ok(Value::Str("c".into()))
+    );
+}
+
+#[tokio::test]
+async fn test_load_concurrently() {
+    let mut loaders = Loaders::default();
+    loaders.add(DummyLoader);
+    let specs = [
+        DatasetSpec::new("first", "dummy", "a,b,c"),
+        DatasetSpec::new("second", "dummy", "d"),
+    ];
+    let registry = Registry::load_concurrently(&specs, Arc::new(loaders))
+        .await
+        .unwrap();
+
+    assert_eq!(registry.ids(), vec!["first", "second"]);
+    assert_eq!(registry.get("first").unwrap().rows.rows.len(), 3);
+    assert_eq!(registry.get("second").unwrap().rows.rows.len(), 1);
+
+    let specs = [
+        DatasetSpec::new("first", "dummy", "a"),
+        DatasetSpec::new("books", "books", "books.zip"),
+    ];
+    let error = Registry::load_concurrently(&specs, Arc::new(Loaders::default()))
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(
+        format!("{:#}", error),
+        "Failed to load dataset first: Unknown dataset kind dummy"
+    );
+}
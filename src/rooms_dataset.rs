@@ -1,10 +1,13 @@
+use crate::geocode::{Geocoder, default_geocoder};
 use crate::types::Dataset;
-use crate::types::Value;
+use crate::types::{DatasetLoader, Row, Value, boxed};
 use anyhow::{Context, anyhow};
 use macros::Dataset;
 use ordered_float::OrderedFloat;
 use scraper::{Html, Selector};
+use std::collections::BTreeMap;
 use std::io::Read;
+use tracing::{info, warn};
 
 #[derive(Debug, Clone, Dataset)]
 #[field_prefix("rooms_")]
@@ -13,15 +16,40 @@ pub struct Room {
     pub shortname: String,
     pub number: String,
     pub name: String,
-    pub address: String,
+    /// None when the building page has no address
+    pub address: Option<String>,
     pub seats: OrderedFloat<f32>,
     pub r#type: String, // Renamed 'type' to 'r#type' as 'type' is a Rust keyword
     pub furniture: String,
     pub href: String,
+    /// 0, 0 when the address couldn't be geocoded
+    pub lat: OrderedFloat<f32>,
+    pub lon: OrderedFloat<f32>,
 }
 
 /// Load rooms from a zip file containing HTML files
 pub fn load_rooms_dataset(path_to_zip_file: &str) -> anyhow::Result<Vec<Room>> {
+    load_rooms_dataset_with(path_to_zip_file, default_geocoder().as_ref())
+}
+
+/// Loads "rooms" datasets from zips of building HTML
+pub struct RoomsLoader;
+
+impl DatasetLoader for RoomsLoader {
+    fn kind(&self) -> &str {
+        "rooms"
+    }
+
+    fn load(&self, path: &str) -> anyhow::Result<Vec<Row>> {
+        Ok(boxed(load_rooms_dataset(path)?))
+    }
+}
+
+/// Load rooms, locating each building's address with the geocoder
+pub fn load_rooms_dataset_with(
+    path_to_zip_file: &str,
+    geocoder: &dyn Geocoder,
+) -> anyhow::Result<Vec<Room>> {
     let file = std::fs::File::open(path_to_zip_file)
         .with_context(|| format!("Failed to open zip file: {}", path_to_zip_file))?;
 
@@ -33,7 +61,13 @@ pub fn load_rooms_dataset(path_to_zip_file: &str) -> anyhow::Result<Vec<Room>> {
     // Parse the index to get building information
     let building_entries = parse_index_for_buildings(&index_content)?;
 
+    // Parsed up front, so a bad selector fails the load instead of a building
+    let address_selector = selector(ADDRESS_SELECTOR)?;
+    let layouts = room_layouts()?;
+    let mut matched_layouts: BTreeMap<&str, usize> = BTreeMap::new();
+
     let mut all_rooms = Vec::new();
+    let mut unparsed_seats = 0;
 
     // Process each building
     for (building_code, building_name, building_link) in building_entries {
@@ -45,19 +79,41 @@ pub fn load_rooms_dataset(path_to_zip_file: &str) -> anyhow::Result<Vec<Room>> {
             if let Some(building_content) = read_file_from_archive(&mut archive, &filename) {
                 // Pass the document for parsing the address
                 let document = Html::parse_document(&building_content);
-                let building_address = parse_building_address(&document);
-
-                let rooms = parse_building_rooms(
+                let building_address = parse_building_address(&document, &address_selector);
+                // One bad lookup shouldn't lose the building's rooms
+                let (lat, lon) = building_address.as_deref().map_or((0.0, 0.0), |address| {
+                    geocoder.geocode(address).unwrap_or_else(|e| {
+                        warn!(address, error = %e, "Error while geocoding");
+                        (0.0, 0.0)
+                    })
+                });
+
+                if let Some((layout, rooms)) = parse_building_rooms(
                     &document,
+                    &layouts,
                     &building_code,
                     &building_name,
-                    &building_address,
-                );
-                all_rooms.extend(rooms);
+                    building_address.as_deref(),
+                    (lat, lon),
+                    &mut unparsed_seats,
+                ) {
+                    *matched_layouts.entry(layout).or_default() += 1;
+                    all_rooms.extend(rooms);
+                }
             }
         }
     }
 
+    for (layout, buildings) in matched_layouts {
+        info!(buildings, layout, "Buildings matched a room layout");
+    }
+    if unparsed_seats > 0 {
+        warn!(
+            rooms = unparsed_seats,
+            "Rooms have no readable capacity, their seats are 0"
+        );
+    }
+
     Ok(all_rooms)
 }
 
@@ -74,45 +130,52 @@ fn read_index_from_archive(archive: &mut zip::ZipArchive<std::fs::File>) -> anyh
         }
     }
 
+    // Otherwise look for it in a subdirectory
+    for index_name in &index_names {
+        if let Some(content) = read_file_from_archive(archive, index_name) {
+            return Ok(content);
+        }
+    }
+
     anyhow::bail!("Could not find index.html or index.htm in the archive")
 }
 
+// Entries may use either separator depending on the tool that built the archive
+fn entry_file_name(entry: &str) -> &str {
+    entry.rsplit(['/', '\\']).next().unwrap_or(entry)
+}
+
+/// Index of the entry whose final path segment is filename, falling back to a case-insensitive match
+fn find_entry(archive: &zip::ZipArchive<std::fs::File>, filename: &str) -> Option<usize> {
+    let position = |matches: &dyn Fn(&str) -> bool| {
+        (0..archive.len()).find(|i| {
+            archive
+                .name_for_index(*i)
+                .is_some_and(|name| matches(entry_file_name(name)))
+        })
+    };
+    position(&|name| name == filename)
+        .or_else(|| position(&|name| name.eq_ignore_ascii_case(filename)))
+}
+
 fn read_file_from_archive(
     archive: &mut zip::ZipArchive<std::fs::File>,
     filename: &str,
 ) -> Option<String> {
-    // Try to find the file (might be at root or in subdirectories)
-    // First try exact filename
-    if let Ok(mut file) = archive.by_name(filename) {
-        let mut content = String::new();
-        if file.read_to_string(&mut content).is_ok() {
-            return Some(content);
-        }
-    }
-
-    // Try to find by iterating through all files (in case it's in a subdirectory)
-    for i in 0..archive.len() {
-        if let Ok(mut file) = archive.by_index(i) {
-            if file.name().ends_with(filename) {
-                let mut content = String::new();
-                if file.read_to_string(&mut content).is_ok() {
-                    return Some(content);
-                }
-            }
-        }
-    }
-
-    None
+    let index = find_entry(archive, filename)?;
+    let mut file = archive.by_index(index).ok()?;
+    let mut content = String::new();
+    file.read_to_string(&mut content).ok()?;
+    Some(content)
 }
 
 macro_rules! extract {
-    ($elem:ident, $selector:ident) => {
+    ($elem:ident, $selector:expr) => {
         $elem
             .select(&$selector)
             .next()
             .and_then(|el| el.text().next())
             .map(|s| s.trim().to_string())
-            .unwrap_or_default()
     };
 }
 
@@ -129,7 +192,7 @@ fn parse_index_for_buildings(html_content: &str) -> anyhow::Result<Vec<(String,
         .map_err(|e| anyhow!("Failed to parse selector: {:?}", e))?;
 
     for row in document.select(&row_selector) {
-        let building_code = extract!(row, code_selector);
+        let building_code = extract!(row, code_selector).unwrap_or_default();
 
         let name_element = row.select(&name_selector).next();
 
@@ -150,34 +213,120 @@ fn extract_filename_from_link(link: &str) -> String {
     link.rsplit('/').next().unwrap_or("").to_string()
 }
 
-// NEW FUNCTION to parse the building address
-fn parse_building_address(document: &Html) -> String {
-    let address_selector =
-        Selector::parse("#building-info > div.building-field > div.field-content").unwrap(); // This selector targets the first div.building-field under #building-info
+// Targets the first div.building-field under #building-info
+const ADDRESS_SELECTOR: &str = "#building-info > div.building-field > div.field-content";
 
+fn selector(css: &str) -> anyhow::Result<Selector> {
+    Selector::parse(css).map_err(|e| anyhow!("Failed to parse selector {}: {:?}", css, e))
+}
+
+// NEW FUNCTION to parse the building address, None if the page has none
+fn parse_building_address(document: &Html, address_selector: &Selector) -> Option<String> {
     // Find the address element and extract its text
     extract!(document, address_selector)
 }
 
+/// CSS selectors for one layout of a building page's room table
+struct RoomLayout {
+    name: &'static str,
+    row: &'static str,
+    // The room number link, which also gives the href
+    number: &'static str,
+    capacity: &'static str,
+    furniture: &'static str,
+    room_type: &'static str,
+}
+
+// Tried in order until one finds rooms: the current Drupal views table, then plain room classes
+const ROOM_LAYOUTS: [RoomLayout; 2] = [
+    RoomLayout {
+        name: "views-table",
+        row: "table.views-table tbody tr",
+        number: "td.views-field-field-room-number a",
+        capacity: "td.views-field-field-room-capacity",
+        furniture: "td.views-field-field-room-furniture",
+        room_type: "td.views-field-field-room-type",
+    },
+    RoomLayout {
+        name: "room-list",
+        row: "table.room-list tr",
+        number: "td.room-number a",
+        capacity: "td.room-capacity",
+        furniture: "td.room-furniture",
+        room_type: "td.room-type",
+    },
+];
+
+struct RoomSelectors {
+    name: &'static str,
+    row: Selector,
+    number: Selector,
+    capacity: Selector,
+    furniture: Selector,
+    room_type: Selector,
+}
+
+fn room_layouts() -> anyhow::Result<Vec<RoomSelectors>> {
+    ROOM_LAYOUTS
+        .iter()
+        .map(|layout| {
+            Ok(RoomSelectors {
+                name: layout.name,
+                row: selector(layout.row)?,
+                number: selector(layout.number)?,
+                capacity: selector(layout.capacity)?,
+                furniture: selector(layout.furniture)?,
+                room_type: selector(layout.room_type)?,
+            })
+        })
+        .collect()
+}
+
+/// Leading number of a capacity cell such as "50" or "50 seats", None if there isn't one
+fn parse_seats(text: &str) -> Option<f32> {
+    let text = text.trim();
+    let end = text
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(text.len());
+    text[..end].parse().ok()
+}
+
+/// Rooms from the first layout that finds any, with that layout's name, or None if none do
 fn parse_building_rooms(
     document: &Html,
+    layouts: &[RoomSelectors],
+    building_code: &str,
+    building_name: &str,
+    building_address: Option<&str>, // Pass the extracted address
+    (lat, lon): (f32, f32),
+    unparsed_seats: &mut usize,
+) -> Option<(&'static str, Vec<Room>)> {
+    layouts.iter().find_map(|layout| {
+        let rooms = parse_rooms_with(
+            document,
+            layout,
+            building_code,
+            building_name,
+            building_address,
+            (lat, lon),
+            unparsed_seats,
+        );
+        (!rooms.is_empty()).then_some((layout.name, rooms))
+    })
+}
+
+fn parse_rooms_with(
+    document: &Html,
+    selectors: &RoomSelectors,
     building_code: &str,
     building_name: &str,
-    building_address: &str, // Pass the extracted address
+    building_address: Option<&str>,
+    (lat, lon): (f32, f32),
+    unparsed_seats: &mut usize,
 ) -> Vec<Room> {
     let mut rooms = Vec::new();
 
-    // Selectors for room table
-    let room_row_selector = Selector::parse("table.views-table tbody tr").unwrap();
-    let room_number_selector = Selector::parse("td.views-field-field-room-number a").unwrap();
-    let capacity_selector = Selector::parse("td.views-field-field-room-capacity").unwrap();
-    let furniture_selector = Selector::parse("td.views-field-field-room-furniture").unwrap();
-    let room_type_selector = Selector::parse("td.views-field-field-room-type").unwrap();
-
-    // Assuming the href comes from the room number link
-    let href_selector = Selector::parse("td.views-field-field-room-number a").unwrap();
-
-    for row in document.select(&room_row_selector) {
+    for row in document.select(&selectors.row) {
         // Extract fullname (assuming it's the building_name for each room for now)
         let fullname = building_name.to_string();
 
@@ -185,32 +334,35 @@ fn parse_building_rooms(
         let shortname = building_code.to_string();
 
         // Extract room number
-        let number = extract!(row, room_number_selector);
+        let number = extract!(row, selectors.number).unwrap_or_default();
 
         // Construct name as "rooms_shortname"_"rooms_number"
         let name = format!("{}_{}", shortname, number);
 
         // Use the building_address passed into the function
-        let address = building_address.to_string();
+        let address = building_address.map(str::to_string);
 
-        // Extract seats (capacity)
+        // Extract seats (capacity), counting the rooms that fall back to 0
         let seats = row
-            .select(&capacity_selector)
+            .select(&selectors.capacity)
             .next()
             .and_then(|el| el.text().next())
-            .and_then(|s| s.trim().parse::<f32>().ok())
-            .map(OrderedFloat)
-            .unwrap_or(OrderedFloat::from(0.0f32));
+            .and_then(parse_seats)
+            .unwrap_or_else(|| {
+                *unparsed_seats += 1;
+                0.0
+            });
+        let seats = OrderedFloat(seats);
 
         // Extract type
-        let room_type = extract!(row, room_type_selector);
+        let room_type = extract!(row, selectors.room_type).unwrap_or_default();
 
         // Extract furniture
-        let furniture = extract!(row, furniture_selector);
+        let furniture = extract!(row, selectors.furniture).unwrap_or_default();
 
         // Extract href (assuming it's the link from the room number)
         let href = row
-            .select(&href_selector)
+            .select(&selectors.number)
             .next()
             .and_then(|el| el.value().attr("href"))
             .map(|s| s.to_string())
@@ -226,8 +378,14 @@ fn parse_building_rooms(
             r#type: room_type, // Using 'r#type' for the keyword 'type'
             furniture,
             href,
+            lat: OrderedFloat(lat),
+            lon: OrderedFloat(lon),
         });
     }
 
     rooms
 }
+
+#[cfg(test)]
+#[path = "rooms_dataset_test.rs"]
+mod rooms_dataset_test;
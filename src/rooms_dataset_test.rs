@@ -0,0 +1,211 @@
+use crate::geocode::Geocoder;
+use crate::rooms_dataset::{
+    ADDRESS_SELECTOR, load_rooms_dataset_with, parse_building_address, parse_building_rooms,
+    parse_seats, read_file_from_archive, read_index_from_archive, room_layouts,
+};
+use crate::types::{Dataset, Value};
+use ordered_float::OrderedFloat;
+use scraper::Html;
+use std::io::Write;
+
+fn archive(name: &str, entries: &[(&str, &str)]) -> zip::ZipArchive<std::fs::File> {
+    let path = std::env::temp_dir().join(format!("three-ten-rooms-{}.zip", name));
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+    for (entry, contents) in entries {
+        zip.start_file(*entry, zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(contents.as_bytes()).unwrap();
+    }
+    zip.finish().unwrap();
+    zip::ZipArchive::new(std::fs::File::open(&path).unwrap()).unwrap()
+}
+
+#[test]
+fn test_shared_suffix() {
+    let mut archive = archive(
+        "suffix",
+        &[
+            ("campus/buildings/BIOLAB.htm", "biolab"),
+            ("campus/buildings/LAB.htm", "lab"),
+        ],
+    );
+    assert_eq!(
+        read_file_from_archive(&mut archive, "LAB.htm").as_deref(),
+        Some("lab")
+    );
+    assert_eq!(
+        read_file_from_archive(&mut archive, "BIOLAB.htm").as_deref(),
+        Some("biolab")
+    );
+    assert_eq!(read_file_from_archive(&mut archive, "AB.htm"), None);
+}
+
+#[test]
+fn test_separators_and_case() {
+    let mut archive = archive(
+        "separators",
+        &[
+            ("campus\\buildings\\DMP.htm", "dmp"),
+            ("campus/buildings/angu.HTM", "angu"),
+            ("campus/index.html", "index"),
+        ],
+    );
+    assert_eq!(
+        read_file_from_archive(&mut archive, "DMP.htm").as_deref(),
+        Some("dmp")
+    );
+    assert_eq!(
+        read_file_from_archive(&mut archive, "ANGU.htm").as_deref(),
+        Some("angu")
+    );
+    assert_eq!(read_index_from_archive(&mut archive).unwrap(), "index");
+}
+
+struct FixedGeocoder;
+
+impl Geocoder for FixedGeocoder {
+    fn geocode(&self, address: &str) -> anyhow::Result<(f32, f32)> {
+        if address.contains("Agronomy") {
+            anyhow::bail!("no result");
+        }
+        Ok((49.26, -123.25))
+    }
+}
+
+#[test]
+fn test_geocoding() {
+    let rooms = load_rooms_dataset_with("campus.zip", &FixedGeocoder).unwrap();
+    let (failed, located): (Vec<_>, Vec<_>) = rooms.iter().partition(|room| {
+        room.address
+            .as_deref()
+            .is_some_and(|address| address.contains("Agronomy"))
+    });
+    assert!(!failed.is_empty() && !located.is_empty());
+    assert!(
+        failed
+            .iter()
+            .all(|room| room.lat == OrderedFloat(0.0) && room.lon == OrderedFloat(0.0))
+    );
+    assert!(
+        located
+            .iter()
+            .all(|room| room.lat == OrderedFloat(49.26) && room.lon == OrderedFloat(-123.25))
+    );
+}
+
+#[test]
+fn test_parse_seats() {
+    assert_eq!(parse_seats("50"), Some(50.0));
+    assert_eq!(parse_seats(" 50 "), Some(50.0));
+    assert_eq!(parse_seats("50 seats"), Some(50.0));
+    assert_eq!(parse_seats(""), None);
+    assert_eq!(parse_seats("seats"), None);
+}
+
+// An older page layout, with plain room classes instead of the Drupal views table
+const ROOM_LIST_BUILDING: &str = r#"
+<html><body>
+  <div id="building-info"><div class="building-field"><div class="field-content">1 Alt Way</div></div></div>
+  <table class="room-list">
+    <tr>
+      <td class="room-number"><a href="http://example.com/ALT-101">101</a></td>
+      <td class="room-capacity">40</td>
+      <td class="room-furniture">Classroom-Movable Tables &amp; Chairs</td>
+      <td class="room-type">Small Group</td>
+    </tr>
+    <tr>
+      <td class="room-number"><a href="http://example.com/ALT-102">102</a></td>
+      <td class="room-capacity">120 seats</td>
+      <td class="room-furniture">Classroom-Fixed Tablets</td>
+      <td class="room-type">Tiered Large Group</td>
+    </tr>
+  </table>
+</body></html>
+"#;
+
+#[test]
+fn test_fallback_layout() {
+    let layouts = room_layouts().unwrap();
+    let document = Html::parse_document(ROOM_LIST_BUILDING);
+    let mut unparsed_seats = 0;
+    let (layout, rooms) = parse_building_rooms(
+        &document,
+        &layouts,
+        "ALT",
+        "Alt Hall",
+        Some("1 Alt Way"),
+        (0.0, 0.0),
+        &mut unparsed_seats,
+    )
+    .unwrap();
+
+    assert_eq!(layout, "room-list");
+    assert_eq!(unparsed_seats, 0);
+    let summary: Vec<_> = rooms
+        .iter()
+        .map(|room| {
+            (
+                room.name.as_str(),
+                room.seats.0,
+                room.r#type.as_str(),
+                room.href.as_str(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            ("ALT_101", 40.0, "Small Group", "http://example.com/ALT-101"),
+            (
+                "ALT_102",
+                120.0,
+                "Tiered Large Group",
+                "http://example.com/ALT-102"
+            ),
+        ]
+    );
+    assert_eq!(rooms[0].furniture, "Classroom-Movable Tables & Chairs");
+
+    // A page neither layout recognizes has no rooms rather than empty ones
+    let document = Html::parse_document("<html><body><p>Closed</p></body></html>");
+    assert!(
+        parse_building_rooms(
+            &document,
+            &layouts,
+            "ALT",
+            "Alt Hall",
+            Some("1 Alt Way"),
+            (0.0, 0.0),
+            &mut unparsed_seats,
+        )
+        .is_none()
+    );
+}
+
+#[test]
+fn test_missing_address() {
+    let layouts = room_layouts().unwrap();
+    let document = Html::parse_document(ROOM_LIST_BUILDING);
+    let address_selector = scraper::Selector::parse(ADDRESS_SELECTOR).unwrap();
+    assert_eq!(
+        parse_building_address(&document, &address_selector).as_deref(),
+        Some("1 Alt Way")
+    );
+
+    // A page without the address field gives rooms with a null address, not an empty one
+    let without = ROOM_LIST_BUILDING.replace("building-field", "other-field");
+    let document = Html::parse_document(&without);
+    let address = parse_building_address(&document, &address_selector);
+    assert_eq!(address, None);
+    let (_, rooms) = parse_building_rooms(
+        &document,
+        &layouts,
+        "ALT",
+        "Alt Hall",
+        address.as_deref(),
+        (0.0, 0.0),
+        &mut 0,
+    )
+    .unwrap();
+    assert_eq!(rooms[0].get("rooms_address"), Ok(Value::Null));
+}
@@ -1,10 +1,36 @@
-use crate::dataset::load_dataset;
-use crate::query::{Query, execute_query};
+use crate::dataset::{Section, load_dataset};
+use crate::query::{Query, execute_query, parse_query};
+use ordered_float::OrderedFloat;
 use serde::Deserialize;
 use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use crate::types::Value;
 
+/// A section fixture, passing with 10 students in 2015
+pub fn section(uuid: &str, dept: &str, id: &str, avg: f32) -> Section {
+    Section {
+        uuid: uuid.to_string(),
+        id: id.to_string(),
+        title: format!("{} {}", dept, id),
+        instructor: "".to_string(),
+        dept: dept.to_string(),
+        year: OrderedFloat::from(2015f32),
+        avg: OrderedFloat::from(avg),
+        pass: OrderedFloat::from(10f32),
+        fail: OrderedFloat::from(0f32),
+        audit: OrderedFloat::from(0f32),
+    }
+}
+
+/// A query for the uuids of the sections matching filter
+pub fn where_query(filter: &str) -> Query {
+    parse_query(&format!(
+        r#"{{"WHERE": {}, "OPTIONS": {{"COLUMNS": ["sections_uuid"]}}}}"#,
+        filter
+    ))
+    .unwrap()
+}
+
 #[derive(Deserialize)]
 pub struct Test {
     pub title: String,
@@ -1,3 +1,4 @@
+use crate::explain::Plan;
 use ordered_float::OrderedFloat;
 use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::{BTreeMap, HashMap};
@@ -5,21 +6,147 @@ use std::collections::{BTreeMap, HashMap};
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
 #[serde(untagged)]
 pub enum Value {
+    /// A field that has no value, e.g. a room whose page lists no address.
+    /// Filters on it match nothing, HAS counts it as absent, and it sorts before every other value.
+    Null,
     Num(OrderedFloat<f32>),
     Str(String),
+    Bool(bool),
 }
 
 pub trait Dataset {
     fn get(&self, field_name: &str) -> Result<Value, String>;
-    fn get_all(&self) -> &'static [&'static str];
+    fn set(&mut self, field_name: &str, value: Value) -> Result<(), String>;
+    /// Every prefixed column name the row can `get`
+    fn columns(&self) -> Vec<String>;
+    /// The `field_prefix` every column starts with, e.g. "sections_"
+    fn prefix(&self) -> &'static str;
+    /// "number" or "string" for known columns, None otherwise
+    fn column_type(&self, field_name: &str) -> Option<&'static str>;
 }
 
-#[derive(Debug, PartialEq)]
+/// A row of any dataset kind
+pub type Row = Box<dyn Dataset + Send + Sync>;
+
+pub fn boxed<D: Dataset + Send + Sync + 'static>(rows: Vec<D>) -> Vec<Row> {
+    rows.into_iter().map(|row| Box::new(row) as Row).collect()
+}
+
+/// Every column of a row, keyed by its prefixed name
+pub fn full_row<D: Dataset>(item: &D) -> anyhow::Result<BTreeMap<String, Value>> {
+    item.columns()
+        .iter()
+        .map(|column| {
+            Ok((
+                column.to_string(),
+                item.get(column).map_err(|e| anyhow::anyhow!(e))?,
+            ))
+        })
+        .collect()
+}
+
+/// Every row in full, as `GET /export/{id}` returns them
+pub fn export_rows<D: Dataset>(rows: &[D]) -> anyhow::Result<Vec<BTreeMap<String, Value>>> {
+    rows.iter().map(full_row).collect()
+}
+
+/// Rebuilds exported rows by setting every column on a default row, which must all be present
+pub fn import_rows<D: Dataset + Default>(
+    rows: Vec<BTreeMap<String, Value>>,
+) -> anyhow::Result<Vec<D>> {
+    rows.into_iter()
+        .map(|columns| {
+            let mut row = D::default();
+            if let Some(missing) = row
+                .columns()
+                .into_iter()
+                .find(|column| !columns.contains_key(column))
+            {
+                return Err(anyhow::anyhow!("Exported row is missing {}", missing));
+            }
+            for (column, value) in columns {
+                row.set(&column, value).map_err(|e| anyhow::anyhow!(e))?;
+            }
+            Ok(row)
+        })
+        .collect()
+}
+
+/// Loads every row of one dataset kind, so new kinds can be registered without touching main.rs
+pub trait DatasetLoader: Send + Sync {
+    /// The kind named in `--datasets id:kind:path`
+    fn kind(&self) -> &str;
+    fn load(&self, path: &str) -> anyhow::Result<Vec<Row>>;
+}
+
+// Lets rows of different dataset types share one collection
+impl<T: Dataset + ?Sized> Dataset for Box<T> {
+    fn get(&self, field_name: &str) -> Result<Value, String> {
+        (**self).get(field_name)
+    }
+
+    fn set(&mut self, field_name: &str, value: Value) -> Result<(), String> {
+        (**self).set(field_name, value)
+    }
+
+    fn columns(&self) -> Vec<String> {
+        (**self).columns()
+    }
+
+    fn prefix(&self) -> &'static str {
+        (**self).prefix()
+    }
+
+    fn column_type(&self, field_name: &str) -> Option<&'static str> {
+        (**self).column_type(field_name)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DatasetInfo {
+    pub id: String,
+    pub kind: String,
+    pub rows: usize,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ColumnSchema {
+    pub name: String,
+    pub r#type: &'static str,
+}
+
+/// Describes each column of the dataset by probing its first row
+pub fn schema<D: Dataset>(dataset: &[D]) -> Vec<ColumnSchema> {
+    dataset
+        .first()
+        .map(|row| {
+            row.columns()
+                .into_iter()
+                .filter_map(|name| {
+                    row.column_type(&name)
+                        .map(|r#type| ColumnSchema { name, r#type })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct KVPair<T> {
     pub key: String,
     pub value: T,
 }
 
+#[cfg(test)]
+impl<T> KVPair<T> {
+    pub fn new(key: impl Into<String>, value: T) -> Self {
+        KVPair {
+            key: key.into(),
+            value,
+        }
+    }
+}
+
 impl<'de, T> Deserialize<'de> for KVPair<T>
 where
     T: Deserialize<'de>,
@@ -42,13 +169,87 @@ where
     }
 }
 
+// Lets projected and transformed rows be queried like any other dataset
+impl Dataset for BTreeMap<String, Value> {
+    fn get(&self, field_name: &str) -> Result<Value, String> {
+        self.get(field_name)
+            // Like the derived impls, mixed-case names fall back to their lowercase spelling
+            .or_else(|| self.get(&field_name.to_ascii_lowercase()))
+            .cloned()
+            .ok_or_else(|| format!("Field '{}' not found", field_name))
+    }
+
+    fn set(&mut self, field_name: &str, value: Value) -> Result<(), String> {
+        self.insert(field_name.to_string(), value);
+        Ok(())
+    }
+
+    fn columns(&self) -> Vec<String> {
+        self.keys().cloned().collect()
+    }
+
+    // Keys are used as they are
+    fn prefix(&self) -> &'static str {
+        ""
+    }
+
+    fn column_type(&self, field_name: &str) -> Option<&'static str> {
+        match self.get(field_name)? {
+            Value::Num(_) => Some("number"),
+            Value::Str(_) => Some("string"),
+            Value::Bool(_) => Some("boolean"),
+            // A null says nothing about the column's type
+            Value::Null => None,
+        }
+    }
+}
+
+/// Where a LIMIT/OFFSET page sits in the full result
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Page {
+    pub total: usize,
+    pub offset: usize,
+    pub has_more: bool,
+    /// Passed as CURSOR to fetch the next page, when ORDER was given and more rows remain
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
 #[derive(Debug, Serialize)]
 #[serde(untagged)]
 pub enum QueryResult {
     OK {
         result: Vec<BTreeMap<String, Value>>,
+        // Only present for paged queries
+        #[serde(flatten)]
+        page: Option<Page>,
+        /// Server-side time spent answering the query, including cache hits
+        elapsed_ms: f64,
+    },
+    /// OK in `"FORMAT": "table"`
+    TABLE {
+        columns: Vec<String>,
+        rows: Vec<Vec<Value>>,
+        #[serde(flatten)]
+        page: Option<Page>,
+        elapsed_ms: f64,
     },
     ERROR {
         error: String,
+        code: &'static str,
+    },
+    PLAN {
+        plan: Plan,
     },
 }
+
+#[derive(Debug, Serialize)]
+pub struct ValidationResult {
+    pub valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[cfg(test)]
+#[path = "types_test.rs"]
+mod types_test;
@@ -0,0 +1,198 @@
+use crate::query::{Query, execute_query};
+use crate::types::{Dataset, Value};
+use macros::Dataset;
+use ordered_float::OrderedFloat;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Dataset)]
+#[field_prefix("points_")]
+struct Point {
+    label: String,
+    x: OrderedFloat<f32>,
+    count: i32,
+    visible: bool,
+}
+
+fn point() -> Point {
+    Point {
+        label: "origin".to_string(),
+        x: OrderedFloat::from(0f32),
+        count: 0,
+        visible: false,
+    }
+}
+
+#[test]
+fn test_set_round_trip() {
+    let mut point = point();
+    let values = [
+        ("points_label", Value::Str("a".to_string())),
+        ("points_x", Value::Num(OrderedFloat::from(1.5f32))),
+        ("points_count", Value::Num(OrderedFloat::from(3f32))),
+        ("points_visible", Value::Bool(true)),
+    ];
+    for (field, value) in values {
+        point.set(field, value.clone()).unwrap();
+        assert_eq!(point.get(field).unwrap(), value);
+    }
+    assert_eq!(point.count, 3);
+    assert!(point.visible);
+}
+
+#[test]
+fn test_set_type_mismatch() {
+    let mut point = point();
+    assert!(point.set("points_x", Value::Str("a".to_string())).is_err());
+    assert!(
+        point
+            .set("points_label", Value::Num(OrderedFloat::from(1f32)))
+            .is_err()
+    );
+    assert_eq!(point.label, "origin");
+}
+
+#[test]
+fn test_set_unknown_field() {
+    let mut point = point();
+    let error = point.set("label", Value::Str("a".to_string())).unwrap_err();
+    assert_eq!(
+        error,
+        "Field 'label' not found. Fields must start with prefix 'points_'"
+    );
+}
+
+#[test]
+fn test_bool_values() {
+    let mut hidden = point();
+    hidden.label = "hidden".to_string();
+    let mut shown = point();
+    shown.label = "shown".to_string();
+    shown.visible = true;
+    assert_eq!(shown.get("points_visible").unwrap(), Value::Bool(true));
+    assert_eq!(shown.column_type("points_visible"), Some("boolean"));
+    assert!(Value::Bool(false) < Value::Bool(true));
+    assert_eq!(serde_json::to_string(&Value::Bool(true)).unwrap(), "true");
+
+    let points = vec![hidden, shown];
+    let labels = |filter: &str| -> Vec<Value> {
+        let json = format!(
+            r#"{{"WHERE": {}, "OPTIONS": {{"COLUMNS": ["points_label"]}}}}"#,
+            filter
+        );
+        let query: Query = serde_json::from_str(&json).unwrap();
+        execute_query(&query, &points)
+            .unwrap()
+            .into_iter()
+            .map(|row| row["points_label"].clone())
+            .collect()
+    };
+    let shown = vec![Value::Str("shown".to_string())];
+    assert_eq!(labels(r#"{"IS": {"points_visible": "true"}}"#), shown);
+    assert_eq!(labels(r#"{"EQ": {"points_visible": 1}}"#), shown);
+    assert_eq!(labels(r#"{"GT": {"points_visible": 0}}"#), shown);
+}
+
+#[derive(Debug, Dataset)]
+struct Building {
+    code: String,
+    floors: i32,
+}
+
+#[derive(Debug, Dataset)]
+#[field_prefix("rooms_")]
+struct NestedRoom {
+    name: String,
+    #[nested]
+    building: Building,
+}
+
+#[test]
+fn test_nested_path() {
+    let mut room = NestedRoom {
+        name: "DMP_110".to_string(),
+        building: Building {
+            code: "DMP".to_string(),
+            floors: 4,
+        },
+    };
+    assert_eq!(
+        room.get("rooms_name"),
+        Ok(Value::Str("DMP_110".to_string()))
+    );
+    assert_eq!(
+        room.get("rooms_building.code"),
+        Ok(Value::Str("DMP".to_string()))
+    );
+    assert_eq!(room.column_type("rooms_building.floors"), Some("number"));
+    assert!(room.get("rooms_building.missing").is_err());
+
+    room.set(
+        "rooms_building.floors",
+        Value::Num(OrderedFloat::from(5f32)),
+    )
+    .unwrap();
+    assert_eq!(room.building.floors, 5);
+}
+
+#[test]
+fn test_map_row() {
+    let mut row = BTreeMap::from([
+        ("avg".to_string(), Value::Num(OrderedFloat::from(91f32))),
+        ("sections_dept".to_string(), Value::Str("cpsc".to_string())),
+    ]);
+    assert_eq!(row.columns(), vec!["avg", "sections_dept"]);
+    assert_eq!(
+        Dataset::get(&row, "sections_dept").unwrap(),
+        Value::Str("cpsc".to_string())
+    );
+    assert!(Dataset::get(&row, "sections_avg").is_err());
+    assert_eq!(row.column_type("avg"), Some("number"));
+    Dataset::set(&mut row, "honours", Value::Bool(true)).unwrap();
+    assert_eq!(row.column_type("honours"), Some("boolean"));
+
+    // Transformed rows can be queried again
+    let rows = vec![row];
+    let query: Query = serde_json::from_str(
+        r#"{"WHERE": {"GT": {"avg": 90}}, "OPTIONS": {"COLUMNS": ["sections_dept"]}}"#,
+    )
+    .unwrap();
+    let result = execute_query(&query, &rows).unwrap();
+    assert_eq!(result[0]["sections_dept"], Value::Str("cpsc".to_string()));
+}
+
+#[derive(Debug, Dataset)]
+#[field_prefix("rooms_")]
+struct Listing {
+    address: Option<String>,
+    seats: Option<OrderedFloat<f32>>,
+}
+
+#[test]
+fn test_null_values() {
+    let mut listing = Listing {
+        address: None,
+        seats: Some(OrderedFloat::from(40f32)),
+    };
+    assert_eq!(listing.get("rooms_address"), Ok(Value::Null));
+    assert_eq!(listing.column_type("rooms_address"), Some("string"));
+    assert_eq!(
+        listing.get("rooms_seats"),
+        Ok(Value::Num(OrderedFloat::from(40f32)))
+    );
+
+    listing
+        .set("rooms_address", Value::Str("".to_string()))
+        .unwrap();
+    assert_eq!(listing.address.as_deref(), Some(""));
+    listing.set("rooms_seats", Value::Null).unwrap();
+    assert_eq!(listing.seats, None);
+    assert!(listing.set("rooms_address", Value::Bool(true)).is_err());
+    assert_eq!(listing.address.as_deref(), Some(""));
+
+    assert_eq!(serde_json::to_string(&Value::Null).unwrap(), "null");
+    assert_eq!(serde_json::from_str::<Value>("null").unwrap(), Value::Null);
+    // Null sorts before every other value, and isn't an empty string
+    assert!(Value::Null < Value::Num(OrderedFloat::from(f32::MIN)));
+    assert!(Value::Null < Value::Str("".to_string()));
+    assert_ne!(Value::Null, Value::Str("".to_string()));
+}